@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::CommandFactory;
+
+#[allow(dead_code)]
+#[path = "src/cli.rs"]
+mod cli;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/cli.rs");
+    println!("cargo:rerun-if-env-changed=YURECOLLECT_MAN_DIR");
+
+    let page = render_man_page().expect("render man page");
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    write(&out_dir, &page);
+    // Packagers can collect the page from a fixed location instead of the hashed OUT_DIR
+    if let Some(dir) = std::env::var_os("YURECOLLECT_MAN_DIR") {
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir).expect("create YURECOLLECT_MAN_DIR");
+        write(&dir, &page);
+    }
+
+    emit_build_info();
+    compile_protos();
+}
+
+/// Generates the gRPC server and client from `proto/`. Uses protox, a protobuf compiler in
+/// Rust, so building does not need `protoc` installed.
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto");
+    let descriptors = protox::compile(["yurecollect.proto"], ["proto"]).expect("compile proto/yurecollect.proto");
+    tonic_build::configure()
+        .compile_fds(descriptors)
+        .expect("generate gRPC bindings");
+}
+
+/// Exposes the commit, build date and compiler to `--build-info` as compile-time env vars.
+fn emit_build_info() {
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // Rebuild on commits and checkouts so the embedded hash stays current
+    if let Some(head) = git(&["rev-parse", "--git-path", "HEAD"]) {
+        println!("cargo:rerun-if-changed={}", head);
+    }
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"])
+        && let Some(reference) = git(&["rev-parse", "--git-path", &branch])
+    {
+        println!("cargo:rerun-if-changed={}", reference);
+    }
+
+    let commit = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|v| v.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    // SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let epoch_secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+
+    println!("cargo:rustc-env=YURECOLLECT_GIT_COMMIT={}", commit);
+    println!(
+        "cargo:rustc-env=YURECOLLECT_BUILD_DATE={}",
+        utc_date(epoch_secs)
+    );
+    println!(
+        "cargo:rustc-env=YURECOLLECT_RUSTC_VERSION={}",
+        rustc_version
+    );
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let out = Command::new("git").args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(out.stdout).ok()?.trim().to_string())
+}
+
+/// Formats UNIX seconds as a UTC `YYYY-MM-DD` date (Howard Hinnant's civil-from-days).
+fn utc_date(epoch_secs: u64) -> String {
+    let days = (epoch_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn write(dir: &Path, page: &[u8]) {
+    let path = dir.join("yurecollect.1");
+    std::fs::write(&path, page).unwrap_or_else(|e| panic!("write {}: {}", path.display(), e));
+}
+
+/// The clap-generated sections plus EXAMPLES and EXIT STATUS from `cli.rs`.
+fn render_man_page() -> std::io::Result<Vec<u8>> {
+    let cmd = cli::Args::command().disable_help_subcommand(true);
+    let man = clap_mangen::Man::new(cmd.clone());
+    let mut page = Vec::new();
+    man.render_title(&mut page)?;
+    man.render_name_section(&mut page)?;
+    man.render_synopsis_section(&mut page)?;
+    man.render_description_section(&mut page)?;
+    man.render_options_section(&mut page)?;
+    man.render_subcommands_section(&mut page)?;
+
+    page.extend_from_slice(b".SH EXAMPLES\n");
+    for (description, command) in cli::EXAMPLES {
+        page.extend_from_slice(
+            format!(
+                ".TP\n{}\n.B {}\n",
+                roff_escape(description),
+                roff_escape(command)
+            )
+            .as_bytes(),
+        );
+    }
+    page.extend_from_slice(b".SH \"EXIT STATUS\"\n");
+    for (code, meaning) in cli::EXIT_CODES {
+        page.extend_from_slice(format!(".TP\n.B {}\n{}\n", code, roff_escape(meaning)).as_bytes());
+    }
+
+    man.render_version_section(&mut page)?;
+    Ok(page)
+}
+
+/// Escapes backslashes and hyphens so roff prints them literally.
+fn roff_escape(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
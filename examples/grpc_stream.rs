@@ -0,0 +1,76 @@
+//! Lists the newest buffered messages over a running yurecollect's gRPC API, then follows the
+//! live ones.
+//!
+//! ```text
+//! cargo run --example grpc_stream -- http://127.0.0.1:50051
+//! cargo run --example grpc_stream -- http://127.0.0.1:50051 <api token>
+//! ```
+
+use tonic::metadata::MetadataValue;
+use tonic::Request;
+
+pub mod proto {
+    tonic::include_proto!("yurecollect.v1");
+}
+
+use proto::message_event::Event;
+use proto::yure_collect_client::YureCollectClient;
+use proto::{ListRequest, StreamRequest};
+
+fn authorized<T>(message: T, token: Option<&str>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(token) = token {
+        let value: MetadataValue<_> = format!("Bearer {}", token).parse().expect("token is a valid header value");
+        request.metadata_mut().insert("authorization", value);
+    }
+    request
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let server = args.next().unwrap_or_else(|| "http://127.0.0.1:50051".to_string());
+    let token = args.next();
+    let mut client = YureCollectClient::connect(server.clone()).await.unwrap_or_else(|err| {
+        eprintln!("{}: {}", server, err);
+        std::process::exit(1);
+    });
+
+    let listed = client
+        .list_messages(authorized(ListRequest { limit: Some(5) }, token.as_deref()))
+        .await
+        .unwrap_or_else(|status| {
+            eprintln!("ListMessages: {}", status);
+            std::process::exit(1);
+        })
+        .into_inner();
+    println!("{} buffered, newest {}:", listed.total, listed.messages.len());
+    for message in listed.messages {
+        println!("#{} {} {}", message.seq, message.received_at_ms, message.payload);
+    }
+
+    let mut events = client
+        .stream_messages(authorized(StreamRequest::default(), token.as_deref()))
+        .await
+        .unwrap_or_else(|status| {
+            eprintln!("StreamMessages: {}", status);
+            std::process::exit(1);
+        })
+        .into_inner();
+    loop {
+        match events.message().await {
+            Ok(Some(event)) => match event.event {
+                Some(Event::Message(message)) => {
+                    println!("#{} {} {}", message.seq, message.received_at_ms, message.payload)
+                }
+                Some(Event::Lagged(lagged)) => eprintln!("skipped {} messages", lagged.missed),
+                None => {}
+            },
+            Ok(None) => break,
+            Err(status) => {
+                eprintln!("{}", status);
+                std::process::exit(1);
+            }
+        }
+    }
+}
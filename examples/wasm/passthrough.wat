@@ -0,0 +1,21 @@
+;; The smallest --wasm-plugin: forwards every message unchanged. See docs/wasm-plugin.md.
+;;
+;;   yurecollect --wasm-plugin examples/wasm/passthrough.wat wss://example.com/your/ws
+(module
+  (memory (export "memory") 1)
+
+  ;; A single buffer at offset 0, reused for every message: the host copies the output
+  ;; out before it calls `alloc` again, so there is nothing to free and no `dealloc`
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $pages i32)
+    (local.set $pages
+      (i32.shr_u (i32.add (local.get $len) (i32.const 65535)) (i32.const 16)))
+    (if (i32.gt_u (local.get $pages) (memory.size))
+      (then (drop (memory.grow (i32.sub (local.get $pages) (memory.size))))))
+    (i32.const 0))
+
+  ;; Answers with the input buffer itself: (ptr << 32) | len
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len)))))
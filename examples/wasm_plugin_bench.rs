@@ -0,0 +1,60 @@
+//! Measures what a `--wasm-plugin` costs per message, with the same host code the server runs.
+//!
+//! ```text
+//! cargo run --release --example wasm_plugin_bench
+//! cargo run --release --example wasm_plugin_bench -- path/to/plugin.wasm 100000
+//! ```
+//!
+//! The default plugin, `examples/wasm/passthrough.wat`, does no work of its own, so its time
+//! is the overhead of the call: copying the message in and out and entering the instance.
+
+#[path = "../src/wasm_plugin.rs"]
+mod wasm_plugin;
+
+use std::path::PathBuf;
+use std::time::Instant;
+
+use wasm_plugin::WasmPlugin;
+
+// A typical accelerometer message, as in the README
+const MESSAGE: &str = r#"{"t":1768117058365,"userAgent":"yuredroid 1.4.2 on Xiaomi 2201117TG","x":-0.005534179508686066,"y":0.005334913730621338,"z":0.00000762939453125,"yureId":"EReERYeurRE"}"#;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/wasm/passthrough.wat")));
+    let iterations: u32 = args.next().map_or(100_000, |n| n.parse().expect("iterations must be a number"));
+
+    let mut plugin = WasmPlugin::load(&path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    // Warm up, and show what the plugin makes of the message
+    println!("{:?}", plugin.apply(MESSAGE));
+    for _ in 0..1_000 {
+        let _ = plugin.apply(MESSAGE);
+    }
+
+    // The String copy every message costs anyway, to subtract from the plugin's time
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(MESSAGE.to_string());
+    }
+    let baseline = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let _ = std::hint::black_box(plugin.apply(MESSAGE));
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{} messages of {} bytes: {:.0} ns per message ({:.0} ns without the baseline copy)",
+        iterations,
+        MESSAGE.len(),
+        elapsed.as_nanos() as f64 / iterations as f64,
+        elapsed.saturating_sub(baseline).as_nanos() as f64 / iterations as f64,
+    );
+}
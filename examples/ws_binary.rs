@@ -0,0 +1,47 @@
+//! Follows a running yurecollect's `/ws` with a binary `format` and prints every decoded message.
+//!
+//! ```text
+//! cargo run --example ws_binary -- ws://127.0.0.1:3000/ws msgpack
+//! cargo run --example ws_binary -- ws://127.0.0.1:3000/ws cbor
+//! ```
+//!
+//! Each binary frame is one message: the same JSON value the `json` format sends as text,
+//! including notices such as `{"type":"lagged","missed":n}`.
+
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let url = args.next().unwrap_or_else(|| "ws://127.0.0.1:3000/ws".to_string());
+    let format = args.next().unwrap_or_else(|| "msgpack".to_string());
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let url = format!("{}{}format={}", url, separator, format);
+
+    let (mut socket, _) = connect_async(url.as_str()).await.unwrap_or_else(|err| {
+        eprintln!("{}: {}", url, err);
+        std::process::exit(1);
+    });
+    while let Some(frame) = socket.next().await {
+        let bytes = match frame {
+            Ok(Message::Binary(bytes)) => bytes,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        let decoded: Result<Value, String> = match format.as_str() {
+            "cbor" => ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string()),
+            _ => rmp_serde::from_slice(&bytes).map_err(|e| e.to_string()),
+        };
+        match decoded {
+            Ok(value) => println!("{}", value),
+            Err(err) => eprintln!("undecodable {} frame ({} bytes): {}", format, bytes.len(), err),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Logs one line per request under the `yurecollect::access` target and tags the response
+/// (and everything logged while handling it) with a request ID and the request's trace ID.
+///
+/// An incoming `X-Request-Id` is reused so IDs can be correlated with a fronting proxy.
+pub async fn log_request(
+    mut req: Request,
+    next: Next,
+    client_ip: Option<IpAddr>,
+    excluded: bool,
+) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id);
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        req.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let method = req.method().clone();
+    let version = req.version();
+    let path = req.uri().path().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|m| m.as_str().to_string());
+    // Set by the trace_context middleware around this one
+    let trace_id = crate::trace_context::current_trace_id();
+    let span = tracing::info_span!("request", id = %request_id, trace_id = trace_id.as_deref());
+
+    let start = Instant::now();
+    let mut response = next.run(req).instrument(span.clone()).await;
+    let latency = start.elapsed();
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    if excluded {
+        return response;
+    }
+
+    let bytes = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| "-".to_string());
+    let client = client_ip.map_or_else(|| "-".to_string(), |ip| ip.to_string());
+    span.in_scope(|| {
+        tracing::info!(
+            target: "yurecollect::access",
+            method = %method,
+            version = ?version,
+            path = %path,
+            route = route.as_deref().unwrap_or("-"),
+            status = response.status().as_u16(),
+            latency_ms = latency.as_secs_f64() * 1000.0,
+            bytes = %bytes,
+            client = %client,
+        );
+    });
+    response
+}
+
+fn generate_request_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Summary statistics for one time bucket.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Bucket {
+    /// Bucket start, `floor(received_at / bucket_ms) * bucket_ms` in UNIX milliseconds
+    pub start_ms: u64,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+}
+
+/// Running statistics for a bucket (Welford's algorithm for the variance).
+struct Accumulator {
+    count: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Accumulator {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+}
+
+/// Groups timestamped values into fixed-width time buckets.
+pub struct Aggregator {
+    bucket_ms: u64,
+    buckets: BTreeMap<u64, Accumulator>,
+}
+
+impl Aggregator {
+    /// `bucket_ms` must be non-zero.
+    pub fn new(bucket_ms: u64) -> Self {
+        assert!(bucket_ms > 0, "bucket_ms must be non-zero");
+        Self {
+            bucket_ms,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, timestamp_ms: u64, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let key = timestamp_ms / self.bucket_ms;
+        self.buckets.entry(key).or_insert_with(Accumulator::new).add(value);
+    }
+
+    /// Returns the buckets sorted by start time.
+    pub fn finish(self) -> Vec<Bucket> {
+        let bucket_ms = self.bucket_ms;
+        self.buckets
+            .into_iter()
+            .map(|(key, acc)| Bucket {
+                start_ms: key * bucket_ms,
+                count: acc.count,
+                min: acc.min,
+                max: acc.max,
+                mean: acc.mean,
+                stddev: (acc.m2 / acc.count as f64).sqrt(),
+            })
+            .collect()
+    }
+}
+
+/// Reads a numeric field from a sample object.
+///
+/// The acceleration axes accept the same aliases as the web UI (`ax` also matches `x`,
+/// `accelerationX` and `acceleration.x`). Numeric strings are accepted as well.
+pub fn extract_field(item: &Value, field: &str) -> Option<f64> {
+    let axis = match field {
+        "x" | "ax" => Some("x"),
+        "y" | "ay" => Some("y"),
+        "z" | "az" => Some("z"),
+        _ => None,
+    };
+    let value = match axis {
+        Some(axis) => {
+            let short = format!("a{}", axis);
+            let long = format!("acceleration{}", axis.to_uppercase());
+            item.get(axis)
+                .or_else(|| item.get(&short))
+                .or_else(|| item.get(&long))
+                .or_else(|| item.get("acceleration").and_then(|a| a.get(axis)))
+        }
+        None => item.get(field),
+    }?;
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
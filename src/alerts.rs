@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_graphql::SimpleObject;
+use opentelemetry::Context;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use crate::correlation::ConfirmedEvent;
+use crate::shake::ShakeEvent;
+use crate::trace_context;
+
+/// Alerts waiting per chat service before further ones are dropped, and counted, so a dead or
+/// throttled webhook never holds up ingest.
+const QUEUE_SIZE: usize = 64;
+/// Attempts per alert, with the wait doubling from `FIRST_RETRY` in between unless the
+/// service asks for longer.
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+/// Longest wait a rate-limit response is obeyed for; beyond it the alert is stale anyway.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Events and confirmed events remembered as alerted, oldest forgotten first.
+const MAX_ALERTED: usize = 1024;
+/// Shown around an event in the UI link.
+const LINK_MARGIN_MS: u64 = 30_000;
+
+/// A chat service taking incoming webhooks.
+#[derive(Clone, Copy)]
+pub enum ChatService {
+    Discord,
+    Slack,
+}
+
+impl ChatService {
+    fn name(self) -> &'static str {
+        match self {
+            ChatService::Discord => "Discord",
+            ChatService::Slack => "Slack",
+        }
+    }
+
+    /// The webhook body carrying `text`.
+    fn body(self, text: &str) -> String {
+        let body = match self {
+            // Device names must not ping anyone
+            ChatService::Discord => json!({ "content": text, "allowed_mentions": { "parse": [] } }),
+            // Slack reads `&`, `<` and `>` as markup
+            ChatService::Slack => json!({
+                "text": text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+            }),
+        };
+        body.to_string()
+    }
+}
+
+/// --discord-webhook and --slack-webhook deliveries since startup.
+#[derive(Serialize, ToSchema, SimpleObject)]
+pub struct AlertStats {
+    /// Alerts posted, counted once per service
+    pub delivered: u64,
+    /// Responses asking to slow down (429), each waited out before trying again
+    pub rate_limited: u64,
+    /// Posts that failed or were answered with another error status
+    pub failed_attempts: u64,
+    /// Alerts given up on after every attempt failed, or dropped because a queue was full
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    delivered: AtomicU64,
+    rate_limited: AtomicU64,
+    failed_attempts: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// What an alert was about, to alert of each once.
+#[derive(PartialEq)]
+enum Subject {
+    /// A --sta-lta event, by id
+    Device(u64),
+    /// A --correlate-devices confirmed event, by id
+    Confirmed(u64),
+}
+
+/// What an alert says about an event.
+struct Report {
+    headline: String,
+    start_ms: u64,
+    end_ms: Option<u64>,
+    peak_acceleration: f64,
+    peak_intensity: Option<f64>,
+}
+
+/// A service's queues of alert texts, with the trace context each alert was made in; confirmed
+/// events go ahead of single devices.
+struct Queues {
+    priority: mpsc::Sender<(String, Context)>,
+    normal: mpsc::Sender<(String, Context)>,
+}
+
+/// Chat alert settings, validated at startup.
+pub struct AlertConfig {
+    pub targets: Vec<(ChatService, reqwest::Url)>,
+    pub instance: Option<String>,
+    /// --alert-min-intensity
+    pub min_intensity: Option<f64>,
+    /// --public-url, without a trailing slash
+    pub public_url: Option<String>,
+}
+
+/// Posts a human-readable message to Discord and Slack incoming webhooks when a --sta-lta
+/// event or a --correlate-devices confirmed event ends, at most once per event.
+///
+/// Each service has its own queues and delivery task, so one being throttled or down delays
+/// neither the other nor ingest. Confirmed events are posted before any waiting single-device
+/// alerts, and the events they confirm are not alerted of on their own.
+pub struct Alerter {
+    queues: Vec<Queues>,
+    instance: Option<String>,
+    min_intensity: Option<f64>,
+    public_url: Option<String>,
+    /// `(namespace, subject)`, oldest first
+    alerted: Mutex<VecDeque<(Option<String>, Subject)>>,
+    counters: Arc<Counters>,
+}
+
+impl Alerter {
+    /// Starts a delivery task per service.
+    pub fn spawn(config: AlertConfig) -> Self {
+        let counters = Arc::new(Counters::default());
+        let queues = config
+            .targets
+            .into_iter()
+            .map(|(service, url)| {
+                let (priority, priority_rx) = mpsc::channel(QUEUE_SIZE);
+                let (normal, normal_rx) = mpsc::channel(QUEUE_SIZE);
+                tokio::spawn(deliver(service, url, priority_rx, normal_rx, counters.clone()));
+                Queues { priority, normal }
+            })
+            .collect();
+        Self {
+            queues,
+            instance: config.instance,
+            min_intensity: config.min_intensity,
+            public_url: config.public_url,
+            alerted: Mutex::new(VecDeque::new()),
+            counters,
+        }
+    }
+
+    /// Alerts of an event that ended, unless it is below --alert-min-intensity, part of a
+    /// confirmed event or was alerted of already. `ui_path` is where the namespace's web UI is
+    /// served.
+    pub fn event(&self, namespace: Option<&str>, ui_path: &str, event: &ShakeEvent) {
+        if event.confirmed_id.is_some()
+            || !self.severe(event.peak_intensity)
+            || !self.first(namespace, Subject::Device(event.id))
+        {
+            return;
+        }
+        let report = Report {
+            headline: format!("Shaking on {}", event.user_agent),
+            start_ms: event.start_ms,
+            end_ms: event.end_ms,
+            peak_acceleration: event.peak_acceleration,
+            peak_intensity: event.peak_intensity,
+        };
+        let text = self.text(namespace, ui_path, report);
+        self.enqueue(false, text, event.id);
+    }
+
+    /// Alerts of a confirmed event that ended, ahead of single-device alerts, unless it is
+    /// below --alert-min-intensity or was alerted of already.
+    pub fn confirmed(&self, namespace: Option<&str>, ui_path: &str, event: &ConfirmedEvent) {
+        if !self.severe(event.peak_intensity) || !self.first(namespace, Subject::Confirmed(event.id)) {
+            return;
+        }
+        let report = Report {
+            headline: format!("Shaking confirmed on {} devices ({})", event.devices.len(), event.devices.join(", ")),
+            start_ms: event.start_ms,
+            end_ms: event.end_ms,
+            peak_acceleration: event.peak_acceleration,
+            peak_intensity: event.peak_intensity,
+        };
+        let text = self.text(namespace, ui_path, report);
+        self.enqueue(true, text, event.id);
+    }
+
+    pub fn stats(&self) -> AlertStats {
+        AlertStats {
+            delivered: self.counters.delivered.load(Ordering::Relaxed),
+            rate_limited: self.counters.rate_limited.load(Ordering::Relaxed),
+            failed_attempts: self.counters.failed_attempts.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn severe(&self, peak_intensity: Option<f64>) -> bool {
+        match self.min_intensity {
+            Some(min) => peak_intensity.is_some_and(|intensity| intensity >= min),
+            None => true,
+        }
+    }
+
+    /// Remembers `subject` as alerted of, returning whether it was new.
+    fn first(&self, namespace: Option<&str>, subject: Subject) -> bool {
+        let key = (namespace.map(str::to_string), subject);
+        let mut alerted = self.alerted.lock().unwrap();
+        if alerted.contains(&key) {
+            return false;
+        }
+        alerted.push_back(key);
+        if alerted.len() > MAX_ALERTED {
+            alerted.pop_front();
+        }
+        true
+    }
+
+    fn enqueue(&self, priority: bool, text: String, id: u64) {
+        for queues in &self.queues {
+            let queue = if priority { &queues.priority } else { &queues.normal };
+            if queue.try_send((text.clone(), Context::current())).is_err() {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(event = id, "chat alert queue full, dropped an alert");
+            }
+        }
+    }
+
+    /// E.g. `Shaking on iPhone (home/tenant-a): intensity 2.4, peak acceleration 0.123, 12.3 s
+    /// from 2026-10-16T01:02:03Z`, followed by the UI link under --public-url.
+    fn text(&self, namespace: Option<&str>, ui_path: &str, report: Report) -> String {
+        let Report {
+            headline: mut text,
+            start_ms,
+            end_ms,
+            peak_acceleration,
+            peak_intensity,
+        } = report;
+        let origin: Vec<&str> = self.instance.as_deref().into_iter().chain(namespace).collect();
+        if !origin.is_empty() {
+            text.push_str(&format!(" ({})", origin.join("/")));
+        }
+        text.push(':');
+        if let Some(intensity) = peak_intensity {
+            text.push_str(&format!(" intensity {:.1},", intensity));
+        }
+        let end_ms = end_ms.unwrap_or(start_ms);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(start_ms);
+        text.push_str(&format!(
+            " peak acceleration {:.3}, {:.1} s from {}",
+            peak_acceleration,
+            end_ms.saturating_sub(start_ms) as f64 / 1000.0,
+            humantime::format_rfc3339_seconds(start)
+        ));
+        if let Some(public_url) = &self.public_url {
+            text.push_str(&format!(
+                "\n{}{}/#from={}&to={}",
+                public_url,
+                ui_path,
+                start_ms.saturating_sub(LINK_MARGIN_MS),
+                end_ms + LINK_MARGIN_MS
+            ));
+        }
+        text
+    }
+}
+
+/// Checks a --discord-webhook or --slack-webhook.
+pub fn parse_url(raw: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(raw).map_err(|e| e.to_string())?;
+    match url.scheme() {
+        "https" => Ok(url),
+        other => Err(format!("unsupported scheme `{}`, expected https", other)),
+    }
+}
+
+/// How long the service asked to wait: `Retry-After`, or Discord's
+/// `X-RateLimit-Reset-After`, in (possibly fractional) seconds.
+fn rate_limit_wait(headers: &HeaderMap) -> Option<Duration> {
+    ["retry-after", "x-ratelimit-reset-after"].iter().find_map(|name| {
+        let secs: f64 = headers.get(*name)?.to_str().ok()?.trim().parse().ok()?;
+        (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs).min(MAX_RATE_LIMIT_WAIT))
+    })
+}
+
+/// Posts queued alerts one at a time, priority ones first and each queue in order, waiting out
+/// rate limits and retrying failures with backoff. Each POST carries a `traceparent` continuing
+/// the trace the alert was made in, if any.
+async fn deliver(
+    service: ChatService,
+    url: reqwest::Url,
+    mut priority: mpsc::Receiver<(String, Context)>,
+    mut normal: mpsc::Receiver<(String, Context)>,
+    counters: Arc<Counters>,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("chat alert client builds");
+    loop {
+        let (text, cx) = tokio::select! {
+            biased;
+            Some(alert) = priority.recv() => alert,
+            Some(alert) = normal.recv() => alert,
+            else => break,
+        };
+        let body = service.body(&text);
+        let trace_headers = trace_context::outgoing(&cx, "POST chat alert");
+        let mut retry = FIRST_RETRY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let response = client
+                .post(url.clone())
+                .headers(trace_headers.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+            let (failure, wait) = match response {
+                Ok(response) if response.status().is_success() => {
+                    counters.delivered.fetch_add(1, Ordering::Relaxed);
+                    // Discord announces an exhausted bucket before it starts refusing
+                    let exhausted = response
+                        .headers()
+                        .get("x-ratelimit-remaining")
+                        .is_some_and(|remaining| remaining == "0");
+                    if exhausted && let Some(wait) = rate_limit_wait(response.headers()) {
+                        tokio::time::sleep(wait).await;
+                    }
+                    break;
+                }
+                Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    counters.rate_limited.fetch_add(1, Ordering::Relaxed);
+                    let wait = rate_limit_wait(response.headers()).unwrap_or(retry).max(retry);
+                    ("rate limited".to_string(), wait)
+                }
+                Ok(response) => {
+                    counters.failed_attempts.fetch_add(1, Ordering::Relaxed);
+                    (format!("answered {}", response.status()), retry)
+                }
+                Err(err) => {
+                    counters.failed_attempts.fetch_add(1, Ordering::Relaxed);
+                    (err.to_string(), retry)
+                }
+            };
+            if attempt == MAX_ATTEMPTS {
+                counters.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    service = service.name(),
+                    "chat alert failed {} times, giving up: {}",
+                    MAX_ATTEMPTS,
+                    failure
+                );
+                break;
+            }
+            tracing::warn!(service = service.name(), attempt, "chat alert failed, retrying in {:?}: {}", wait, failure);
+            tokio::time::sleep(wait).await;
+            retry *= 2;
+        }
+    }
+}
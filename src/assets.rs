@@ -0,0 +1,70 @@
+use axum::{
+    extract::Path,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+use crate::error::ApiError;
+
+/// Static files vendored under `assets/`, compiled into the binary.
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+#[exclude = "*.md"]
+struct Assets;
+
+pub const UPLOT_VERSION: &str = "1.6.27";
+const UPLOT_JS: &str = "uplot.iife.min.js";
+const UPLOT_CSS: &str = "uplot.min.css";
+
+/// URLs the web UI loads uPlot's script and stylesheet from.
+pub struct UplotUrls {
+    pub js: String,
+    pub css: String,
+}
+
+impl UplotUrls {
+    /// Points at the embedded copy under `base_path`, or at unpkg.com with `--cdn`.
+    ///
+    /// The version query keeps long-lived caches correct across uPlot upgrades.
+    pub fn new(base_path: &str, cdn: bool) -> Self {
+        if cdn {
+            let dist = format!("https://unpkg.com/uplot@{}/dist", UPLOT_VERSION);
+            Self {
+                js: format!("{}/uPlot.iife.min.js", dist),
+                css: format!("{}/uPlot.min.css", dist),
+            }
+        } else {
+            Self {
+                js: format!("{}/assets/{}?v={}", base_path, UPLOT_JS, UPLOT_VERSION),
+                css: format!("{}/assets/{}?v={}", base_path, UPLOT_CSS, UPLOT_VERSION),
+            }
+        }
+    }
+}
+
+/// Whether both uPlot files were present in `assets/` at build time.
+pub fn uplot_vendored() -> bool {
+    Assets::get(UPLOT_JS).is_some() && Assets::get(UPLOT_CSS).is_some()
+}
+
+/// Serves an embedded asset with its content type and a one-year immutable cache policy.
+pub async fn serve(Path(name): Path<String>) -> Result<Response, ApiError> {
+    let file = Assets::get(&name).ok_or_else(|| ApiError::NotFound(format!("no asset `{}`", name)))?;
+    let content_type = match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        _ => "application/octet-stream",
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            ),
+        ],
+        file.data,
+    )
+        .into_response())
+}
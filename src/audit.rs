@@ -0,0 +1,134 @@
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+/// Records waiting for the writer before further ones are dropped, and counted, so a slow disk
+/// never holds up ingest or requests.
+const QUEUE_SIZE: usize = 65_536;
+
+/// What an --audit-log record is about; the variant's name is its `event` field.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// An HTTP request, once answered
+    Http {
+        method: String,
+        path: String,
+        client_ip: Option<IpAddr>,
+        status: u16,
+        request_id: String,
+    },
+    /// A message received from the upstream
+    Ingest { source: Arc<str>, bytes: usize },
+    /// Messages evicted from the buffer to make room for a new one
+    Eviction { count: usize, bytes: usize },
+    WsConnect { client: u64, client_ip: Option<IpAddr> },
+    WsDisconnect {
+        client: u64,
+        client_ip: Option<IpAddr>,
+        duration_ms: u64,
+    },
+    /// Records lost because the queue was full
+    Dropped { count: u64 },
+}
+
+struct Queued {
+    at: SystemTime,
+    event: AuditEvent,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Counts every record of this run from 1, in the order they were written
+    seq: u64,
+    time: String,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+/// The --audit-log: one JSON record per line, written by a dedicated task. A no-op when
+/// the flag is not given.
+#[derive(Default)]
+pub struct AuditLog {
+    tx: Option<mpsc::Sender<Queued>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending and starts the writer task.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(write_records(tokio::fs::File::from_std(file), rx, dropped.clone()));
+        Ok(Self { tx: Some(tx), dropped })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let queued = Queued {
+            at: SystemTime::now(),
+            event,
+        };
+        if tx.try_send(queued).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Numbers and writes the records in the order they were queued, flushing whenever the queue
+/// runs empty.
+async fn write_records(file: tokio::fs::File, mut rx: mpsc::Receiver<Queued>, dropped: Arc<AtomicU64>) {
+    let mut out = BufWriter::new(file);
+    let mut seq: u64 = 0;
+    let mut line = Vec::new();
+    while let Some(first) = rx.recv().await {
+        let mut next = Some(first);
+        while let Some(queued) = next {
+            seq += 1;
+            encode(&mut line, seq, queued.at, &queued.event);
+            if let Err(err) = out.write_all(&line).await {
+                tracing::error!("failed to write the audit log: {}", err);
+            }
+            next = rx.try_recv().ok();
+        }
+        let lost = dropped.swap(0, Ordering::Relaxed);
+        if lost > 0 {
+            seq += 1;
+            encode(&mut line, seq, SystemTime::now(), &AuditEvent::Dropped { count: lost });
+            if let Err(err) = out.write_all(&line).await {
+                tracing::error!("failed to write the audit log: {}", err);
+            }
+        }
+        if let Err(err) = out.flush().await {
+            tracing::error!("failed to write the audit log: {}", err);
+        }
+    }
+}
+
+fn encode(line: &mut Vec<u8>, seq: u64, at: SystemTime, event: &AuditEvent) {
+    let record = Record {
+        seq,
+        time: humantime::format_rfc3339_millis(at).to_string(),
+        event,
+    };
+    line.clear();
+    serde_json::to_writer(&mut *line, &record).expect("audit record serializes");
+    line.push(b'\n');
+}
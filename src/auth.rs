@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use axum::extract::Query;
+use base64::Engine;
+use axum::http::{header, HeaderMap, Uri};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// WebSocket subprotocol that marks the next offered one as the API token.
+pub const BEARER_PROTOCOL: &str = "bearer";
+
+/// Set of accepted API tokens, stored only as SHA-256 digests.
+#[derive(Default)]
+pub struct ApiTokens {
+    digests: Vec<[u8; 32]>,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+impl ApiTokens {
+    /// Builds the token set from plain `--api-token` values and an optional file of
+    /// hex-encoded SHA-256 digests (one per line, `#` starts a comment).
+    pub fn load(plain: &[String], digest_file: Option<&Path>) -> Result<Self, String> {
+        let mut digests: Vec<[u8; 32]> = plain.iter().map(|t| sha256(t.as_bytes())).collect();
+        if let Some(path) = digest_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            for (lineno, line) in contents.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let hex_digest = line.strip_prefix("sha256:").unwrap_or(line);
+                let mut digest = [0u8; 32];
+                hex::decode_to_slice(hex_digest, &mut digest).map_err(|e| {
+                    format!("{}:{}: invalid SHA-256 digest: {}", path.display(), lineno + 1, e)
+                })?;
+                digests.push(digest);
+            }
+        }
+        Ok(Self { digests })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Checks the bearer token, falling back to `?token=` or the `bearer` WebSocket subprotocol
+    /// for browser WebSocket/EventSource clients, which cannot set headers.
+    pub fn authorize(&self, headers: &HeaderMap, uri: &Uri) -> bool {
+        let Some(presented) = presented_token(headers, uri) else {
+            return false;
+        };
+        let digest = sha256(presented.as_bytes());
+        // Check every digest so the timing does not reveal which one matched
+        self.digests
+            .iter()
+            .fold(false, |found, d| constant_time_eq(d, &digest) | found)
+    }
+}
+
+/// Claims of a JWT accepted via `--jwt-secret`, added to the request's extensions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtClaims {
+    pub sub: Option<String>,
+    pub exp: u64,
+}
+
+/// Verifies HS256 JWTs signed with the `--jwt-secret` key, which are presented wherever an
+/// API token is.
+pub struct JwtVerifier {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    /// Takes the key base64-encoded, as `openssl rand -base64 32` prints one.
+    pub fn new(secret: &str) -> Result<Self, String> {
+        let secret = base64::engine::general_purpose::STANDARD
+            .decode(secret.trim())
+            .map_err(|e| format!("not base64: {}", e))?;
+        if secret.is_empty() {
+            return Err("the key is empty".to_string());
+        }
+        // HS256 only, so a token cannot pick a weaker algorithm; `exp` is required, and held
+        // to the second as open /ws connections are closed then
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        Ok(Self {
+            key: DecodingKey::from_secret(&secret),
+            validation,
+        })
+    }
+
+    /// The claims of the presented token, if it is signed with the key and not expired.
+    pub fn authorize(&self, headers: &HeaderMap, uri: &Uri) -> Option<JwtClaims> {
+        let presented = presented_token(headers, uri)?;
+        match jsonwebtoken::decode::<JwtClaims>(&presented, &self.key, &self.validation) {
+            Ok(data) => Some(data.claims),
+            Err(err) => {
+                tracing::debug!("rejected JWT: {}", err);
+                None
+            }
+        }
+    }
+}
+
+/// Credentials for HTTP Basic auth, kept only as a digest of `user:password`.
+pub struct BasicCredentials {
+    digest: [u8; 32],
+}
+
+impl BasicCredentials {
+    /// Parses `user:password`.
+    pub fn parse(pair: &str) -> Result<Self, String> {
+        let pair = pair.trim_end_matches(['\r', '\n']);
+        match pair.split_once(':') {
+            Some((user, password)) if !user.is_empty() && !password.is_empty() => Ok(Self {
+                digest: sha256(pair.as_bytes()),
+            }),
+            _ => Err("expected `user:password`".to_string()),
+        }
+    }
+
+    pub fn authorize(&self, headers: &HeaderMap) -> bool {
+        let Some(encoded) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Basic "))
+        else {
+            return false;
+        };
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+        constant_time_eq(&sha256(&decoded), &self.digest)
+    }
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// The subprotocol offered after `bearer` in `Sec-WebSocket-Protocol`, as sent by
+/// `new WebSocket(url, ["bearer", token])`; the server then selects `bearer`.
+fn protocol_token(headers: &HeaderMap) -> Option<&str> {
+    let offered = headers.get(header::SEC_WEBSOCKET_PROTOCOL)?.to_str().ok()?;
+    let mut protocols = offered.split(',').map(str::trim);
+    protocols.find(|&protocol| protocol == BEARER_PROTOCOL)?;
+    protocols.next()
+}
+
+fn query_token(uri: &Uri) -> Option<String> {
+    Query::<TokenQuery>::try_from_uri(uri).ok()?.0.token
+}
+
+/// The bearer token, falling back to the `bearer` subprotocol and then `?token=`.
+fn presented_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    bearer_token(headers)
+        .or_else(|| protocol_token(headers))
+        .map(str::to_string)
+        .or_else(|| query_token(uri))
+}
+
+pub fn bearer_matches(headers: &HeaderMap, expected: &str) -> bool {
+    bearer_token(headers).is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+/// Compares two byte strings without short-circuiting on the first mismatch.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
@@ -0,0 +1,725 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+
+// Also compiled into build.rs, which renders the man page from this definition; keep it free of
+// crate-internal imports.
+
+/// `(status, meaning)` documented in `--help` and the man page's EXIT STATUS section.
+pub const EXIT_CODES: &[(i32, &str)] = &[
+    (0, "Clean shutdown after Ctrl+C or SIGTERM"),
+    (1, "The HTTP server failed while running, or watch/tui could not follow the stream"),
+    (2, "Invalid command line or configuration"),
+    (3, "In-flight work outlived --shutdown-timeout-secs"),
+];
+
+/// `(description, command)` shown in `--help` and the man page's EXAMPLES section.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "Collect from an upstream and serve the UI on port 3000",
+        "yurecollect wss://example.com/yure/",
+    ),
+    (
+        "Serve under /yure behind a reverse proxy, with an API token",
+        "yurecollect --base-path /yure --api-token secret wss://example.com/yure/",
+    ),
+    (
+        "Run in the background, logging to a file",
+        "yurecollect --daemonize --log-file /var/log/yurecollect.log --pid-file /run/yurecollect.pid wss://example.com/yure/",
+    ),
+    (
+        "Install bash completions",
+        "yurecollect completions bash > /etc/bash_completion.d/yurecollect",
+    ),
+];
+
+fn after_long_help() -> String {
+    let mut help = String::from("Examples:\n");
+    for (description, command) in EXAMPLES {
+        help.push_str(&format!("  # {}\n  {}\n", description, command));
+    }
+    help.push_str("\nExit status:\n");
+    for (code, meaning) in EXIT_CODES {
+        help.push_str(&format!("  {}  {}\n", code, meaning));
+    }
+    help
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    version,
+    about,
+    after_long_help = after_long_help(),
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print version, git commit, build date and rustc version as JSON and exit
+    #[arg(long)]
+    pub build_info: bool,
+
+    /// Validate the configuration, print the resolved settings as JSON and exit without serving
+    #[arg(long, env = "DRY_RUN")]
+    pub dry_run: bool,
+
+    /// Detach into the background (Unix only); the starting process exits 0 once the HTTP port
+    /// is bound
+    #[arg(long, env = "DAEMONIZE", conflicts_with = "dry_run")]
+    pub daemonize: bool,
+
+    /// File that stdout and stderr are appended to with --daemonize (default: discarded);
+    /// reopened on SIGHUP, e.g. after `logrotate` moved it away
+    #[arg(long, env = "LOG_FILE", requires = "daemonize")]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate --log-file to `<path>.1`, `<path>.2`, ... once it grows past this many bytes,
+    /// checked every second (0 disables)
+    #[arg(long, env = "LOG_MAX_SIZE_BYTES", default_value_t = 0, requires = "log_file")]
+    pub log_max_size_bytes: u64,
+
+    /// Rotated --log-file copies kept; older ones are deleted
+    #[arg(long, env = "LOG_MAX_FILES", default_value_t = 5, requires = "log_file")]
+    pub log_max_files: usize,
+
+    /// A log level set through `POST /api/log-level` goes back to the startup one after this
+    /// many seconds (0 keeps it)
+    #[arg(long, env = "LOG_LEVEL_TIMEOUT_SECS", default_value_t = 0)]
+    pub log_level_timeout_secs: u64,
+
+    /// File the daemon's PID is written to (and locked) with --daemonize
+    #[arg(long, env = "PID_FILE", requires = "daemonize")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Upstream WebSocket URL (ws:// or wss://)
+    #[arg(env = "WS_URL", required_unless_present_any = ["build_info", "namespaces_file"])]
+    pub url: Option<String>,
+
+    /// Serve the API under `/api/<NAME>/` and /ws as `/ws/<NAME>`, and everything else (the
+    /// web UI, probes, assets) under `/<NAME>/`
+    #[arg(long, env = "NAMESPACE", value_name = "NAME", value_parser = parse_namespace)]
+    pub namespace: Option<String>,
+
+    /// File of `<name> <upstream URL>` lines, instead of the upstream URL: each namespace gets
+    /// its own upstream connection, buffer and live stream, served as with --namespace
+    #[arg(
+        long,
+        env = "NAMESPACES_FILE",
+        conflicts_with_all = ["url", "namespace", "grpc_port"]
+    )]
+    pub namespaces_file: Option<PathBuf>,
+
+    /// Give up on an upstream connection attempt after this many milliseconds
+    #[arg(long, env = "UPSTREAM_CONNECT_TIMEOUT_MS", default_value_t = 10_000)]
+    pub upstream_connect_timeout_ms: u64,
+
+    /// Reconnect when the upstream sends nothing for this many milliseconds (0 disables)
+    #[arg(long, env = "UPSTREAM_READ_TIMEOUT_MS", default_value_t = 0)]
+    pub upstream_read_timeout_ms: u64,
+
+    /// Seconds to drain in-flight requests and close WebSockets on shutdown before forcing exit
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECS", default_value_t = 10)]
+    pub shutdown_timeout_secs: u64,
+
+    /// Send a WebSocket ping to the upstream every this many seconds (0 disables)
+    #[arg(long, env = "UPSTREAM_PING_INTERVAL_SECS", default_value_t = 30)]
+    pub upstream_ping_interval_secs: u64,
+
+    /// Reconnect when a ping is not answered with a pong within this many seconds
+    #[arg(long, env = "UPSTREAM_PONG_TIMEOUT_SECS", default_value_t = 10)]
+    pub upstream_pong_timeout_secs: u64,
+
+    /// Field of upstream JSON messages holding an increasing sequence number; messages numbered
+    /// at or below the highest seen are discarded as duplicates, e.g. history replayed on
+    /// reconnect, unless the upstream evidently restarted its numbering
+    #[arg(long, env = "UPSTREAM_SEQ_FIELD", value_name = "FIELD")]
+    pub upstream_seq_field: Option<String>,
+
+    /// Ingest at most this many upstream messages per second, with bursts of up to one second's
+    /// worth; beyond that the upstream is read more slowly (0 disables)
+    #[arg(long, env = "UPSTREAM_RATE_LIMIT_RPS", default_value_t = 0)]
+    pub upstream_rate_limit_rps: u32,
+
+    /// Lua script whose `transform(msg)` rewrites every upstream text message before it is
+    /// buffered, or drops it by returning nil
+    #[arg(long, env = "TRANSFORM_SCRIPT")]
+    pub transform_script: Option<PathBuf>,
+
+    /// WebAssembly module (`.wasm` or `.wat`) whose `transform` export rewrites or drops every
+    /// upstream text message, as an alternative to --transform-script (ABI in
+    /// docs/wasm-plugin.md)
+    #[arg(long, env = "WASM_PLUGIN", conflicts_with = "transform_script")]
+    pub wasm_plugin: Option<PathBuf>,
+
+    /// Compute each device's JMA instrumental seismic intensity from its `x`/`y`/`z`
+    /// acceleration, given in this unit (see /api/intensity)
+    #[arg(long, env = "INTENSITY", value_enum, value_name = "UNIT")]
+    pub intensity: Option<AccelUnit>,
+
+    /// High-pass filter each device's `x`/`y`/`z` at ingest, removing gravity and other constant
+    /// offsets from the stored and delivered messages
+    #[arg(long, env = "HIGHPASS")]
+    pub highpass: bool,
+
+    /// Cutoff frequency of --highpass, in Hz
+    #[arg(long, env = "HIGHPASS_CUTOFF", value_name = "HZ", value_parser = parse_ratio, default_value_t = 0.1)]
+    pub highpass_cutoff: f64,
+
+    /// Whether --highpass replaces `x`/`y`/`z` or adds `x_hp`/`y_hp`/`z_hp` next to them
+    #[arg(long, env = "HIGHPASS_OUTPUT", value_enum, default_value_t = HighpassOutput::Alongside)]
+    pub highpass_output: HighpassOutput,
+
+    /// A device's --highpass filter starts over after a gap in its samples longer than this
+    #[arg(long, env = "HIGHPASS_MAX_GAP", value_parser = parse_interval, default_value = "1s")]
+    pub highpass_max_gap: Duration,
+
+    /// Reject corrupt samples at ingest, before --highpass and the per-device statistics: those
+    /// whose `x`/`y`/`z` magnitude exceeds --outlier-max, and isolated single-sample spikes
+    #[arg(long, env = "OUTLIER_FILTER")]
+    pub outlier_filter: bool,
+
+    /// Magnitude of `x`/`y`/`z` above which --outlier-filter rejects a sample, in the devices'
+    /// unit (default: no cap)
+    #[arg(long, env = "OUTLIER_MAX", value_parser = parse_ratio)]
+    pub outlier_max: Option<f64>,
+
+    /// --outlier-filter rejects a sample more than this many MADs (as standard deviations) away
+    /// from the device's recent median and its previous sample, when the next sample returns;
+    /// 0 leaves only --outlier-max
+    #[arg(long, env = "OUTLIER_MADS", value_parser = parse_non_negative, default_value_t = 10.0)]
+    pub outlier_mads: f64,
+
+    /// Whether --outlier-filter marks rejected samples with `"outlier": true` or drops them
+    #[arg(long, env = "OUTLIER_MODE", value_enum, default_value_t = OutlierMode::Flag)]
+    pub outlier_mode: OutlierMode,
+
+    /// Window of device sample time the per-device RMS at /api/noise is taken over
+    #[arg(long, env = "RMS_WINDOW", value_parser = parse_interval, default_value = "10s")]
+    pub rms_window: Duration,
+
+    /// Detect shaking per device with an STA/LTA trigger on the vector acceleration, reported at
+    /// /api/shake-events and as `event_start`/`event_end` messages on /ws
+    #[arg(long, env = "STA_LTA")]
+    pub sta_lta: bool,
+
+    /// Short-term average window of --sta-lta
+    #[arg(long, env = "STA_WINDOW", value_parser = parse_interval, default_value = "1s")]
+    pub sta_window: Duration,
+
+    /// Long-term average window of --sta-lta; a device triggers only after sending for this long
+    #[arg(long, env = "LTA_WINDOW", value_parser = parse_interval, default_value = "30s")]
+    pub lta_window: Duration,
+
+    /// STA/LTA ratio that triggers an event
+    #[arg(long, env = "TRIGGER_RATIO", value_parser = parse_ratio, default_value_t = 3.0)]
+    pub trigger_ratio: f64,
+
+    /// STA/LTA ratio below which an event ends; lower than --trigger-ratio so a ratio hovering
+    /// around the trigger does not start and end events over and over
+    #[arg(long, env = "DETRIGGER_RATIO", value_parser = parse_ratio, default_value_t = 1.5)]
+    pub detrigger_ratio: f64,
+
+    /// Triggers that detrigger sooner are discarded instead of reported as events
+    #[arg(long, env = "MIN_EVENT_DURATION", value_parser = parse_interval, default_value = "2s")]
+    pub min_event_duration: Duration,
+
+    /// Confirm --sta-lta events once this many distinct devices have shaking starting within
+    /// --correlate-window of each other; events of one device alone stay unconfirmed
+    #[arg(long, env = "CORRELATE_DEVICES", value_name = "N", requires = "sta_lta", value_parser = parse_min_devices)]
+    pub correlate_devices: Option<usize>,
+
+    /// How far apart the onsets of events confirming each other under --correlate-devices may be
+    #[arg(long, env = "CORRELATE_WINDOW", value_parser = parse_interval, default_value = "2s")]
+    pub correlate_window: Duration,
+
+    /// Save the messages around each --sta-lta event to a file in this directory, from
+    /// --record-pre-trigger before it to --record-post-roll after it, for
+    /// /api/events/{id}/recording
+    #[arg(long, env = "RECORD_DIR", requires = "sta_lta")]
+    pub record_dir: Option<PathBuf>,
+
+    /// Messages kept in memory to start a --record-dir recording with
+    #[arg(long, env = "RECORD_PRE_TRIGGER", value_parser = parse_interval, default_value = "30s")]
+    pub record_pre_trigger: Duration,
+
+    /// A --record-dir recording goes on for this long after its last event ended; an event
+    /// starting meanwhile extends it
+    #[arg(long, env = "RECORD_POST_ROLL", value_parser = parse_interval, default_value = "30s")]
+    pub record_post_roll: Duration,
+
+    /// POST a JSON notification here when a --sta-lta event starts or ends, or a device's
+    /// intensity exceeds --webhook-threshold and clears again
+    #[arg(long, env = "WEBHOOK_URL")]
+    pub webhook_url: Option<String>,
+
+    /// Sign --webhook-url bodies with HMAC-SHA256 under this hex-encoded key, sent as
+    /// `X-Yurecollect-Signature: sha256=<hex>`
+    #[arg(long, env = "WEBHOOK_SECRET", value_name = "HEX_KEY", hide_env_values = true, requires = "webhook_url")]
+    pub webhook_secret: Option<String>,
+
+    /// Notify --webhook-url when a device's --intensity reaches this JMA intensity
+    #[arg(long, env = "WEBHOOK_THRESHOLD", value_name = "INTENSITY", requires_all = ["webhook_url", "intensity"])]
+    pub webhook_threshold: Option<f64>,
+
+    /// A --webhook-threshold exceedance is only cleared once the intensity has stayed 0.5 below
+    /// the threshold for this long
+    #[arg(long, env = "WEBHOOK_COOLDOWN", value_parser = parse_interval, default_value = "10s")]
+    pub webhook_cooldown: Duration,
+
+    /// Name of this collector, sent as `instance` in --webhook-url notifications and named in
+    /// chat alerts
+    #[arg(long, env = "INSTANCE_NAME")]
+    pub instance_name: Option<String>,
+
+    /// Post a message to this Discord webhook when a --sta-lta event ends
+    #[arg(long, env = "DISCORD_WEBHOOK", value_name = "URL", hide_env_values = true, requires = "sta_lta")]
+    pub discord_webhook: Option<String>,
+
+    /// Post a message to this Slack incoming webhook when a --sta-lta event ends
+    #[arg(long, env = "SLACK_WEBHOOK", value_name = "URL", hide_env_values = true, requires = "sta_lta")]
+    pub slack_webhook: Option<String>,
+
+    /// Only alert --discord-webhook and --slack-webhook of events whose peak --intensity
+    /// reached this JMA intensity
+    #[arg(long, env = "ALERT_MIN_INTENSITY", value_name = "INTENSITY", requires = "intensity")]
+    pub alert_min_intensity: Option<f64>,
+
+    /// Address the web UI is reached at from outside (e.g. `https://yure.example.com`, without
+    /// --base-path), for links in chat alerts
+    #[arg(long, env = "PUBLIC_URL", value_name = "URL")]
+    pub public_url: Option<String>,
+
+    /// Send a WebSocket ping to each /ws client every this many seconds (0 disables)
+    #[arg(long, env = "WS_PING_INTERVAL_SECS", default_value_t = 30)]
+    pub ws_ping_interval_secs: u64,
+
+    /// Also send each /ws client a `{"type":"heartbeat",...}` text message this often (e.g.
+    /// `25s`), for proxies that close connections without data despite pings
+    #[arg(long, env = "WS_HEARTBEAT", value_parser = parse_interval)]
+    pub ws_heartbeat: Option<Duration>,
+
+    /// Drop a /ws client that does not answer a ping with a pong within this many seconds
+    #[arg(long, env = "WS_PONG_TIMEOUT_SECS", default_value_t = 10)]
+    pub ws_pong_timeout_secs: u64,
+
+    /// Messages queued per /ws client before the oldest are dropped (or, with --ws-strict, the
+    /// client is disconnected)
+    #[arg(long, env = "WS_QUEUE_SIZE", default_value_t = 256, value_parser = parse_positive)]
+    pub ws_queue_size: usize,
+
+    /// Disconnect a /ws client whose send queue overflows with close reason "too slow" instead
+    /// of dropping its oldest queued messages
+    #[arg(long, env = "WS_STRICT")]
+    pub ws_strict: bool,
+
+    /// Disconnect a /ws client with close reason "lagged" when it falls behind the broadcast
+    /// and would skip messages, instead of telling it with a `lagged` notice and carrying on
+    #[arg(long, env = "WS_DISCONNECT_ON_LAG")]
+    pub ws_disconnect_on_lag: bool,
+
+    /// Warn when a /ws client that acknowledges messages with `{"ack":<seq>}` has more than
+    /// this many sent messages unacknowledged (0 disables)
+    #[arg(long, env = "WS_ACK_WARN_THRESHOLD", default_value_t = 1000)]
+    pub ws_ack_warn_threshold: u64,
+
+    /// /healthz answers 503 once the upstream has been disconnected for longer (0 disables)
+    #[arg(long, env = "HEALTH_STALE_SECS", default_value_t = 60)]
+    pub health_stale_secs: u64,
+
+    /// /healthz answers 503 while the buffer holds no messages
+    #[arg(long, env = "HEALTH_REQUIRE_DATA")]
+    pub health_require_data: bool,
+
+    /// Bearer token required by the /api/admin/* endpoints (disabled when unset)
+    #[arg(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// API token required for the HTTP API and /ws (repeatable; comma-separated in the env var)
+    #[arg(
+        long = "api-token",
+        env = "API_TOKENS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    pub api_tokens: Vec<String>,
+
+    /// File of hex SHA-256 digests of accepted API tokens, one per line
+    #[arg(long, env = "API_TOKEN_FILE")]
+    pub api_token_file: Option<PathBuf>,
+
+    /// Require HTTP Basic auth as `user:password` (either this or an API token is accepted)
+    #[arg(
+        long,
+        env = "BASIC_AUTH",
+        hide_env_values = true,
+        conflicts_with = "basic_auth_file"
+    )]
+    pub basic_auth: Option<String>,
+
+    /// Read the Basic auth `user:password` from a file instead
+    #[arg(long, env = "BASIC_AUTH_FILE")]
+    pub basic_auth_file: Option<PathBuf>,
+
+    /// Also accept HS256 JWTs signed with this base64-encoded key wherever an API token is
+    #[arg(long, env = "JWT_SECRET", value_name = "BASE64_KEY", hide_env_values = true)]
+    pub jwt_secret: Option<String>,
+
+    /// Serve the web UI from this directory (index.html and its files) instead of the embedded page
+    #[arg(long, env = "UI_DIR")]
+    pub ui_dir: Option<PathBuf>,
+
+    /// Serve the web UI page without a token even when API tokens are configured
+    #[arg(long, env = "PUBLIC_UI")]
+    pub public_ui: bool,
+
+    /// Load uPlot from unpkg.com instead of the copy embedded in the binary
+    #[arg(long, env = "CDN")]
+    pub cdn: bool,
+
+    /// Keep copies of messages that failed processing (JSON parsing, --transform-script or
+    /// --wasm-plugin) for /api/messages/dead-letter, in up to this many bytes (0 disables)
+    #[arg(long, env = "DEAD_LETTER_BYTES", default_value_t = 16 * 1024 * 1024)]
+    pub dead_letter_bytes: usize,
+
+    /// Insert a global `"_seq": <n>` counter into every JSON object message
+    #[arg(long, env = "INJECT_SEQ")]
+    pub inject_seq: bool,
+
+    /// Sign every stored message with HMAC-SHA256 under this hex-encoded key: a `"_sig"` member
+    /// for JSON objects, a ` _sig=<hex>` suffix for other text
+    #[arg(long, env = "SIGN_KEY", value_name = "HEX_KEY", hide_env_values = true)]
+    pub sign_key: Option<String>,
+
+    /// Time window shown by the web UI chart, in seconds
+    #[arg(long, env = "CHART_WINDOW_SECS", default_value_t = 3600)]
+    pub chart_window_secs: u64,
+
+    /// Maximum number of samples kept by the web UI chart
+    #[arg(long, env = "CHART_MAX_POINTS", default_value_t = 20000)]
+    pub chart_max_points: usize,
+
+    /// Maximum number of entries returned by /api/messages/search
+    #[arg(long, env = "SEARCH_MAX_RESULTS", default_value_t = 1000)]
+    pub search_max_results: usize,
+
+    /// Maximum number of resampled points /api/spectrum computes a spectrum of; larger ranges
+    /// are rejected with 413
+    #[arg(long, env = "SPECTRUM_MAX_POINTS", default_value_t = 1 << 20)]
+    pub spectrum_max_points: usize,
+
+    /// Address the HTTP server listens on (repeatable); `[::]:3000` listens on IPv6
+    #[arg(
+        long,
+        env = "BIND",
+        value_delimiter = ',',
+        default_value = "0.0.0.0:3000"
+    )]
+    pub bind: Vec<SocketAddr>,
+
+    /// Serve the gRPC API on this port, at the address of the first --bind (disabled when unset)
+    #[arg(long, env = "GRPC_PORT")]
+    pub grpc_port: Option<u16>,
+
+    /// Also listen on `[::]` at each IPv4 `--bind` port, serving IPv4 and IPv6 side by side
+    #[arg(long, env = "BIND_IPV6")]
+    pub bind_ipv6: bool,
+
+    /// Skip `--bind` addresses that fail to bind with a warning instead of exiting
+    #[arg(long, env = "BIND_BEST_EFFORT")]
+    pub bind_best_effort: bool,
+
+    /// PEM certificate chain; serves HTTPS (reloaded on SIGHUP) together with --tls-key
+    #[arg(long, env = "TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Serve everything under this path prefix, e.g. `/yure` behind a reverse proxy
+    #[arg(long, env = "BASE_PATH", default_value = "", value_parser = parse_base_path)]
+    pub base_path: String,
+
+    /// Allow cross-origin requests from this origin (repeatable, or `*` for any)
+    #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// Origins allowed to open /ws and /api/graphql/ws (comma-separated, or `*` for any);
+    /// defaults to the --cors-origin list
+    #[arg(long, env = "ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub allowed_origins: Vec<String>,
+
+    /// Request paths left out of the access log (repeatable); `/ws` can be added to skip upgrades
+    #[arg(
+        long = "access-log-exclude",
+        env = "ACCESS_LOG_EXCLUDE",
+        value_delimiter = ',',
+        default_values_t = [
+            "/healthz".to_string(),
+            "/metrics".to_string(),
+            "/livez".to_string(),
+            "/readyz".to_string(),
+        ]
+    )]
+    pub access_log_exclude: Vec<String>,
+
+    /// Append an NDJSON audit record of every HTTP request, upstream message, buffer eviction
+    /// and /ws connect and disconnect to this file
+    #[arg(long, env = "AUDIT_LOG")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Trust the X-Forwarded-For header for the client IP (only behind a reverse proxy)
+    #[arg(long, env = "TRUST_PROXY")]
+    pub trust_proxy: bool,
+
+    /// Only accept clients in this network, a CIDR or single address (repeatable;
+    /// comma-separated in the env var)
+    #[arg(long = "allow-ip", env = "ALLOW_IPS", value_delimiter = ',')]
+    pub allow_ips: Vec<String>,
+
+    /// Reject clients in this network with 403, even if allowed (repeatable; comma-separated in
+    /// the env var)
+    #[arg(long = "block-ip", env = "BLOCK_IPS", value_delimiter = ',')]
+    pub block_ips: Vec<String>,
+
+    /// File of networks to allow, one per line, in addition to --allow-ip; re-read on SIGHUP
+    #[arg(long, env = "ALLOW_IP_FILE")]
+    pub allow_ip_file: Option<PathBuf>,
+
+    /// File of networks to block, one per line, in addition to --block-ip; re-read on SIGHUP
+    #[arg(long, env = "BLOCK_IP_FILE")]
+    pub block_ip_file: Option<PathBuf>,
+
+    /// Per-IP requests per minute for cheap API endpoints such as /api/status (0 disables)
+    #[arg(long, env = "RATE_LIMIT_CHEAP", default_value_t = 600)]
+    pub rate_limit_cheap: u32,
+
+    /// Per-IP requests per minute for expensive endpoints such as /api/messages (0 disables)
+    #[arg(long, env = "RATE_LIMIT_EXPENSIVE", default_value_t = 60)]
+    pub rate_limit_expensive: u32,
+
+    /// Per-IP /ws connection attempts per minute (0 disables)
+    #[arg(long, env = "RATE_LIMIT_WS", default_value_t = 30)]
+    pub rate_limit_ws: u32,
+
+    /// Requests handled at once before answering 503 (0 disables)
+    #[arg(long, env = "MAX_CONCURRENT_REQUESTS", default_value_t = 256)]
+    pub max_concurrent_requests: usize,
+
+    /// Seconds before a request is answered 408; /ws and /api/messages/stream are exempt (0 disables)
+    #[arg(long, env = "REQUEST_TIMEOUT_SECS", default_value_t = 30)]
+    pub request_timeout_secs: u64,
+
+    /// Largest accepted POST body in bytes before answering 413 (0 disables)
+    #[arg(long, env = "MAX_BODY_BYTES", default_value_t = 1024 * 1024)]
+    pub max_body_bytes: u64,
+
+    /// /ws connections served at once before answering upgrades with 503 (0 disables)
+    #[arg(long, env = "MAX_WS_CLIENTS", default_value_t = 100)]
+    pub max_ws_clients: usize,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the yurecollect(1) man page (roff) to stdout
+    Man,
+    /// Follow a running instance's live messages on the terminal, like `tail -f`
+    Watch {
+        /// Base URL of the instance, including any --base-path
+        #[arg(long, env = "YURECOLLECT_SERVER", default_value = "http://127.0.0.1:3000")]
+        server: String,
+        /// API token sent as a bearer token
+        #[arg(long, env = "YURECOLLECT_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// How to print each message
+        #[arg(long, value_enum, default_value_t = WatchFormat::Raw)]
+        format: WatchFormat,
+        /// Only print messages matching this regex
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// Interactive terminal dashboard of a running instance's messages and message rate
+    Tui {
+        /// Base URL of the instance, including any --base-path
+        #[arg(long, env = "YURECOLLECT_SERVER", default_value = "http://127.0.0.1:3000")]
+        server: String,
+        /// API token sent as a bearer token
+        #[arg(long, env = "YURECOLLECT_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum WatchFormat {
+    /// Exactly as received
+    Raw,
+    /// One compact JSON document per line
+    Json,
+    /// Indented JSON
+    Pretty,
+}
+
+/// Where --highpass puts the filtered acceleration.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighpassOutput {
+    /// As `x_hp`/`y_hp`/`z_hp`, keeping the raw values
+    Alongside,
+    /// In place of the raw values
+    Replace,
+}
+
+/// What --outlier-filter does with a rejected sample.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutlierMode {
+    /// Keep it, with `"outlier": true` added; the server's own statistics skip it
+    Flag,
+    /// Remove it from its message, and the message if nothing is left
+    Drop,
+}
+
+/// Unit of the `x`/`y`/`z` acceleration devices send.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum AccelUnit {
+    /// Standard gravity, 9.80665 m/s²
+    G,
+    /// Metres per second squared
+    #[value(name = "m/s2")]
+    MetersPerSecondSquared,
+    /// Gal (cm/s²), the unit of the JMA formula
+    Gal,
+}
+
+impl AccelUnit {
+    /// Gal per unit.
+    pub fn to_gal(self) -> f64 {
+        match self {
+            AccelUnit::G => 980.665,
+            AccelUnit::MetersPerSecondSquared => 100.0,
+            AccelUnit::Gal => 1.0,
+        }
+    }
+}
+
+impl Args {
+    /// The upstream URL; clap only lets it be absent with a subcommand, `--build-info` or
+    /// --namespaces-file, whose namespaces each get a copy of the arguments with their own.
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or_default()
+    }
+
+    /// Where the web UI and the other non-API paths are served: --base-path, plus `/<NAME>`
+    /// under --namespace.
+    pub fn ui_path(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}/{}", self.base_path, namespace),
+            None => self.base_path.clone(),
+        }
+    }
+
+    /// The prefix of the HTTP API, `/api` under --base-path and --namespace.
+    pub fn api_path(&self) -> String {
+        self.namespaced("api")
+    }
+
+    /// The path of /ws under --base-path and --namespace.
+    pub fn ws_path(&self) -> String {
+        self.namespaced("ws")
+    }
+
+    fn namespaced(&self, root: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}/{}/{}", self.base_path, root, namespace),
+            None => format!("{}/{}", self.base_path, root),
+        }
+    }
+
+    /// The origins WebSocket upgrades are checked against: --allowed-origins, or the CORS
+    /// allow-list when it is not given.
+    pub fn ws_origins(&self) -> &[String] {
+        match self.allowed_origins.is_empty() {
+            true => &self.cors_origins,
+            false => &self.allowed_origins,
+        }
+    }
+}
+
+/// Normalizes `--base-path` to either "" or "/segment[/segment...]" without a trailing slash.
+pub fn parse_base_path(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    let valid = trimmed
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~' | '/'));
+    if !valid
+        || trimmed
+            .split('/')
+            .any(|seg| seg.is_empty() || seg == "." || seg == "..")
+    {
+        return Err(format!("invalid base path `{}`", raw));
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+/// A --namespace: letters, digits, `-` and `_`, other than the `api` and `ws` path roots.
+pub fn parse_namespace(raw: &str) -> Result<String, String> {
+    let valid = !raw.is_empty() && raw.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    if !valid {
+        return Err(format!("invalid namespace `{}`: use letters, digits, `-` and `_`", raw));
+    }
+    if raw == "api" || raw == "ws" {
+        return Err(format!("`{}` cannot be a namespace", raw));
+    }
+    Ok(raw.to_string())
+}
+
+fn parse_interval(raw: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(raw) {
+        Ok(interval) if interval.is_zero() => Err("must be longer than 0".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_ratio(raw: &str) -> Result<f64, String> {
+    match raw.parse::<f64>() {
+        Ok(ratio) if ratio.is_finite() && ratio > 0.0 => Ok(ratio),
+        Ok(_) => Err("must be a number greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_non_negative(raw: &str) -> Result<f64, String> {
+    match raw.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        Ok(_) => Err("must be a number of at least 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_min_devices(raw: &str) -> Result<usize, String> {
+    match raw.parse::<usize>() {
+        Ok(n) if n < 2 => Err("must be at least 2".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_positive(raw: &str) -> Result<usize, String> {
+    match raw.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,35 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::HeaderMap,
+};
+
+/// Resolves the client address of a request.
+///
+/// The left-most `X-Forwarded-For` entry is only honored when `trust_proxy` is set, since
+/// any client can send that header; otherwise the TCP peer address is used.
+pub fn client_ip(req: &Request, trust_proxy: bool) -> Option<IpAddr> {
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+    resolve(req.headers(), peer, trust_proxy)
+}
+
+/// `client_ip` for handlers that extract the headers and peer address separately.
+pub fn resolve(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Option<IpAddr> {
+    if trust_proxy && let Some(ip) = forwarded_for(headers) {
+        return Some(ip);
+    }
+    peer.map(|addr| addr.ip())
+}
+
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get("x-forwarded-for")?
+        .to_str()
+        .ok()?
+        .split(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
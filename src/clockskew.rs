@@ -0,0 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Recent `t - received_at` deltas the offset is the median of, per device.
+const WINDOW: usize = 64;
+/// A delta this far (ms) from the current offset is an outlier, or part of a clock step.
+const STEP_THRESHOLD_MS: i64 = 5_000;
+/// Consecutive outliers that are taken as a clock step rather than delivery delays.
+const STEP_CONFIRMATIONS: usize = 3;
+/// `t` values below this are UNIX seconds rather than milliseconds (1e11 ms is 1973, 1e11 s is
+/// far in the future).
+const SECONDS_BELOW: f64 = 1e11;
+
+/// The sample time `t` in UNIX milliseconds, accepting seconds (possibly fractional) as well.
+pub fn sample_time_ms(t: f64) -> Option<f64> {
+    if !t.is_finite() || t <= 0.0 {
+        return None;
+    }
+    Some(if t < SECONDS_BELOW { t * 1000.0 } else { t })
+}
+
+struct DeviceClock {
+    deltas: VecDeque<i64>,
+    // Median of `deltas`
+    offset_ms: i64,
+    // Outliers in a row, kept apart until they confirm a step
+    outliers: Vec<i64>,
+    steps: u64,
+}
+
+impl DeviceClock {
+    fn new() -> Self {
+        Self {
+            deltas: VecDeque::with_capacity(WINDOW),
+            offset_ms: 0,
+            outliers: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    fn add(&mut self, delta: i64) {
+        if !self.deltas.is_empty() && (delta - self.offset_ms).abs() > STEP_THRESHOLD_MS {
+            // A step moves every later delta alike; scattered delays do not add up to one
+            if self.outliers.first().is_some_and(|first| (delta - first).abs() > STEP_THRESHOLD_MS) {
+                self.outliers.clear();
+            }
+            self.outliers.push(delta);
+            if self.outliers.len() < STEP_CONFIRMATIONS {
+                return;
+            }
+            // The clock was set: start over from the deltas since
+            self.deltas = self.outliers.drain(..).collect();
+            self.steps += 1;
+        } else {
+            self.outliers.clear();
+            if self.deltas.len() == WINDOW {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(delta);
+        }
+        self.offset_ms = median(self.deltas.iter().copied());
+    }
+
+    /// Median absolute deviation of the deltas from the offset.
+    fn jitter_ms(&self) -> i64 {
+        median(self.deltas.iter().map(|delta| (delta - self.offset_ms).abs()))
+    }
+}
+
+fn median(values: impl Iterator<Item = i64>) -> i64 {
+    let mut values: Vec<i64> = values.collect();
+    values.sort_unstable();
+    match values.len() {
+        0 => 0,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2,
+    }
+}
+
+/// A device's clock as estimated from its samples.
+pub struct ClockEstimate {
+    /// How far the device's clock is ahead of the server's, in ms (negative when behind)
+    pub offset_ms: i64,
+    /// Median deviation of recent samples from that offset, in ms
+    pub jitter_ms: i64,
+    /// Clock steps detected, each of which restarted the estimate
+    pub steps: u64,
+}
+
+/// Per-userAgent clock offsets, estimated at ingest from each sample's `t` against its
+/// receive time, which also includes the delivery delay.
+#[derive(Default)]
+pub struct ClockOffsets {
+    devices: HashMap<String, DeviceClock>,
+}
+
+impl ClockOffsets {
+    /// Records a sample of `ua` taken at `t_ms` by its clock and received at `received_ms`.
+    pub fn record(&mut self, ua: &str, t_ms: f64, received_ms: u64) {
+        let device = match self.devices.get_mut(ua) {
+            Some(device) => device,
+            None => self.devices.entry(ua.to_string()).or_insert_with(DeviceClock::new),
+        };
+        device.add(t_ms as i64 - received_ms as i64);
+    }
+
+    /// `t_ms` moved to the server's clock, once the device has an estimate.
+    pub fn corrected(&self, ua: &str, t_ms: f64) -> Option<u64> {
+        let device = self.devices.get(ua)?;
+        u64::try_from(t_ms as i64 - device.offset_ms).ok()
+    }
+
+    pub fn estimate(&self, ua: &str) -> Option<ClockEstimate> {
+        self.devices.get(ua).map(|device| ClockEstimate {
+            offset_ms: device.offset_ms,
+            jitter_ms: device.jitter_ms(),
+            steps: device.steps,
+        })
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::shake::ShakeEvent;
+
+/// Groups of events kept, confirmed or not, oldest dropped first.
+const MAX_GROUPS: usize = 1000;
+
+/// Settings of --correlate-devices.
+pub struct CorrelationConfig {
+    /// Distinct devices needed to confirm shaking
+    pub min_devices: usize,
+    /// How far apart their onsets may be
+    pub window: Duration,
+}
+
+/// Shaking that several devices picked up at once, as an earthquake does and a dropped phone
+/// does not.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ConfirmedEvent {
+    pub id: u64,
+    /// The devices, in the order their events started
+    pub devices: Vec<String>,
+    /// Their --sta-lta events, as in `/api/shake-events`
+    pub event_ids: Vec<u64>,
+    /// Earliest onset among the events (UNIX ms, collector clock)
+    pub start_ms: u64,
+    /// When the last of the events ended; null while any is ongoing
+    pub end_ms: Option<u64>,
+    /// Largest of the events' peak accelerations
+    pub peak_acceleration: f64,
+    /// Highest of the events' peak intensities; null without --intensity
+    pub peak_intensity: Option<f64>,
+}
+
+/// What a starting event did to its group.
+pub enum Confirmation {
+    /// The event brought the group to --correlate-devices devices
+    New(ConfirmedEvent),
+    /// The event joined a group confirmed before
+    Joined(ConfirmedEvent),
+}
+
+/// Events whose onsets are within --correlate-window of the first one's.
+struct Group {
+    start_ms: u64,
+    /// As they started, with their last known peaks
+    members: Vec<ShakeEvent>,
+    confirmed_id: Option<u64>,
+    /// The end of the confirmed event has been reported
+    ended: bool,
+}
+
+impl Group {
+    fn devices(&self) -> Vec<String> {
+        let mut devices: Vec<String> = Vec::new();
+        for member in &self.members {
+            if !devices.contains(&member.user_agent) {
+                devices.push(member.user_agent.clone());
+            }
+        }
+        devices
+    }
+
+    /// The group as a confirmed event, taking each member's latest state from `current` when
+    /// it is there.
+    fn summary(&self, id: u64, current: &HashMap<u64, &ShakeEvent>) -> ConfirmedEvent {
+        let members: Vec<&ShakeEvent> = self
+            .members
+            .iter()
+            .map(|member| current.get(&member.id).copied().unwrap_or(member))
+            .collect();
+        let end_ms = members
+            .iter()
+            .map(|member| member.end_ms)
+            .collect::<Option<Vec<u64>>>()
+            .and_then(|ends| ends.into_iter().max());
+        ConfirmedEvent {
+            id,
+            devices: self.devices(),
+            event_ids: members.iter().map(|member| member.id).collect(),
+            start_ms: self.start_ms,
+            end_ms,
+            peak_acceleration: members.iter().map(|member| member.peak_acceleration).fold(0.0, f64::max),
+            peak_intensity: members
+                .iter()
+                .filter_map(|member| member.peak_intensity)
+                .reduce(f64::max),
+        }
+    }
+}
+
+/// Groups --sta-lta events of different devices by onset and confirms a group once
+/// --correlate-devices distinct devices are in it.
+///
+/// An event joins the latest group whose first onset is within --correlate-window of its own,
+/// or starts a new group. Events of a group joining after its confirmation are part of the
+/// confirmed event too.
+pub struct Correlator {
+    config: CorrelationConfig,
+    groups: VecDeque<Group>,
+    /// Event id to the confirmed event it is part of
+    confirmed: HashMap<u64, u64>,
+    next_id: u64,
+}
+
+impl Correlator {
+    pub fn new(config: CorrelationConfig) -> Self {
+        Self {
+            config,
+            groups: VecDeque::new(),
+            confirmed: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Places an event that just started in its group.
+    pub fn start(&mut self, event: &ShakeEvent) -> Option<Confirmation> {
+        let window_ms = self.config.window.as_millis() as u64;
+        let index = self
+            .groups
+            .iter()
+            .rposition(|group| group.start_ms.abs_diff(event.start_ms) <= window_ms)
+            .unwrap_or_else(|| {
+                if self.groups.len() == MAX_GROUPS
+                    && let Some(dropped) = self.groups.pop_front()
+                {
+                    for member in dropped.members {
+                        self.confirmed.remove(&member.id);
+                    }
+                }
+                self.groups.push_back(Group {
+                    start_ms: event.start_ms,
+                    members: Vec::new(),
+                    confirmed_id: None,
+                    ended: false,
+                });
+                self.groups.len() - 1
+            });
+        let group = &mut self.groups[index];
+        group.start_ms = group.start_ms.min(event.start_ms);
+        group.members.push(event.clone());
+        let no_updates = HashMap::new();
+        match group.confirmed_id {
+            Some(id) => {
+                self.confirmed.insert(event.id, id);
+                Some(Confirmation::Joined(group.summary(id, &no_updates)))
+            }
+            None if group.devices().len() >= self.config.min_devices => {
+                let id = self.next_id;
+                self.next_id += 1;
+                group.confirmed_id = Some(id);
+                for member in &group.members {
+                    self.confirmed.insert(member.id, id);
+                }
+                Some(Confirmation::New(group.summary(id, &no_updates)))
+            }
+            None => None,
+        }
+    }
+
+    /// Notes that an event ended. Returns its confirmed event when this was the last of its
+    /// events to end, once per confirmed event.
+    pub fn end(&mut self, event: &ShakeEvent) -> Option<ConfirmedEvent> {
+        let group = self
+            .groups
+            .iter_mut()
+            .rev()
+            .find(|group| group.members.iter().any(|member| member.id == event.id))?;
+        for member in group.members.iter_mut().filter(|member| member.id == event.id) {
+            *member = event.clone();
+        }
+        let id = group.confirmed_id?;
+        if group.ended || group.members.iter().any(|member| member.end_ms.is_none()) {
+            return None;
+        }
+        group.ended = true;
+        Some(group.summary(id, &HashMap::new()))
+    }
+
+    /// The confirmed event `event_id` is part of, if any.
+    pub fn confirmed_id(&self, event_id: u64) -> Option<u64> {
+        self.confirmed.get(&event_id).copied()
+    }
+
+    /// Confirmed events oldest first, ongoing ones included, with the peaks of ongoing events
+    /// taken from `current` (the detector's events).
+    pub fn events(&self, current: &[ShakeEvent]) -> Vec<ConfirmedEvent> {
+        let current: HashMap<u64, &ShakeEvent> = current.iter().map(|event| (event.id, event)).collect();
+        self.groups
+            .iter()
+            .filter_map(|group| Some(group.summary(group.confirmed_id?, &current)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+    use crate::shake::{ShakeDetector, StaLtaConfig, Transition};
+
+    const WINDOW: Duration = Duration::from_secs(2);
+
+    fn event(id: u64, ua: &str, start_ms: u64) -> ShakeEvent {
+        ShakeEvent {
+            id,
+            user_agent: ua.to_string(),
+            start_ms,
+            end_ms: None,
+            peak_acceleration: 0.1,
+            peak_intensity: Some(1.0),
+            confirmed_id: None,
+        }
+    }
+
+    fn ended(mut event: ShakeEvent, end_ms: u64, peak_intensity: f64) -> ShakeEvent {
+        event.end_ms = Some(end_ms);
+        event.peak_intensity = Some(peak_intensity);
+        event
+    }
+
+    fn correlator(min_devices: usize) -> Correlator {
+        Correlator::new(CorrelationConfig { min_devices, window: WINDOW })
+    }
+
+    /// What starting each event did: `none`, `new` or `joined`, with the confirmed event's id.
+    fn start_all(correlator: &mut Correlator, events: &[ShakeEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match correlator.start(event) {
+                None => "none".to_string(),
+                Some(Confirmation::New(confirmed)) => format!("new {}", confirmed.id),
+                Some(Confirmation::Joined(confirmed)) => format!("joined {}", confirmed.id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn confirms_staggered_onsets_within_the_window() {
+        let mut c = correlator(3);
+        let events = [event(1, "a", 10_000), event(2, "b", 10_900), event(3, "c", 11_800)];
+        assert_eq!(start_all(&mut c, &events), ["none", "none", "new 1"]);
+        let confirmed = c.events(&[]);
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].devices, ["a", "b", "c"]);
+        assert_eq!(confirmed[0].start_ms, 10_000);
+        for id in 1..=3 {
+            assert_eq!(c.confirmed_id(id), Some(1));
+        }
+    }
+
+    #[test]
+    fn late_devices_join_only_within_the_window() {
+        let mut c = correlator(3);
+        start_all(&mut c, &[event(1, "a", 10_000), event(2, "b", 10_900), event(3, "c", 11_800)]);
+        let outcomes = start_all(&mut c, &[event(4, "d", 11_900), event(5, "e", 12_500)]);
+        assert_eq!(outcomes, ["joined 1", "none"]);
+        assert_eq!(c.confirmed_id(4), Some(1));
+        assert_eq!(c.confirmed_id(5), None);
+    }
+
+    #[test]
+    fn reports_the_end_once_when_the_last_device_ends() {
+        let mut c = correlator(3);
+        let events = [event(1, "a", 10_000), event(2, "b", 10_900), event(3, "c", 11_800)];
+        start_all(&mut c, &events);
+        assert!(c.end(&ended(events[0].clone(), 20_000, 2.1)).is_none());
+        assert!(c.end(&ended(events[1].clone(), 21_000, 3.4)).is_none());
+        let end = c.end(&ended(events[2].clone(), 20_500, 2.8)).expect("last member ended");
+        assert_eq!(end.end_ms, Some(21_000));
+        assert_eq!(end.peak_intensity, Some(3.4));
+        assert_eq!(end.event_ids, [1, 2, 3]);
+        assert!(c.end(&ended(events[2].clone(), 20_500, 2.8)).is_none());
+    }
+
+    #[test]
+    fn waits_for_a_device_that_joined_late() {
+        let mut c = correlator(2);
+        let events = [event(1, "a", 0), event(2, "b", 500), event(3, "c", 1_500)];
+        start_all(&mut c, &events);
+        assert!(c.end(&ended(events[0].clone(), 5_000, 1.0)).is_none());
+        assert!(c.end(&ended(events[1].clone(), 5_000, 1.0)).is_none());
+        assert!(c.end(&ended(events[2].clone(), 6_000, 1.0)).is_some());
+    }
+
+    #[test]
+    fn ignores_onsets_spread_beyond_the_window() {
+        let mut c = correlator(3);
+        let outcomes = start_all(&mut c, &[event(1, "a", 0), event(2, "b", 1_500), event(3, "c", 3_000)]);
+        assert_eq!(outcomes, ["none", "none", "none"]);
+        assert!(c.events(&[]).is_empty());
+    }
+
+    #[test]
+    fn counts_a_device_once() {
+        let mut c = correlator(2);
+        let outcomes = start_all(&mut c, &[event(1, "a", 0), event(2, "a", 500), event(3, "b", 1_000)]);
+        assert_eq!(outcomes, ["none", "none", "new 1"]);
+        let confirmed = c.events(&[]);
+        assert_eq!(confirmed[0].devices, ["a", "b"]);
+        assert_eq!(confirmed[0].event_ids, [1, 2, 3]);
+    }
+
+    #[test]
+    fn earlier_onset_reported_later_moves_the_start() {
+        let mut c = correlator(2);
+        start_all(&mut c, &[event(1, "a", 5_000), event(2, "b", 4_200)]);
+        assert_eq!(c.events(&[])[0].start_ms, 4_200);
+    }
+
+    #[test]
+    fn takes_ongoing_peaks_from_the_detector() {
+        let mut c = correlator(2);
+        start_all(&mut c, &[event(1, "a", 5_000), event(2, "b", 4_200)]);
+        let current = [ShakeEvent {
+            peak_acceleration: 0.9,
+            ..event(2, "b", 4_200)
+        }];
+        assert_eq!(c.events(&current)[0].peak_acceleration, 0.9);
+    }
+
+    /// Three devices shaken 0.4-0.8 s apart and one shaken alone later, through the STA/LTA
+    /// detector as on ingest.
+    #[test]
+    fn confirms_detector_events_of_staggered_devices() {
+        let mut detector = ShakeDetector::new(StaLtaConfig {
+            sta_window: Duration::from_secs(1),
+            lta_window: Duration::from_secs(30),
+            trigger_ratio: 3.0,
+            detrigger_ratio: 1.5,
+            min_event_duration: Duration::from_secs(2),
+        });
+        let mut c = correlator(3);
+        // Repeatable uniform noise in [-1, 1), from a xorshift
+        let mut state: u32 = 0x2545_f491;
+        let mut noise = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f64 / u32::MAX as f64 * 2.0 - 1.0
+        };
+        // (device, when its shaking starts, ms)
+        let onsets = [("a", 40_000.0), ("b", 40_400.0), ("c", 41_200.0), ("dropped", 90_000.0)];
+        let mut starts = Vec::new();
+        let mut confirmed_ends = Vec::new();
+        // 50 Hz for two minutes, 0.3 g at 5 Hz for 10 s on top of noise
+        for step in 0..6_000 {
+            let t_ms = step as f64 * 20.0;
+            for (ua, onset) in onsets {
+                let mut accel = [noise() * 0.002, noise() * 0.002, 1.0 + noise() * 0.002];
+                if (onset..onset + 10_000.0).contains(&t_ms) {
+                    let phase = TAU * 5.0 * (t_ms - onset) / 1000.0;
+                    accel[0] += 0.3 * phase.sin();
+                    accel[1] += 0.3 * phase.cos();
+                }
+                match detector.record(ua, t_ms, accel, None, t_ms as u64) {
+                    Some(Transition::Start(event)) => {
+                        let outcome = match c.start(&event) {
+                            None => "none",
+                            Some(Confirmation::New(_)) => "new",
+                            Some(Confirmation::Joined(_)) => "joined",
+                        };
+                        starts.push((ua, outcome));
+                    }
+                    Some(Transition::End(event)) => confirmed_ends.extend(c.end(&event)),
+                    None => {}
+                }
+            }
+        }
+        assert_eq!(starts, [("a", "none"), ("b", "none"), ("c", "new"), ("dropped", "none")]);
+        let events = detector.events(None);
+        for event in &events {
+            assert_eq!(c.confirmed_id(event.id).is_none(), event.user_agent == "dropped", "{}", event.user_agent);
+        }
+        assert_eq!(confirmed_ends.len(), 1);
+        assert_eq!(confirmed_ends[0].devices, ["a", "b", "c"]);
+        assert_eq!(Some(confirmed_ends[0].start_ms), events.iter().map(|e| e.start_ms).min());
+    }
+}
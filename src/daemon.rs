@@ -0,0 +1,102 @@
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+/// Write end of the readiness pipe; only set in a daemon whose parent is still waiting.
+static READINESS: Mutex<Option<std::io::PipeWriter>> = Mutex::new(None);
+
+const READY: &str = "ready";
+
+/// Detaches for `--daemonize`: only the daemon returns.
+///
+/// This runs before the tokio runtime starts, so configuration is validated in the daemon. The
+/// starting process waits on a pipe until the daemon has bound the HTTP port (exit 0) or failed,
+/// in which case the daemon's errors and exit code are passed through.
+#[cfg(unix)]
+pub fn detach(args: &crate::cli::Args) {
+    use daemonize::{Daemonize, Outcome};
+
+    let (mut reader, writer) = std::io::pipe().unwrap_or_else(|err| fail_early(err.to_string()));
+    let cwd = std::env::current_dir().unwrap_or_else(|err| fail_early(err.to_string()));
+    // Keep relative paths (--ui-dir, --tls-cert, ...) working after the fork
+    let mut daemon = Daemonize::new().working_directory(cwd);
+    if let Some(path) = &args.pid_file {
+        daemon = daemon.pid_file(path);
+    }
+    if let Some(path) = &args.log_file {
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|err| fail_early(format!("--log-file {}: {}", path.display(), err)));
+        let log_err = log.try_clone().unwrap_or_else(|err| fail_early(err.to_string()));
+        daemon = daemon.stdout(log).stderr(log_err);
+    }
+
+    match daemon.execute() {
+        Outcome::Parent(Ok(_)) => {
+            drop(writer);
+            let mut report = String::new();
+            let _ = reader.read_to_string(&mut report);
+            wait_result(&report, args.log_file.as_deref())
+        }
+        Outcome::Parent(Err(err)) => fail_early(err.to_string()),
+        Outcome::Child(Ok(_)) => {
+            drop(reader);
+            *READINESS.lock().unwrap() = Some(writer);
+        }
+        Outcome::Child(Err(err)) => {
+            drop(reader);
+            *READINESS.lock().unwrap() = Some(writer);
+            failed(1, &format!("Failed to daemonize: {}", err));
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn detach(_args: &crate::cli::Args) {
+    fail_early("--daemonize is only supported on Unix".to_string())
+}
+
+fn fail_early(err: String) -> ! {
+    tracing::error!("failed to daemonize: {}", err);
+    std::process::exit(1);
+}
+
+/// Exits the starting process according to what the daemon reported.
+fn wait_result(report: &str, log_file: Option<&std::path::Path>) -> ! {
+    if report == READY {
+        std::process::exit(0);
+    }
+    // "<exit code>\n<message>", or nothing when the daemon died without reporting
+    match report.split_once('\n').and_then(|(code, msg)| Some((code.parse().ok()?, msg))) {
+        Some((code, msg)) => {
+            for line in msg.lines() {
+                tracing::error!("{}", line);
+            }
+            std::process::exit(code);
+        }
+        None => {
+            match log_file {
+                Some(path) => tracing::error!("the daemon exited before serving, see {}", path.display()),
+                None => tracing::error!("the daemon exited before serving; use --log-file to see why"),
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Tells the waiting parent that the HTTP port is bound. No-op unless daemonized.
+pub fn ready() {
+    if let Some(mut pipe) = READINESS.lock().unwrap().take() {
+        let _ = pipe.write_all(READY.as_bytes());
+    }
+}
+
+/// Passes `message` and the exit `code` the daemon is about to exit with to the waiting parent.
+/// No-op unless daemonized.
+pub fn failed(code: i32, message: &str) {
+    if let Some(mut pipe) = READINESS.lock().unwrap().take() {
+        let _ = write!(pipe, "{}\n{}\n", code, message);
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Frame encoding a /ws client asked for with `?format=`.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    /// Text frames carrying the message as received (plus `_seq`)
+    #[default]
+    Json,
+    /// Binary frames with the message encoded as MessagePack
+    Msgpack,
+    /// Binary frames with the message encoded as CBOR
+    Cbor,
+}
+
+/// Binary encodings of one live message, each computed at most once however many clients
+/// want it.
+#[derive(Default)]
+pub struct Encoded {
+    msgpack: OnceLock<Vec<u8>>,
+    cbor: OnceLock<Vec<u8>>,
+}
+
+impl Encoded {
+    /// `text` encoded as `format`; `None` for JSON, which is sent as the text itself.
+    pub fn get(&self, text: &str, format: WireFormat) -> Option<Vec<u8>> {
+        let cell = match format {
+            WireFormat::Json => return None,
+            WireFormat::Msgpack => &self.msgpack,
+            WireFormat::Cbor => &self.cbor,
+        };
+        Some(cell.get_or_init(|| encode(text, format)).clone())
+    }
+}
+
+/// Encodes a message's JSON text as `format`. Text that is not JSON (e.g. the placeholder for
+/// an upstream binary frame) is encoded as a string.
+pub fn encode(text: &str, format: WireFormat) -> Vec<u8> {
+    let value = serde_json::from_str::<Value>(text).unwrap_or_else(|_| Value::String(text.to_string()));
+    match format {
+        WireFormat::Json => value.to_string().into_bytes(),
+        // Values are plain maps, arrays and scalars, which both encoders always accept
+        WireFormat::Msgpack => rmp_serde::to_vec(&value).expect("JSON value encodes as MessagePack"),
+        WireFormat::Cbor => {
+            let mut out = Vec::new();
+            ciborium::into_writer(&value, &mut out).expect("JSON value encodes as CBOR");
+            out
+        }
+    }
+}
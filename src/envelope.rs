@@ -0,0 +1,64 @@
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::message_format::MessageFormat;
+
+/// A message together with what the collector knows about it, sent instead of the bare message
+/// by `/ws?envelope=1` and `/api/messages?envelope=1`.
+#[derive(Serialize, ToSchema)]
+pub struct Envelope {
+    /// Ingest-order id, incremented for every received message (unlike `_seq`), so a jump means
+    /// messages were missed or filtered out
+    pub seq: u64,
+    /// When the collector received the message, RFC 3339 in UTC with milliseconds
+    pub received_at: String,
+    /// Host of the upstream the message came from, as in `/api/sources`
+    pub source: String,
+    /// Encoding detected at ingest; only `json` payloads are embedded as JSON
+    pub format: MessageFormat,
+    /// The payload's `t` in UNIX milliseconds on the collector's clock, corrected by the
+    /// device's estimated clock offset at ingest (see `/api/devices`); `null` unless the payload
+    /// is a single sample with a `userAgent` and `t`
+    pub t_corrected: Option<u64>,
+    /// Under --intensity, the JMA instrumental seismic intensity of the payload's device just
+    /// after this sample (see `/api/intensity`); left out otherwise, and for payloads that are
+    /// not a single sample
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intensity: Option<f64>,
+    /// The message: embedded as JSON when it parses, otherwise as a string
+    #[schema(value_type = Object)]
+    pub payload: Value,
+}
+
+impl Envelope {
+    pub fn new(
+        seq: u64,
+        received_at_ms: u64,
+        source: &str,
+        format: MessageFormat,
+        t_corrected: Option<u64>,
+        intensity: Option<f64>,
+        text: &str,
+    ) -> Self {
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_millis(received_at_ms);
+        Self {
+            seq,
+            received_at: humantime::format_rfc3339_millis(received_at).to_string(),
+            source: source.to_string(),
+            format,
+            t_corrected,
+            intensity,
+            payload: match format {
+                MessageFormat::Json => serde_json::from_str(text).unwrap_or_else(|_| Value::from(text)),
+                _ => Value::from(text),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("envelope serializes")
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Sample times kept per device; the oldest are dropped first.
+const MAX_SAMPLES_PER_DEVICE: usize = 200_000;
+
+/// An interval in which a device sent no samples for longer than the threshold.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Gap {
+    /// Time of the last sample before the gap, in UNIX milliseconds
+    pub start_ms: u64,
+    /// Time of the first sample after the gap, in UNIX milliseconds
+    pub end_ms: u64,
+    pub duration_ms: u64,
+}
+
+/// Per-device timeline of sample times, used for gap detection.
+#[derive(Default)]
+pub struct Timelines {
+    devices: HashMap<String, VecDeque<u64>>,
+}
+
+impl Timelines {
+    pub fn record(&mut self, ua: &str, t_ms: u64) {
+        let samples = match self.devices.get_mut(ua) {
+            Some(samples) => samples,
+            None => self.devices.entry(ua.to_string()).or_default(),
+        };
+        if samples.len() == MAX_SAMPLES_PER_DEVICE {
+            samples.pop_front();
+        }
+        samples.push_back(t_ms);
+    }
+
+    /// Finds spacings longer than `min_gap_ms` between consecutive samples in `[since, until]`.
+    ///
+    /// Samples are sorted first, so out-of-order delivery does not produce spurious gaps.
+    /// Devices without any gap are omitted.
+    pub fn gaps(
+        &self,
+        ua: Option<&str>,
+        since: Option<u64>,
+        until: Option<u64>,
+        min_gap_ms: u64,
+    ) -> BTreeMap<String, Vec<Gap>> {
+        let mut result = BTreeMap::new();
+        for (device, samples) in &self.devices {
+            if ua.is_some_and(|ua| ua != device) {
+                continue;
+            }
+            let mut times: Vec<u64> = samples
+                .iter()
+                .copied()
+                .filter(|&t| since.is_none_or(|s| t >= s) && until.is_none_or(|u| t <= u))
+                .collect();
+            times.sort_unstable();
+            let gaps: Vec<Gap> = times
+                .windows(2)
+                .filter(|pair| pair[1] - pair[0] > min_gap_ms)
+                .map(|pair| Gap {
+                    start_ms: pair[0],
+                    end_ms: pair[1],
+                    duration_ms: pair[1] - pair[0],
+                })
+                .collect();
+            if !gaps.is_empty() {
+                result.insert(device.clone(), gaps);
+            }
+        }
+        result
+    }
+}
+
+/// Parses a duration such as `5s`, `500ms`, `2m` or `1h` into milliseconds.
+///
+/// A bare number is taken as milliseconds.
+pub fn parse_duration_ms(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+    let scale = match unit {
+        "" | "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(format!("invalid duration unit in `{}` (use ms, s, m or h)", s)),
+    };
+    value
+        .checked_mul(scale)
+        .ok_or_else(|| format!("duration `{}` is too large", s))
+}
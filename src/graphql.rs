@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
+use async_graphql::{ComplexObject, Context, EmptyMutation, Json, Object, Schema, SimpleObject, Subscription};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::extract::ws::{close_code, CloseFrame, Message as WsMessage, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Extension;
+use futures_util::{SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::{
+    cors, record_lag, stats_response, url_host, AppState, StatsResponse, UpstreamState, DEFAULT_LIST_LIMIT,
+    LIMIT_CONSTRAINT, WS_CLOSE_TIMEOUT,
+};
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+pub fn schema(state: AppState) -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .data(state)
+        .finish()
+}
+
+/// A received message. Timestamps are `Float`s because milliseconds since the epoch do not fit
+/// GraphQL's 32-bit `Int`.
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Message {
+    /// When the collector received the message, in milliseconds since the Unix epoch
+    received_at: f64,
+    /// Host of the upstream the message came from, as in `/api/sources`
+    source: String,
+    /// The message as received (with `_seq` under --inject-seq)
+    payload: String,
+}
+
+#[ComplexObject]
+impl Message {
+    /// `payload` parsed as JSON; null when it is not JSON. Only parsed when selected.
+    async fn parsed(&self) -> Option<Json<Value>> {
+        serde_json::from_str(&self.payload).ok().map(Json)
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Buffered messages, oldest first: the newest `limit` (default 500) of those received in
+    /// `[since_ms, until_ms]` from `source`.
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        #[graphql(name = "since_ms")] since_ms: Option<f64>,
+        #[graphql(name = "until_ms")] until_ms: Option<f64>,
+        source: Option<String>,
+    ) -> async_graphql::Result<Vec<Message>> {
+        let state = ctx.data_unchecked::<AppState>();
+        let limit = match limit {
+            Some(n) => usize::try_from(n).map_err(|_| LIMIT_CONSTRAINT)?,
+            None => DEFAULT_LIST_LIMIT,
+        };
+        let label = url_host(state.config.url());
+        // Only one upstream, so a source filter either keeps everything or nothing
+        if source.is_some_and(|source| source != label) {
+            return Ok(Vec::new());
+        }
+        let buf = state.buffer.read().await;
+        let mut messages: Vec<Message> = buf
+            .iter()
+            .rev()
+            .filter(|m| until_ms.is_none_or(|until| m.received_at_ms as f64 <= until))
+            .filter(|m| since_ms.is_none_or(|since| m.received_at_ms as f64 >= since))
+            .take(limit)
+            .map(|m| Message {
+                received_at: m.received_at_ms as f64,
+                source: label.to_string(),
+                payload: m.text.clone(),
+            })
+            .collect();
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// Same as `GET /api/stats`.
+    async fn stats(&self, ctx: &Context<'_>) -> StatsResponse {
+        stats_response(ctx.data_unchecked::<AppState>()).await
+    }
+
+    /// Same as `GET /api/status`.
+    async fn upstream_status(&self, ctx: &Context<'_>) -> UpstreamState {
+        ctx.data_unchecked::<AppState>().upstream.read().await.clone()
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Messages as they are received. A subscriber that falls too far behind skips what it
+    /// missed; the stream completes when the server shuts down.
+    async fn messages(&self, ctx: &Context<'_>) -> impl Stream<Item = Message> + use<> {
+        let state = ctx.data_unchecked::<AppState>();
+        let source: Arc<str> = url_host(state.config.url()).into();
+        let rx = state.tx.subscribe();
+        let lag_dropped = state.lag_dropped.clone();
+        futures_util::stream::unfold(rx, move |mut rx| {
+            let source = source.clone();
+            let lag_dropped = lag_dropped.clone();
+            async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(live) => {
+                            let message = Message {
+                                received_at: live.received_at_ms as f64,
+                                source: source.to_string(),
+                                payload: live.text,
+                            };
+                            return Some((message, rx));
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            record_lag(&lag_dropped, "GraphQL subscription", missed);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+        .take_until(state.shutdown.clone().cancelled_owned())
+    }
+}
+
+/// Runs a GraphQL query.
+///
+/// The schema is printed at `GET /api/graphql/schema`; subscriptions are served over
+/// WebSocket at `/api/graphql/ws`.
+#[utoipa::path(
+    post,
+    path = "/api/graphql",
+    request_body(
+        content = Object,
+        description = "GraphQL request: `query`, optional `variables` and `operationName`",
+        content_type = "application/json",
+    ),
+    responses(
+        (status = 200, description = "GraphQL response with `data` and/or `errors`", body = Object),
+        (status = 400, description = "Not a GraphQL request", body = ErrorBody),
+    )
+)]
+pub async fn execute(Extension(schema): Extension<ApiSchema>, req: GraphQLRequest) -> GraphQLResponse {
+    schema.execute(req.into_inner()).await.into()
+}
+
+/// The schema in GraphQL SDL.
+#[utoipa::path(
+    get,
+    path = "/api/graphql/schema",
+    responses((status = 200, description = "GraphQL schema", content_type = "text/plain")),
+)]
+pub async fn sdl(Extension(schema): Extension<ApiSchema>) -> String {
+    schema.sdl()
+}
+
+/// GraphQL subscriptions over WebSocket (`graphql-transport-ws` or the older `graphql-ws`
+/// subprotocol).
+///
+/// Counts against `--max-ws-clients` like `/ws`.
+#[utoipa::path(
+    get,
+    path = "/api/graphql/ws",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 403, description = "Origin not allowed by --cors-origin", body = ErrorBody),
+        (status = 503, description = "--max-ws-clients already connected", body = ErrorBody),
+    )
+)]
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Extension(schema): Extension<ApiSchema>,
+    headers: HeaderMap,
+    protocol: GraphQLProtocol,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    if !cors::ws_origin_allowed(state.config.ws_origins(), &headers) {
+        return Err(ApiError::Forbidden("origin not allowed".to_string()));
+    }
+    let slot = state.request_limits.ws_slot()?;
+    let sessions = state.ws_sessions.clone();
+    Ok(ws.protocols(ALL_WEBSOCKET_PROTOCOLS).on_upgrade(move |socket| {
+        sessions.track_future(async move {
+            let _slot = slot;
+            let (mut sink, mut stream) = socket.split();
+            let shutting_down = {
+                let served = GraphQLWebSocket::new_with_pair(&mut sink, &mut stream, schema, protocol).serve();
+                tokio::select! {
+                    _ = served => false,
+                    _ = state.shutdown.cancelled() => true,
+                }
+            };
+            if shutting_down {
+                let close = WsMessage::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "server shutting down".into(),
+                }));
+                let _ = tokio::time::timeout(WS_CLOSE_TIMEOUT, sink.send(close)).await;
+            }
+        })
+    }))
+}
@@ -0,0 +1,150 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use futures_util::{Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::cli::Args;
+use crate::client_ip;
+use crate::{record_lag, url_host, AppState, UaFilter, DEFAULT_LIST_LIMIT};
+
+pub mod proto {
+    tonic::include_proto!("yurecollect.v1");
+}
+
+use proto::message_event::Event;
+use proto::yure_collect_server::{YureCollect, YureCollectServer};
+use proto::{Lagged, ListRequest, ListResponse, Message, MessageEvent, StreamRequest};
+
+/// Where `--grpc-port` listens: that port on the address of the first `--bind`.
+pub fn listen_addr(args: &Args) -> Option<SocketAddr> {
+    let port = args.grpc_port?;
+    let ip = args.bind.first().map_or([0, 0, 0, 0].into(), |addr| addr.ip());
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Serves the gRPC API on `listener` until shutdown, when open streams are ended.
+// tonic's interceptors return a bare `Status`
+#[allow(clippy::result_large_err)]
+pub async fn serve(listener: std::net::TcpListener, state: AppState) -> std::io::Result<()> {
+    let incoming = TcpIncoming::from_listener(tokio::net::TcpListener::from_std(listener)?, true, None)
+        .map_err(std::io::Error::other)?;
+    let shutdown = state.shutdown.clone();
+    let auth_state = state.clone();
+    let service = YureCollectServer::with_interceptor(Service { state }, move |req| authorize(&auth_state, req));
+    Server::builder()
+        .add_service(service)
+        .serve_with_incoming_shutdown(incoming, shutdown.cancelled_owned())
+        .await
+        .map_err(std::io::Error::other)
+}
+
+/// The --allow-ip/--block-ip lists and the credentials of `require_auth`, taken from the
+/// request metadata.
+#[allow(clippy::result_large_err)]
+fn authorize(state: &AppState, mut req: Request<()>) -> Result<Request<()>, Status> {
+    let headers = req.metadata().clone().into_headers();
+    let client = client_ip::resolve(&headers, req.remote_addr(), state.config.trust_proxy);
+    if !state.ip_filter.admits(client) {
+        return Err(Status::permission_denied("client address not allowed"));
+    }
+    if state.api_tokens.is_empty() && state.basic_auth.is_none() && state.jwt.is_none() {
+        return Ok(req);
+    }
+    let ip = req.remote_addr().map(|addr| addr.ip());
+    let failures = state.rate_limits.auth_failures.as_ref();
+    if let (Some(limiter), Some(ip)) = (failures, ip)
+        && limiter.retry_after(ip).is_some()
+    {
+        return Err(Status::resource_exhausted("too many failed authentication attempts"));
+    }
+    let token_ok = !state.api_tokens.is_empty() && state.api_tokens.authorize(&headers, &Default::default());
+    let basic_ok = state.basic_auth.as_ref().is_some_and(|b| b.authorize(&headers));
+    if token_ok || basic_ok {
+        return Ok(req);
+    }
+    if let Some(claims) = state.jwt.as_ref().and_then(|jwt| jwt.authorize(&headers, &Default::default())) {
+        req.extensions_mut().insert(claims);
+        return Ok(req);
+    }
+
+    let source = ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    tracing::warn!("rejected unauthenticated gRPC request from {}", source);
+    if let (Some(limiter), Some(ip)) = (failures, ip) {
+        let _ = limiter.check(ip);
+    }
+    Err(Status::unauthenticated("missing or invalid credentials"))
+}
+
+struct Service {
+    state: AppState,
+}
+
+#[tonic::async_trait]
+impl YureCollect for Service {
+    type StreamMessagesStream = Pin<Box<dyn Stream<Item = Result<MessageEvent, Status>> + Send>>;
+
+    async fn stream_messages(
+        &self,
+        request: Request<StreamRequest>,
+    ) -> Result<Response<Self::StreamMessagesStream>, Status> {
+        let request = request.into_inner();
+        let filter = UaFilter {
+            agents: request.user_agents,
+            include_unknown: request.include_unknown,
+        };
+        let source = url_host(self.state.config.url()).to_string();
+        let rx = self.state.tx.subscribe();
+        let lag_dropped = self.state.lag_dropped.clone();
+        let events = futures_util::stream::unfold((rx, filter, source), move |(mut rx, filter, source)| {
+            let lag_dropped = lag_dropped.clone();
+            async move {
+                let event = loop {
+                    match rx.recv().await {
+                        Ok(msg) if !filter.matches(&msg.user_agents) => continue,
+                        Ok(msg) => {
+                            break Event::Message(Message {
+                                seq: msg.id,
+                                received_at_ms: msg.received_at_ms,
+                                source: source.clone(),
+                                payload: msg.text,
+                            })
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            record_lag(&lag_dropped, "gRPC stream", missed);
+                            break Event::Lagged(Lagged { missed });
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                };
+                Some((Ok(MessageEvent { event: Some(event) }), (rx, filter, source)))
+            }
+        });
+        let events = events.take_until(self.state.shutdown.clone().cancelled_owned());
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn list_messages(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        let limit = request.into_inner().limit.map_or(DEFAULT_LIST_LIMIT, |n| n as usize);
+        let source = url_host(self.state.config.url());
+        let buf = self.state.buffer.read().await;
+        let total = buf.len();
+        let messages = buf
+            .iter()
+            .skip(total.saturating_sub(limit))
+            .map(|m| Message {
+                seq: m.id,
+                received_at_ms: m.received_at_ms,
+                source: source.to_string(),
+                payload: m.text.clone(),
+            })
+            .collect();
+        Ok(Response::new(ListResponse {
+            messages,
+            total: total as u64,
+        }))
+    }
+}
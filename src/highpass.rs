@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+struct DeviceFilter {
+    last_t_ms: f64,
+    last_input: [f64; 3],
+    output: [f64; 3],
+}
+
+impl DeviceFilter {
+    // Starting from the sample itself as the previous input, the offset is gone from the first
+    // output on rather than decaying away
+    fn new(t_ms: f64, accel: [f64; 3]) -> Self {
+        Self {
+            last_t_ms: t_ms,
+            last_input: accel,
+            output: [0.0; 3],
+        }
+    }
+}
+
+/// Per-userAgent one-pole high-pass filter over `x`/`y`/`z` (--highpass), which takes out
+/// gravity and other constant offsets.
+///
+/// Each step uses the time since the previous sample, so irregular sampling shifts the cutoff
+/// no more than it does the samples. A gap longer than `max_gap`, or a `t` going backwards,
+/// restarts the device's filter, so the jump in offset across it (the phone was picked up and
+/// put down differently) does not ring through the output.
+pub struct HighPass {
+    // Time constant RC of the cutoff, in ms
+    rc_ms: f64,
+    max_gap_ms: f64,
+    devices: HashMap<String, DeviceFilter>,
+}
+
+impl HighPass {
+    pub fn new(cutoff_hz: f64, max_gap: Duration) -> Self {
+        Self {
+            rc_ms: 1000.0 / (TAU * cutoff_hz),
+            max_gap_ms: max_gap.as_secs_f64() * 1000.0,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// The filtered acceleration of a sample of `ua` taken at `t_ms`.
+    pub fn filter(&mut self, ua: &str, t_ms: f64, accel: [f64; 3]) -> [f64; 3] {
+        let Some(device) = self.devices.get_mut(ua) else {
+            self.devices.insert(ua.to_string(), DeviceFilter::new(t_ms, accel));
+            return [0.0; 3];
+        };
+        let dt = t_ms - device.last_t_ms;
+        if dt < 0.0 || dt > self.max_gap_ms {
+            *device = DeviceFilter::new(t_ms, accel);
+            return [0.0; 3];
+        }
+        let alpha = self.rc_ms / (self.rc_ms + dt);
+        for (axis, output) in device.output.iter_mut().enumerate() {
+            *output = alpha * (*output + accel[axis] - device.last_input[axis]);
+        }
+        device.last_t_ms = t_ms;
+        device.last_input = accel;
+        device.output
+    }
+}
+
+/// Checks against a synthetic constant-plus-sine signal. A one-pole high-pass with cutoff `fc`
+/// passes a sine at `f` with gain `f / sqrt(f² + fc²)` and removes a constant entirely.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UA: &str = "synthetic";
+    const T0: f64 = 1_768_000_000_000.0;
+    const CUTOFF_HZ: f64 = 0.1;
+    const GRAVITY: f64 = 9.80665;
+
+    /// 100 Hz samples of `offset + amplitude·sin(2π f t)` on z from `start_ms`, for `secs`,
+    /// through the filter.
+    fn run(filter: &mut HighPass, start_ms: f64, secs: f64, offset: f64, amplitude: f64, f: f64) -> Vec<f64> {
+        (0..(secs * 100.0) as usize)
+            .map(|i| {
+                let t = i as f64 * 10.0;
+                let z = offset + amplitude * (TAU * f * t / 1000.0).sin();
+                filter.filter(UA, start_ms + t, [0.0, 0.0, z])[2]
+            })
+            .collect()
+    }
+
+    fn peak(values: &[f64]) -> f64 {
+        values.iter().fold(0.0_f64, |peak, v| peak.max(v.abs()))
+    }
+
+    fn gain(f: f64) -> f64 {
+        f / (f * f + CUTOFF_HZ * CUTOFF_HZ).sqrt()
+    }
+
+    /// Gravity plus a 2 Hz sine: after the first seconds only the sine is left.
+    #[test]
+    fn removes_gravity_and_passes_motion() {
+        let (amplitude, f) = (0.5, 2.0);
+        let mut filter = HighPass::new(CUTOFF_HZ, Duration::from_secs(1));
+        let output = run(&mut filter, T0, 60.0, GRAVITY, amplitude, f);
+        let settled = &output[2_000..];
+        let dc = settled.iter().sum::<f64>() / settled.len() as f64;
+        assert!(dc.abs() < 0.005, "mean {:.5} of an offset of {}", dc, GRAVITY);
+        let want = amplitude * gain(f);
+        assert!((peak(settled) - want).abs() < 0.01, "peak {:.4}, expected {:.4}", peak(settled), want);
+        // The offset never shows up, not even at the start
+        assert!(peak(&output[..10]) < amplitude, "{:.4} in the first 0.1 s", peak(&output[..10]));
+    }
+
+    /// Below the cutoff motion is attenuated: a 0.01 Hz sine keeps about a tenth.
+    #[test]
+    fn attenuates_below_the_cutoff() {
+        let amplitude = 0.5;
+        let mut filter = HighPass::new(CUTOFF_HZ, Duration::from_secs(1));
+        let output = run(&mut filter, T0, 300.0, GRAVITY, amplitude, 0.01);
+        let want = amplitude * gain(0.01);
+        let peak = peak(&output[10_000..]);
+        assert!((peak - want).abs() < 0.01, "peak {:.4}, expected {:.4}", peak, want);
+    }
+
+    /// After a gap the phone rests on another axis: restarted, so the jump does not ring.
+    #[test]
+    fn restarts_after_a_gap() {
+        let mut filter = HighPass::new(CUTOFF_HZ, Duration::from_secs(1));
+        run(&mut filter, T0, 30.0, GRAVITY, 0.0, 2.0);
+        let after = run(&mut filter, T0 + 35_000.0, 5.0, -GRAVITY, 0.0, 2.0);
+        assert!(peak(&after) < 1e-9, "at most {:e}", peak(&after));
+    }
+
+    /// Without the restart, the same jump of 2 g goes through the filter as a step.
+    #[test]
+    fn carries_a_jump_within_the_max_gap() {
+        let mut filter = HighPass::new(CUTOFF_HZ, Duration::from_secs(10));
+        run(&mut filter, T0, 30.0, GRAVITY, 0.0, 2.0);
+        let after = run(&mut filter, T0 + 35_000.0, 5.0, -GRAVITY, 0.0, 2.0);
+        assert!(after[0] < -1.0, "{:.3}", after[0]);
+    }
+}
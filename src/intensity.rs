@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Rate the samples are resampled to before filtering.
+pub const RATE_HZ: f64 = 100.0;
+const STEP_MS: f64 = 1000.0 / RATE_HZ;
+/// Resampled points the intensity is computed over (40.96 s).
+pub const WINDOW: usize = 4096;
+/// Points at either end of the window left out of the 0.3 s rule (2 s): the window cuts the
+/// motion off there, which the filter turns into ringing that overstates it by up to 0.5.
+pub const EDGE: usize = 200;
+/// Points needed before a first intensity is reported (5 s).
+const MIN_SAMPLES: usize = 500;
+/// Points between two computations (1 s), since each costs six FFTs.
+const UPDATE_EVERY: usize = 100;
+/// The acceleration must be reached for 0.3 s in total, i.e. by this many points.
+const EXCEEDANCE_SAMPLES: usize = 30;
+/// Filtered acceleration below this is rounding noise, not motion.
+const MIN_ACCEL_GAL: f64 = 1e-6;
+/// A longer silence is not interpolated across; the window starts over after it.
+const MAX_GAP_MS: f64 = 1_000.0;
+/// How far back `peak_intensity` looks.
+const PEAK_WINDOW_MS: u64 = 10 * 60 * 1000;
+
+/// Gain of the JMA instrumental intensity filter at `f` Hz: the period-effect, high-cut and
+/// low-cut filters of the JMA specification combined.
+pub fn filter_gain(f: f64) -> f64 {
+    if f <= 0.0 {
+        return 0.0;
+    }
+    let period_effect = (1.0 / f).sqrt();
+    let x = f / 10.0;
+    let x2 = x * x;
+    let high_cut = 1.0
+        / (1.0
+            + 0.694 * x2
+            + 0.241 * x2.powi(2)
+            + 0.0557 * x2.powi(3)
+            + 0.009664 * x2.powi(4)
+            + 0.00134 * x2.powi(5)
+            + 0.000155 * x2.powi(6))
+        .sqrt();
+    let low_cut = (1.0 - (-(f / 0.5).powi(3)).exp()).sqrt();
+    period_effect * high_cut * low_cut
+}
+
+/// Rounds an intensity the way the JMA reports it: to two decimals, then truncated to one.
+pub fn jma_round(intensity: f64) -> f64 {
+    ((intensity * 100.0).round() / 10.0).floor() / 10.0
+}
+
+/// The shindo class of a rounded intensity.
+pub fn shindo_class(intensity: f64) -> &'static str {
+    const CLASSES: [(f64, &str); 9] = [
+        (0.5, "0"),
+        (1.5, "1"),
+        (2.5, "2"),
+        (3.5, "3"),
+        (4.5, "4"),
+        (5.0, "5-"),
+        (5.5, "5+"),
+        (6.0, "6-"),
+        (6.5, "6+"),
+    ];
+    CLASSES
+        .iter()
+        .find(|(below, _)| intensity < *below)
+        .map_or("7", |(_, class)| class)
+}
+
+/// Computes the instrumental intensity of three-axis acceleration sampled at `RATE_HZ`, in the
+/// frequency domain as the JMA specification does, reusing its plans and buffers.
+pub struct JmaFilter {
+    forward: Arc<dyn RealToComplex<f64>>,
+    inverse: Arc<dyn ComplexToReal<f64>>,
+    // `filter_gain` of every FFT bin
+    gains: Vec<f64>,
+    signal: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    composite: Vec<f64>,
+}
+
+impl JmaFilter {
+    /// A filter for up to `WINDOW` points, zero-padded to twice that so the filter's response
+    /// does not wrap around from one end of the window to the other.
+    pub fn new() -> Self {
+        let len = 2 * WINDOW;
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(len);
+        let inverse = planner.plan_fft_inverse(len);
+        let gains = (0..len / 2 + 1)
+            .map(|bin| filter_gain(bin as f64 * RATE_HZ / len as f64))
+            .collect();
+        Self {
+            signal: forward.make_input_vec(),
+            spectrum: forward.make_output_vec(),
+            forward,
+            inverse,
+            gains,
+            composite: Vec::with_capacity(WINDOW),
+        }
+    }
+
+    /// The unrounded intensity of `samples` (x, y, z in gal, oldest first, at most `WINDOW`):
+    /// each axis filtered, the three composed into one vector, and `2 log10(a) + 0.94` of the
+    /// acceleration `a` reached for 0.3 s in total, not counting the `EDGE` points at either
+    /// end. `None` without enough samples, or without motion.
+    pub fn intensity(&mut self, samples: &[[f64; 3]]) -> Option<f64> {
+        let n = samples.len().min(WINDOW);
+        if n < 2 * EDGE + EXCEEDANCE_SAMPLES {
+            return None;
+        }
+        let samples = &samples[samples.len() - n..];
+        let len = self.signal.len();
+        self.composite.clear();
+        self.composite.resize(n, 0.0);
+        for axis in 0..3 {
+            // The offset (gravity, sensor bias) would only leak through the low-cut filter's edges
+            let mean = samples.iter().map(|s| s[axis]).sum::<f64>() / n as f64;
+            for (point, sample) in self.signal.iter_mut().zip(samples) {
+                *point = sample[axis] - mean;
+            }
+            self.signal[n..].fill(0.0);
+            self.forward
+                .process(&mut self.signal, &mut self.spectrum)
+                .expect("buffers sized by the plan");
+            for (bin, gain) in self.spectrum.iter_mut().zip(&self.gains) {
+                *bin *= gain;
+            }
+            // Zero for real input anyway; the inverse transform rejects rounding noise there
+            self.spectrum[0].im = 0.0;
+            self.spectrum[len / 2].im = 0.0;
+            self.inverse
+                .process(&mut self.spectrum, &mut self.signal)
+                .expect("buffers sized by the plan");
+            for (sum, point) in self.composite.iter_mut().zip(&self.signal) {
+                let filtered = point / len as f64;
+                *sum += filtered * filtered;
+            }
+        }
+        let (_, reached, _) = self.composite[EDGE..n - EDGE]
+            .select_nth_unstable_by(EXCEEDANCE_SAMPLES - 1, |a, b| b.total_cmp(a));
+        let a = reached.sqrt();
+        (a > MIN_ACCEL_GAL).then(|| 2.0 * a.log10() + 0.94)
+    }
+}
+
+impl Default for JmaFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A device's intensity as reported by `/api/intensity`.
+#[derive(Serialize, ToSchema)]
+pub struct IntensityReading {
+    /// JMA instrumental seismic intensity of the last 40.96 s of motion but the newest 2 s,
+    /// rounded as the JMA does; null until 5 s of samples have arrived, or while the device
+    /// reports no motion
+    pub intensity: Option<f64>,
+    /// Shindo class of `intensity`: `0` to `4`, `5-`, `5+`, `6-`, `6+` or `7`
+    pub shindo: Option<&'static str>,
+    /// When `intensity` was last computed (UNIX ms); it is recomputed every second of samples
+    pub updated_ms: Option<u64>,
+    /// Highest `intensity` of the last 10 minutes
+    pub peak_intensity: Option<f64>,
+    pub peak_shindo: Option<&'static str>,
+    /// When `peak_intensity` was computed (UNIX ms)
+    pub peak_at_ms: Option<u64>,
+}
+
+struct DeviceMeter {
+    // Last sample, `(t_ms, acceleration)`, that the next points are interpolated from
+    last: Option<(f64, [f64; 3])>,
+    // Device time of the next resampled point
+    next_point_ms: f64,
+    window: VecDeque<[f64; 3]>,
+    // Points added since the last computation
+    pending: usize,
+    current: Option<f64>,
+    updated_ms: Option<u64>,
+    // `(computed_ms, intensity)` of the last `PEAK_WINDOW_MS`
+    history: VecDeque<(u64, f64)>,
+}
+
+impl DeviceMeter {
+    fn new() -> Self {
+        Self {
+            last: None,
+            next_point_ms: 0.0,
+            window: VecDeque::with_capacity(WINDOW),
+            pending: 0,
+            current: None,
+            updated_ms: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Resamples onto the `RATE_HZ` grid by linear interpolation, so irregular sample spacing
+    /// (jitter, batching, a different native rate) does not skew the filter.
+    fn add(&mut self, t_ms: f64, gal: [f64; 3]) {
+        match self.last {
+            // Repeated or out-of-order `t`: the grid only moves forward
+            Some((last_t, _)) if t_ms <= last_t => return,
+            Some((last_t, last_gal)) if t_ms - last_t <= MAX_GAP_MS => {
+                while self.next_point_ms <= t_ms {
+                    let frac = (self.next_point_ms - last_t) / (t_ms - last_t);
+                    self.push(std::array::from_fn(|axis| {
+                        last_gal[axis] + (gal[axis] - last_gal[axis]) * frac
+                    }));
+                    self.next_point_ms += STEP_MS;
+                }
+            }
+            _ => {
+                self.window.clear();
+                self.pending = 0;
+                self.push(gal);
+                self.next_point_ms = t_ms + STEP_MS;
+            }
+        }
+        self.last = Some((t_ms, gal));
+    }
+
+    fn push(&mut self, point: [f64; 3]) {
+        if self.window.len() == WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(point);
+        self.pending += 1;
+    }
+
+    fn peak(&self) -> Option<(u64, f64)> {
+        self.history.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    fn prune(&mut self, now_ms: u64) {
+        while self
+            .history
+            .front()
+            .is_some_and(|(at, _)| now_ms.saturating_sub(*at) > PEAK_WINDOW_MS)
+        {
+            self.history.pop_front();
+        }
+    }
+}
+
+/// Per-userAgent JMA instrumental intensity under --intensity, kept up to date at ingest.
+#[derive(Default)]
+pub struct Intensities {
+    filter: JmaFilter,
+    devices: HashMap<String, DeviceMeter>,
+}
+
+impl Intensities {
+    /// Records a sample of `ua` taken at `t_ms` (device clock) with acceleration `gal`,
+    /// recomputing the device's intensity once another second of points has accumulated.
+    pub fn record(&mut self, ua: &str, t_ms: f64, gal: [f64; 3], now_ms: u64) {
+        let device = match self.devices.get_mut(ua) {
+            Some(device) => device,
+            None => self.devices.entry(ua.to_string()).or_insert_with(DeviceMeter::new),
+        };
+        device.add(t_ms, gal);
+        if device.pending < UPDATE_EVERY || device.window.len() < MIN_SAMPLES {
+            return;
+        }
+        device.pending = 0;
+        device.current = self.filter.intensity(device.window.make_contiguous()).map(jma_round);
+        device.updated_ms = Some(now_ms);
+        device.prune(now_ms);
+        if let Some(intensity) = device.current {
+            device.history.push_back((now_ms, intensity));
+        }
+    }
+
+    /// The device's current (rounded) intensity.
+    pub fn current(&self, ua: &str) -> Option<f64> {
+        self.devices.get(ua)?.current
+    }
+
+    pub fn snapshot(&mut self, now_ms: u64) -> BTreeMap<String, IntensityReading> {
+        self.devices
+            .iter_mut()
+            .map(|(ua, device)| {
+                device.prune(now_ms);
+                let peak = device.peak();
+                let reading = IntensityReading {
+                    intensity: device.current,
+                    shindo: device.current.map(shindo_class),
+                    updated_ms: device.updated_ms,
+                    peak_intensity: peak.map(|(_, intensity)| intensity),
+                    peak_shindo: peak.map(|(_, intensity)| shindo_class(intensity)),
+                    peak_at_ms: peak.map(|(at, _)| at),
+                };
+                (ua.clone(), reading)
+            })
+            .collect()
+    }
+}
+
+/// Checks against synthetic motion with a known answer. Circular motion of amplitude `A` at
+/// `f` Hz has a constant vector magnitude, so after the JMA filter the acceleration held for
+/// 0.3 s is `A * filter_gain(f)` and the intensity `2 log10(A * filter_gain(f)) + 0.94`.
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+
+    const UA: &str = "synthetic";
+
+    /// `WINDOW` points of circular motion in the x-y plane, on top of 1 G on z.
+    fn circular(amplitude: f64, f: f64) -> Vec<[f64; 3]> {
+        (0..WINDOW)
+            .map(|i| {
+                let phase = TAU * f * i as f64 / RATE_HZ;
+                [amplitude * phase.cos(), amplitude * phase.sin(), 980.665]
+            })
+            .collect()
+    }
+
+    fn expected(amplitude: f64, f: f64) -> f64 {
+        2.0 * (amplitude * filter_gain(f)).log10() + 0.94
+    }
+
+    /// The filter's gain, from the formulas of the JMA specification.
+    #[test]
+    fn filter_gain_matches_the_specification() {
+        for (f, gain) in [(0.1, 0.2823), (0.5, 1.1234), (1.0, 0.9964), (5.0, 0.4101), (10.0, 0.2235)] {
+            let actual = filter_gain(f);
+            assert!((actual - gain).abs() < 0.0001, "{} Hz: {:.4}, expected {:.4}", f, actual, gain);
+        }
+    }
+
+    /// Regularly sampled: the filter alone, to well within the reported 0.1 steps.
+    #[test]
+    fn circular_motion_has_the_expected_intensity() {
+        let mut filter = JmaFilter::new();
+        for f in [0.5, 1.0, 2.0, 5.0, 8.0] {
+            for amplitude in [1.0, 30.0, 400.0] {
+                let actual = filter.intensity(&circular(amplitude, f)).unwrap_or(f64::NAN);
+                let want = expected(amplitude, f);
+                assert!(
+                    (actual - want).abs() < 0.02,
+                    "{} gal at {} Hz: {:.3}, expected {:.3}",
+                    amplitude,
+                    f,
+                    actual,
+                    want
+                );
+            }
+        }
+    }
+
+    /// Gravity and sensor bias alone are no motion.
+    #[test]
+    fn constant_acceleration_has_no_intensity() {
+        let still = vec![[3.0, -2.0, 980.665]; WINDOW];
+        assert_eq!(JmaFilter::new().intensity(&still), None);
+    }
+
+    /// Irregularly sampled around 50 Hz: resampled onto the 100 Hz grid first.
+    #[test]
+    fn jittered_sampling_is_resampled() {
+        let (amplitude, f) = (107.0, 1.5);
+        let mut intensities = Intensities::default();
+        let mut t_ms = 1_768_000_000_000.0;
+        let mut jitter = 0x2545_f491_u32;
+        while t_ms < 1_768_000_000_000.0 + 45_000.0 {
+            let phase = TAU * f * t_ms / 1000.0;
+            intensities.record(UA, t_ms, [amplitude * phase.cos(), amplitude * phase.sin(), 980.665], 0);
+            // 20 ms ± 8 ms, from a xorshift so the run is repeatable
+            jitter ^= jitter << 13;
+            jitter ^= jitter >> 17;
+            jitter ^= jitter << 5;
+            t_ms += 12.0 + (jitter % 1600) as f64 / 100.0;
+        }
+        let want = jma_round(expected(amplitude, f));
+        let actual = intensities.current(UA);
+        assert!(
+            actual.is_some_and(|actual| (actual - want).abs() <= 0.1),
+            "{:?}, expected {} (shindo {})",
+            actual,
+            want,
+            shindo_class(want)
+        );
+    }
+
+    /// The JMA's rounding and classes.
+    #[test]
+    fn rounds_and_classes_as_the_jma() {
+        for (raw, rounded, class) in [(4.449, 4.4, "4"), (4.495, 4.5, "5-"), (5.96, 5.9, "6-"), (6.5, 6.5, "7")] {
+            let actual = jma_round(raw);
+            assert_eq!(actual, rounded, "{}", raw);
+            assert_eq!(shindo_class(actual), class, "{}", raw);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use ipnet::IpNet;
+
+/// The `--allow-ip`/`--block-ip` lists every HTTP and gRPC request is checked against.
+///
+/// The networks given on the command line are fixed; those in `--allow-ip-file` and
+/// `--block-ip-file` are re-read on SIGHUP.
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    block: Vec<IpNet>,
+    allow_file: Option<PathBuf>,
+    block_file: Option<PathBuf>,
+    // The lists in effect, flags and files combined
+    lists: RwLock<Arc<IpLists>>,
+}
+
+#[derive(Default)]
+struct IpLists {
+    allow: Vec<IpNet>,
+    block: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn load(
+        allow: Vec<IpNet>,
+        block: Vec<IpNet>,
+        allow_file: Option<PathBuf>,
+        block_file: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        let filter = Self {
+            allow,
+            block,
+            allow_file,
+            block_file,
+            lists: RwLock::default(),
+        };
+        filter.reload()?;
+        Ok(filter)
+    }
+
+    /// Re-reads the list files; on an error the previous lists stay in effect.
+    pub fn reload(&self) -> Result<(), String> {
+        let lists = IpLists {
+            allow: combine(&self.allow, self.allow_file.as_deref())?,
+            block: combine(&self.block, self.block_file.as_deref())?,
+        };
+        *self.lists.write().unwrap() = Arc::new(lists);
+        Ok(())
+    }
+
+    pub fn has_files(&self) -> bool {
+        self.allow_file.is_some() || self.block_file.is_some()
+    }
+
+    /// Whether a client may connect: never when blocked, and only when listed if there is an
+    /// allow list. A client whose address is unknown only passes without an allow list.
+    pub fn admits(&self, ip: Option<IpAddr>) -> bool {
+        let lists = self.lists.read().unwrap().clone();
+        // Dual-stack listeners see IPv4 clients as `::ffff:a.b.c.d`
+        let ip = ip.map(|ip| ip.to_canonical());
+        if let Some(ip) = ip
+            && lists.block.iter().any(|net| net.contains(&ip))
+        {
+            return false;
+        }
+        lists.allow.is_empty() || ip.is_some_and(|ip| lists.allow.iter().any(|net| net.contains(&ip)))
+    }
+}
+
+/// Parses a CIDR network, or a single address as a /32 or /128.
+pub fn parse_net(raw: &str) -> Result<IpNet, String> {
+    let raw = raw.trim();
+    raw.parse::<IpNet>()
+        .or_else(|_| raw.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("`{}` is not an IP address or CIDR network", raw))
+}
+
+fn combine(fixed: &[IpNet], file: Option<&Path>) -> Result<Vec<IpNet>, String> {
+    let mut nets = fixed.to_vec();
+    if let Some(path) = file {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        // One network per line, `#` starts a comment, as in --api-token-file
+        for (lineno, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            nets.push(parse_net(line).map_err(|e| format!("{}:{}: {}", path.display(), lineno + 1, e))?);
+        }
+    }
+    Ok(nets)
+}
+
+/// Re-reads the list files on every SIGHUP, e.g. after a deploy updated them.
+#[cfg(unix)]
+pub fn reload_on_sighup(filter: Arc<IpFilter>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                tracing::error!("failed to listen for SIGHUP, IP list reload disabled: {}", err);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match filter.reload() {
+                Ok(()) => tracing::info!("reloaded the IP allow/block lists"),
+                Err(err) => tracing::error!("failed to reload the IP allow/block lists, keeping the previous ones: {}", err),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_filter: Arc<IpFilter>) {}
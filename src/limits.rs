@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_graphql::SimpleObject;
+use axum::{
+    extract::Request,
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Duration;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+// Long-lived endpoints that are never cut off by the request timeout (`/api/poll` bounds
+// its own wait)
+const UNTIMED_PATHS: &[&str] = &[
+    "/ws",
+    "/api/messages/stream",
+    "/api/events",
+    "/api/poll",
+    "/api/graphql/ws",
+];
+
+/// Server-wide request limits; each is disabled when configured as 0.
+pub struct RequestLimits {
+    concurrency: Option<Semaphore>,
+    max_concurrent: usize,
+    timeout: Option<Duration>,
+    max_body_bytes: u64,
+    // One permit per live /ws connection, held by its session task
+    ws_slots: Arc<Semaphore>,
+    max_ws_clients: usize,
+    rejected: Rejections,
+}
+
+#[derive(Default)]
+struct Rejections {
+    concurrency: AtomicU64,
+    timeout: AtomicU64,
+    body_too_large: AtomicU64,
+    ws_clients: AtomicU64,
+}
+
+/// Number of requests turned away by each limit since startup.
+#[derive(Serialize, ToSchema, SimpleObject)]
+pub struct RejectionCounts {
+    /// Answered 503 because `--max-concurrent-requests` were already in flight
+    pub concurrency: u64,
+    /// Answered 408 after `--request-timeout-secs`
+    pub timeout: u64,
+    /// Answered 413 because a POST body exceeded `--max-body-bytes`
+    pub body_too_large: u64,
+    /// /ws upgrades answered 503 because `--max-ws-clients` were already connected
+    pub ws_clients: u64,
+}
+
+impl RequestLimits {
+    pub fn new(
+        max_concurrent: usize,
+        timeout_secs: u64,
+        max_body_bytes: u64,
+        max_ws_clients: usize,
+    ) -> Self {
+        // Unlimited still counts connections, just against a bound that is never reached
+        let ws_capacity = match max_ws_clients {
+            0 => Semaphore::MAX_PERMITS,
+            n => n,
+        };
+        Self {
+            concurrency: (max_concurrent > 0).then(|| Semaphore::new(max_concurrent)),
+            max_concurrent,
+            timeout: (timeout_secs > 0).then(|| Duration::from_secs(timeout_secs)),
+            max_body_bytes,
+            ws_slots: Arc::new(Semaphore::new(ws_capacity)),
+            max_ws_clients: ws_capacity,
+            rejected: Rejections::default(),
+        }
+    }
+
+    /// Takes a slot for a new /ws connection, answering 503 when `--max-ws-clients` are
+    /// connected.
+    ///
+    /// The slot is released when the permit is dropped, so a session that ends in any way,
+    /// including a panic or an abort, stops counting.
+    pub fn ws_slot(&self) -> Result<OwnedSemaphorePermit, ApiError> {
+        self.ws_slots.clone().try_acquire_owned().map_err(|_| {
+            self.rejected.ws_clients.fetch_add(1, Ordering::Relaxed);
+            ApiError::ServiceUnavailable(format!(
+                "{} /ws clients are already connected",
+                self.max_ws_clients
+            ))
+        })
+    }
+
+    /// Live /ws connections.
+    pub fn ws_client_count(&self) -> usize {
+        self.max_ws_clients - self.ws_slots.available_permits()
+    }
+
+    pub fn rejections(&self) -> RejectionCounts {
+        RejectionCounts {
+            concurrency: self.rejected.concurrency.load(Ordering::Relaxed),
+            timeout: self.rejected.timeout.load(Ordering::Relaxed),
+            body_too_large: self.rejected.body_too_large.load(Ordering::Relaxed),
+            ws_clients: self.rejected.ws_clients.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Runs the request under the body size check, a concurrency permit and the timeout.
+    ///
+    /// The permit is held until the response head is produced, so a streamed body (e.g. an
+    /// upgraded `/ws`) does not keep a slot after its handler returns.
+    pub async fn enforce(&self, req: Request, next: Next) -> Result<Response, ApiError> {
+        if self.max_body_bytes > 0 && req.method() == Method::POST && self.body_too_large(&req) {
+            self.rejected.body_too_large.fetch_add(1, Ordering::Relaxed);
+            return Err(ApiError::PayloadTooLarge(format!(
+                "request body exceeds {} bytes",
+                self.max_body_bytes
+            )));
+        }
+
+        let _permit = match &self.concurrency {
+            Some(semaphore) => match semaphore.try_acquire() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    self.rejected.concurrency.fetch_add(1, Ordering::Relaxed);
+                    return Err(ApiError::ServiceUnavailable(format!(
+                        "more than {} requests in flight",
+                        self.max_concurrent
+                    )));
+                }
+            },
+            None => None,
+        };
+
+        let timeout = self
+            .timeout
+            .filter(|_| !UNTIMED_PATHS.contains(&req.uri().path()) && !req.uri().path().starts_with("/ws/"));
+        let Some(timeout) = timeout else {
+            return Ok(next.run(req).await);
+        };
+        match tokio::time::timeout(timeout, next.run(req)).await {
+            Ok(response) => Ok(response),
+            Err(_) => {
+                self.rejected.timeout.fetch_add(1, Ordering::Relaxed);
+                Err(ApiError::RequestTimeout(format!(
+                    "request took longer than {}s",
+                    timeout.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// Checks the declared `Content-Length`; chunked bodies are capped by `DefaultBodyLimit`
+    /// once a handler reads them.
+    fn body_too_large(&self, req: &Request) -> bool {
+        req.headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .is_some_and(|len| len > self.max_body_bytes)
+    }
+}
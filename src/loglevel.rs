@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Levels `POST /api/log-level` accepts.
+pub const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+/// What `LogLevel::set` did.
+pub struct LevelChange {
+    /// The filter that was replaced
+    pub previous: String,
+    /// When the default comes back, under --log-level-timeout-secs
+    pub resets_in: Option<Duration>,
+}
+
+/// The tracing filter, changeable at runtime through `POST /api/log-level`.
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// The startup filter: `RUST_LOG`, or `info`
+    default: String,
+    /// --log-level-timeout-secs, after which a changed level goes back to `default`
+    timeout: Option<Duration>,
+    // Bumped on every change, so a pending reset only undoes the change that scheduled it
+    generation: AtomicU64,
+}
+
+impl LogLevel {
+    /// Installs the global subscriber, writing to stderr; stdout carries the raw message stream.
+    pub fn init(timeout: Option<Duration>) -> Self {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        let default = filter.to_string();
+        let (filter, handle) = reload::Layer::new(filter);
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+            .init();
+        Self {
+            handle,
+            default,
+            timeout,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// A filter that is not installed anywhere, for states built by tests.
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (_, handle) = reload::Layer::<EnvFilter, Registry>::new(EnvFilter::new("info"));
+        Self {
+            handle,
+            default: "info".to_string(),
+            timeout: None,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// The filter in effect, e.g. `info` or a `RUST_LOG` directive list.
+    pub fn current(&self) -> String {
+        self.handle.with_current(|filter| filter.to_string()).unwrap_or_default()
+    }
+
+    /// Switches every target to `level`, one of `LEVELS`. Under --log-level-timeout-secs the
+    /// default comes back after that long, unless the level is changed again first.
+    pub fn set(self: &Arc<Self>, level: &str) -> Result<LevelChange, String> {
+        if !LEVELS.contains(&level) {
+            return Err(format!("unknown level `{}`, expected one of {}", level, LEVELS.join(", ")));
+        }
+        let previous = self.current();
+        self.reload(level)?;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let resets_in = self.timeout.filter(|_| level != self.default);
+        if let Some(timeout) = resets_in {
+            let this = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                if this.generation.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+                match this.reload(&this.default) {
+                    Ok(()) => tracing::info!(level = %this.default, "log level reset after --log-level-timeout-secs"),
+                    Err(err) => tracing::error!("Failed to reset the log level: {}", err),
+                }
+            });
+        }
+        Ok(LevelChange { previous, resets_in })
+    }
+
+    fn reload(&self, directives: &str) -> Result<(), String> {
+        self.handle
+            .reload(EnvFilter::new(directives))
+            .map_err(|err| err.to_string())
+    }
+}
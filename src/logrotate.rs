@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How often the --log-file is checked against --log-max-size-bytes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rotation of the --log-file a daemon's stdout and stderr are appended to.
+///
+/// Rotation works on the file descriptors rather than through the tracing subscriber, so the
+/// log lines, the raw messages on stdout and anything else written to either follow the new
+/// file.
+pub struct LogRotation {
+    path: PathBuf,
+    /// 0 disables size-based rotation; SIGHUP still reopens the file
+    max_size: u64,
+    max_files: usize,
+}
+
+impl LogRotation {
+    pub fn new(path: PathBuf, max_size: u64, max_files: usize) -> Self {
+        Self { path, max_size, max_files }
+    }
+}
+
+#[cfg(unix)]
+impl LogRotation {
+    /// Rotates when the file stdout and stderr point at has grown past the size limit.
+    fn rotate_if_full(&self) -> std::io::Result<bool> {
+        use std::os::fd::AsFd;
+
+        // Checked through the descriptor, as the path may already name another file
+        let current = std::fs::File::from(std::io::stderr().as_fd().try_clone_to_owned()?);
+        if self.max_size == 0 || current.metadata()?.len() < self.max_size {
+            return Ok(false);
+        }
+        self.rotate()?;
+        Ok(true)
+    }
+
+    /// Shifts `<path>.1`.. up by one, dropping the oldest beyond --log-max-files, moves the
+    /// current file to `<path>.1` and starts a new one.
+    fn rotate(&self) -> std::io::Result<()> {
+        for n in (1..self.max_files).rev() {
+            ignore_missing(std::fs::rename(self.numbered(n), self.numbered(n + 1)))?;
+        }
+        match self.max_files {
+            0 => ignore_missing(std::fs::remove_file(&self.path))?,
+            _ => ignore_missing(std::fs::rename(&self.path, self.numbered(1)))?,
+        }
+        self.reopen()
+    }
+
+    /// Points stdout and stderr at the file at the --log-file path, creating it if needed.
+    fn reopen(&self) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            // SAFETY: both descriptors are valid for the call; dup2 replaces `fd` atomically
+            if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+#[cfg(unix)]
+fn ignore_missing(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// Checks the file size every second and reopens the file on every SIGHUP, for `logrotate`
+/// configurations that move the file away instead of relying on --log-max-size-bytes.
+///
+/// A failed rotation stops the size checks until the next successful reopen, so a file that
+/// cannot be replaced is not shifted through the numbered names once a second.
+#[cfg(unix)]
+pub fn spawn(rotation: LogRotation) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = signal(SignalKind::hangup())
+            .map_err(|err| tracing::error!("failed to listen for SIGHUP, log file reopening disabled: {}", err))
+            .ok();
+        let mut checks = tokio::time::interval(CHECK_INTERVAL);
+        let mut rotating = rotation.max_size > 0;
+        loop {
+            tokio::select! {
+                _ = checks.tick(), if rotating => match rotation.rotate_if_full() {
+                    Ok(true) => tracing::info!(path = %rotation.path.display(), "rotated the log file"),
+                    Ok(false) => {}
+                    Err(err) => {
+                        rotating = false;
+                        tracing::error!(
+                            "failed to rotate the log file {}, rotation paused until SIGHUP: {}",
+                            rotation.path.display(),
+                            err
+                        );
+                    }
+                },
+                Some(()) = async {
+                    match &mut hangups {
+                        Some(hangups) => hangups.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => match rotation.reopen() {
+                    Ok(()) => {
+                        rotating = rotation.max_size > 0;
+                        tracing::info!(path = %rotation.path.display(), "reopened the log file");
+                    }
+                    Err(err) => tracing::error!(
+                        "failed to reopen the log file {}, still writing to the previous one: {}",
+                        rotation.path.display(),
+                        err
+                    ),
+                },
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn(_rotation: LogRotation) {}
@@ -1,217 +1,4654 @@
-use std::collections::VecDeque;
-use std::env;
-use std::net::SocketAddr;
+mod access_log;
+mod aggregation;
+mod alerts;
+mod assets;
+mod audit;
+mod auth;
+mod cli;
+mod client_ip;
+mod clockskew;
+mod correlation;
+mod cors;
+mod daemon;
+mod encoding;
+mod envelope;
+mod error;
+mod gaps;
+mod highpass;
+mod intensity;
+mod graphql;
+mod grpc;
+mod ipfilter;
+mod limits;
+mod loglevel;
+mod logrotate;
+mod message_format;
+mod namespace;
+mod noise;
+mod outlier;
+mod pga;
+mod process;
+mod ratelimit;
+mod recording;
+mod samplerate;
+mod schema;
+mod seqfilter;
+mod shake;
+mod signing;
+mod spectrum;
+mod systemd;
+#[cfg(test)]
+mod tests;
+mod tls;
+mod trace_context;
+mod transform;
+mod tui;
+mod wasm_plugin;
+mod watch;
+mod webhook;
+mod wsack;
+mod wsbatch;
+mod wscontrol;
+mod wsfilter;
+mod wsqueue;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
-use axum::{
-    extract::{Query, State},
-    response::{Html, IntoResponse},
-    routing::{get},
-    Router,
-};
-use axum::extract::ws::{Message as WsMessage, WebSocketUpgrade};
-use futures_util::StreamExt;
-use serde::Deserialize;
-use serde_json::Value;
-use tokio::sync::{broadcast, RwLock};
-use tokio::time::{sleep, Duration};
-use tokio_tungstenite::connect_async;
+use axum::{
+    body::{Body, Bytes},
+    extract::{rejection::{JsonRejection, QueryRejection}, ConnectInfo, DefaultBodyLimit, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Redirect, Response,
+    },
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use axum::extract::ws::{close_code, CloseFrame, Message as WsMessage, WebSocketUpgrade};
+use clap::{CommandFactory, Parser, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tokio::time::{sleep, Duration, Instant};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode as UpstreamCloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame as UpstreamCloseFrame;
+use tokio_tungstenite::tungstenite::Message as UpstreamMessage;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
+use tower_http::services::ServeDir;
+use async_graphql::SimpleObject;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::aggregation::{Aggregator, Bucket};
+use crate::alerts::{AlertConfig, AlertStats, Alerter, ChatService};
+use crate::assets::UplotUrls;
+use crate::audit::{AuditEvent, AuditLog};
+use crate::auth::{ApiTokens, BasicCredentials, JwtClaims, JwtVerifier};
+use crate::clockskew::ClockOffsets;
+use crate::correlation::{Confirmation, ConfirmedEvent, CorrelationConfig, Correlator};
+use crate::cli::{Args, Command, HighpassOutput, OutlierMode};
+use crate::encoding::{Encoded, WireFormat};
+use crate::envelope::Envelope;
+use crate::error::{ApiError, ErrorBody};
+use crate::gaps::{Gap, Timelines};
+use crate::highpass::HighPass;
+use crate::intensity::{Intensities, IntensityReading};
+use crate::ipfilter::IpFilter;
+use crate::limits::{RejectionCounts, RequestLimits};
+use crate::loglevel::LogLevel;
+use crate::logrotate::LogRotation;
+use crate::message_format::MessageFormat;
+use crate::namespace::NamespaceEntry;
+use crate::ratelimit::{RateLimiter, RateLimits, TokenBucket};
+use crate::recording::{Lookup, Recorder};
+use crate::samplerate::{SampleRate, SampleRates};
+use crate::noise::{NoiseReading, NoiseStats};
+use crate::outlier::{OutlierFilter, Verdict};
+use crate::pga::{PeakAccelerations, PgaReading};
+use crate::spectrum::{Spectrum, SpectrumAxis};
+use crate::schema::{InferredSchema, SchemaInferrer};
+use crate::signing::MessageSigner;
+use crate::seqfilter::{Admission, SeqFilter};
+use crate::shake::{ShakeDetector, ShakeEvent, StaLtaConfig, Transition};
+use crate::transform::{LuaScript, Transformed, Transformer};
+use crate::wasm_plugin::WasmPlugin;
+use crate::webhook::{Notifier, WebhookConfig, WebhookStats};
+use crate::wsack::AckTracker;
+use crate::wsbatch::Batch;
+use crate::wscontrol::{Control, Incoming};
+use crate::wsqueue::SendQueue;
+
+const MAX_BUFFER_BYTES: usize = 1024 * 1024 * 1024; // 1GB
+const MAX_CONNECTION_HISTORY: usize = 100;
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_LIST_LIMIT: usize = 500;
+const SCHEMA_SAMPLE_SIZE: usize = 1000;
+const AUTH_FAILURES_PER_MINUTE: u32 = 10;
+// Rendered by build.rs from the clap definition in cli.rs
+const MAN_PAGE: &str = include_str!(concat!(env!("OUT_DIR"), "/yurecollect.1"));
+// Exit status when in-flight work outlives --shutdown-timeout-secs
+const EXIT_FORCED_SHUTDOWN: i32 = 3;
+// How long a closing /ws session waits for its writer to deliver the close frame
+const WS_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+// Kubernetes probes, reachable without credentials
+const PROBE_PATHS: &[&str] = &["/livez", "/readyz", "/healthz"];
+// Cache policy for --ui-dir files: always revalidate so edits show up on reload
+const UI_DIR_CACHE_CONTROL: &str = "no-cache";
+
+/// A received message together with the time it arrived at the collector.
+struct BufferedMessage {
+    /// Ingest-order id, as in `LiveMessage`
+    id: u64,
+    received_at_ms: u64,
+    /// `_seq` injected into the message under --inject-seq
+    seq: Option<u64>,
+    format: MessageFormat,
+    /// `t` of a single-sample message on the server's clock, as estimated at ingest
+    t_corrected: Option<u64>,
+    /// Its device's intensity just after it was recorded, under --intensity
+    intensity: Option<f64>,
+    /// Why processing failed, for messages in the dead-letter buffer
+    error: Option<Box<str>>,
+    text: String,
+}
+
+/// Messages `MessageBuffer::push` dropped from the front to make room.
+#[derive(Default)]
+struct Evicted {
+    count: usize,
+    bytes: usize,
+}
+
+struct MessageBuffer {
+    max_bytes: usize,
+    total_bytes: usize,
+    entries: VecDeque<BufferedMessage>,
+    // Ingest-order id of the last pushed message (0 before the first), counted for every
+    // message unlike `_seq`
+    last_id: u64,
+}
+
+/// A message as fanned out to live subscribers, published right after it is buffered.
+#[derive(Clone)]
+struct LiveMessage {
+    /// The buffer's `last_id` just after this message was pushed
+    id: u64,
+    received_at_ms: u64,
+    /// The injected `_seq`, under --inject-seq
+    seq: Option<u64>,
+    format: MessageFormat,
+    t_corrected: Option<u64>,
+    intensity: Option<f64>,
+    text: String,
+    /// Distinct `userAgent`s found when the message was parsed at ingest
+    user_agents: Arc<[String]>,
+    /// MessagePack/CBOR forms, encoded on first use and shared by every /ws client
+    encoded: Arc<Encoded>,
+}
+
+impl MessageBuffer {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            total_bytes: 0,
+            entries: VecDeque::new(),
+            last_id: 0,
+        }
+    }
+
+    /// Appends a message received at `received_at_ms` and returns its id, along with the
+    /// oldest messages evicted to stay within `max_bytes`.
+    fn push(
+        &mut self,
+        msg: String,
+        seq: Option<u64>,
+        format: MessageFormat,
+        t_corrected: Option<u64>,
+        intensity: Option<f64>,
+        received_at_ms: u64,
+    ) -> (u64, Evicted) {
+        let evicted = self.make_room(msg.len());
+        self.total_bytes += msg.len();
+        self.last_id += 1;
+        self.entries.push_back(BufferedMessage {
+            id: self.last_id,
+            received_at_ms,
+            seq,
+            format,
+            t_corrected,
+            intensity,
+            error: None,
+            text: msg,
+        });
+        (self.last_id, evicted)
+    }
+
+    /// Appends a message that failed processing, with why.
+    fn push_failed(&mut self, msg: String, format: MessageFormat, error: String, received_at_ms: u64) {
+        self.make_room(msg.len());
+        self.total_bytes += msg.len();
+        self.last_id += 1;
+        self.entries.push_back(BufferedMessage {
+            id: self.last_id,
+            received_at_ms,
+            seq: None,
+            format,
+            t_corrected: None,
+            intensity: None,
+            error: Some(error.into()),
+            text: msg,
+        });
+    }
+
+    fn make_room(&mut self, msg_len: usize) -> Evicted {
+        let mut evicted = Evicted::default();
+        while self.total_bytes + msg_len > self.max_bytes {
+            if let Some(front) = self.entries.pop_front() {
+                self.total_bytes = self.total_bytes.saturating_sub(front.text.len());
+                evicted.count += 1;
+                evicted.bytes += front.text.len();
+            } else {
+                break;
+            }
+        }
+        evicted
+    }
+
+    /// Picks up to `n` messages uniformly at random (reservoir sampling, Algorithm R).
+    fn sample(&self, n: usize, rng: &mut impl Rng) -> Vec<&String> {
+        let mut reservoir = Vec::with_capacity(n.min(self.entries.len()));
+        for (i, msg) in self.entries.iter().enumerate() {
+            if i < n {
+                reservoir.push(&msg.text);
+            } else {
+                let j = rng.gen_range(0..=i);
+                if j < n {
+                    reservoir[j] = &msg.text;
+                }
+            }
+        }
+        reservoir
+    }
+
+    fn len(&self) -> usize { self.entries.len() }
+    fn iter(&self) -> impl DoubleEndedIterator<Item=&BufferedMessage> { self.entries.iter() }
+}
+
+/// Sliding-window counter of message arrivals over the last `RATE_WINDOW`.
+struct RateCounter {
+    arrivals: VecDeque<Instant>,
+}
+
+impl RateCounter {
+    fn new() -> Self {
+        Self { arrivals: VecDeque::new() }
+    }
+
+    fn record(&mut self) {
+        let now = Instant::now();
+        while let Some(&front) = self.arrivals.front() {
+            if now.duration_since(front) > RATE_WINDOW {
+                self.arrivals.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.arrivals.push_back(now);
+    }
+
+    fn rate_per_second(&self) -> f64 {
+        let now = Instant::now();
+        let recent = self
+            .arrivals
+            .iter()
+            .rev()
+            .take_while(|&&t| now.duration_since(t) <= RATE_WINDOW)
+            .count();
+        recent as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+/// Lag and send queue of one live /ws connection.
+#[derive(Clone, Serialize, ToSchema, SimpleObject)]
+struct WsClientLag {
+    id: u64,
+    connected_ms: u64,
+    /// Times the client fell more than the broadcast capacity behind
+    lag_events: u64,
+    /// Messages skipped over those events
+    missed: u64,
+    /// Messages waiting in the client's send queue
+    queued: usize,
+    /// Oldest queued messages discarded because the send queue was full
+    dropped: u64,
+    /// Highest `_seq` the client acknowledged with `{"ack":<seq>}`; null if it never did
+    last_acked_seq: Option<u64>,
+    /// Messages with a `_seq` sent after the last acknowledged one
+    unacked: u64,
+    /// Text and binary frames sent, a `batch_ms` frame counting once
+    messages_sent: u64,
+    /// Payload bytes of those frames
+    bytes_sent: u64,
+}
+
+/// A live /ws connection as listed by `/api/admin/ws-clients`.
+#[derive(Serialize, ToSchema)]
+struct WsClientDetail {
+    /// Client IP (the `X-Forwarded-For` one under --trust-proxy); null when unknown
+    remote_addr: Option<String>,
+    /// `sub` of the --jwt-secret JWT the client connected with; null otherwise
+    subject: Option<String>,
+    #[serde(flatten)]
+    stats: WsClientLag,
+}
+
+struct WsClient {
+    lag: WsClientLag,
+    remote_addr: Option<IpAddr>,
+    subject: Option<String>,
+    queue: Arc<SendQueue>,
+    acks: Arc<AckTracker>,
+}
+
+impl WsClient {
+    fn stats(&self) -> WsClientLag {
+        WsClientLag {
+            queued: self.queue.len(),
+            dropped: self.queue.dropped(),
+            last_acked_seq: self.acks.last_acked(),
+            unacked: self.acks.unacked(),
+            messages_sent: self.queue.sent(),
+            bytes_sent: self.queue.sent_bytes(),
+            ..self.lag.clone()
+        }
+    }
+}
+
+/// Live /ws connections and how many messages each had to skip.
+#[derive(Default)]
+struct WsClients {
+    next_id: u64,
+    live: BTreeMap<u64, WsClient>,
+    // Across all connections since startup, including closed ones
+    missed_total: u64,
+    // Send queue drops and frames sent of connections that have closed
+    closed_dropped: u64,
+    closed_sent: u64,
+    closed_sent_bytes: u64,
+    // Disconnected by --ws-strict for overflowing their send queue
+    slow_disconnects: u64,
+    // Disconnected by --ws-disconnect-on-lag
+    lag_disconnects: u64,
+}
+
+impl WsClients {
+    fn connect(
+        &mut self,
+        remote_addr: Option<IpAddr>,
+        subject: Option<String>,
+        queue: Arc<SendQueue>,
+        acks: Arc<AckTracker>,
+    ) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        let lag = WsClientLag {
+            id,
+            connected_ms: now_ms(),
+            lag_events: 0,
+            missed: 0,
+            queued: 0,
+            dropped: 0,
+            last_acked_seq: None,
+            unacked: 0,
+            messages_sent: 0,
+            bytes_sent: 0,
+        };
+        self.live.insert(
+            id,
+            WsClient {
+                lag,
+                remote_addr,
+                subject,
+                queue,
+                acks,
+            },
+        );
+        id
+    }
+
+    fn lagged(&mut self, id: u64, missed: u64) {
+        if let Some(client) = self.live.get_mut(&id) {
+            client.lag.lag_events += 1;
+            client.lag.missed += missed;
+        }
+        self.missed_total += missed;
+    }
+
+    fn too_slow(&mut self) {
+        self.slow_disconnects += 1;
+    }
+
+    fn lag_disconnected(&mut self) {
+        self.lag_disconnects += 1;
+    }
+
+    fn disconnect(&mut self, id: u64) {
+        if let Some(client) = self.live.remove(&id) {
+            self.closed_dropped += client.queue.dropped();
+            self.closed_sent += client.queue.sent();
+            self.closed_sent_bytes += client.queue.sent_bytes();
+        }
+    }
+
+    /// Send queue drops across all connections since startup.
+    fn dropped_total(&self) -> u64 {
+        self.closed_dropped + self.live.values().map(|c| c.queue.dropped()).sum::<u64>()
+    }
+
+    /// Frames and their bytes sent across all connections since startup.
+    fn sent_total(&self) -> (u64, u64) {
+        self.live.values().fold((self.closed_sent, self.closed_sent_bytes), |(sent, bytes), c| {
+            (sent + c.queue.sent(), bytes + c.queue.sent_bytes())
+        })
+    }
+
+    fn snapshot(&self) -> Vec<WsClientLag> {
+        self.live.values().map(WsClient::stats).collect()
+    }
+
+    fn details(&self) -> Vec<WsClientDetail> {
+        self.live
+            .values()
+            .map(|client| WsClientDetail {
+                remote_addr: client.remote_addr.map(|ip| ip.to_string()),
+                subject: client.subject.clone(),
+                stats: client.stats(),
+            })
+            .collect()
+    }
+}
+
+/// The devices a /ws connection asked for with `?ua=`; empty forwards everything.
+#[derive(Default)]
+struct UaFilter {
+    agents: Vec<String>,
+    // Forward messages without any userAgent as well
+    include_unknown: bool,
+}
+
+impl UaFilter {
+    fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    fn matches(&self, user_agents: &[String]) -> bool {
+        if self.agents.is_empty() {
+            return true;
+        }
+        if user_agents.is_empty() {
+            return self.include_unknown;
+        }
+        user_agents.iter().any(|ua| self.agents.contains(ua))
+    }
+
+    /// Parses a buffered message to decide, for replays that have no ingest-time result.
+    fn matches_text(&self, text: &str) -> bool {
+        if self.agents.is_empty() {
+            return true;
+        }
+        let agents = serde_json::from_str::<Value>(text)
+            .map(|value| message_user_agents(&value))
+            .unwrap_or_default();
+        self.matches(&agents)
+    }
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+struct UaStat {
+    count: u64,
+    last_seen_ms: u64,
+    /// Samples --outlier-filter flagged or dropped
+    outliers: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeviceInfo {
+    user_agent: String,
+    count: u64,
+    last_seen_ms: u64,
+    /// Samples --outlier-filter flagged or dropped
+    outliers: u64,
+    silent_for_ms: u64,
+    /// Estimated delivery rate, as in `/api/samplerate`
+    samples_per_second: f64,
+    /// No sample for longer than `open_gap_threshold_ms`, i.e. a gap is open right now
+    silent: bool,
+    /// How far the device's clock is ahead of the collector's in ms (negative when behind),
+    /// from the median of its recent `t` minus receive time, so delivery delay is included;
+    /// `null` until it sends a `t`
+    clock_offset_ms: Option<i64>,
+    /// Median deviation of recent samples from `clock_offset_ms`
+    clock_jitter_ms: Option<i64>,
+    /// Clock steps detected, each of which restarted the estimate
+    clock_steps: u64,
+    /// Peak ground acceleration, as in `/api/pga`; null until it sends `x`/`y`/`z`
+    pga: Option<PgaReading>,
+    /// RMS and noise floor, as in `/api/noise`; null until it sends two samples with `x`/`y`/`z`
+    noise: Option<NoiseReading>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DevicesResponse {
+    /// Number of devices currently `silent`
+    open_gaps: usize,
+    open_gap_threshold_ms: u64,
+    /// Sorted by userAgent
+    devices: Vec<DeviceInfo>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, ToSchema, async_graphql::Enum)]
+#[serde(rename_all = "snake_case")]
+enum ConnectionEventKind {
+    Connected,
+    ConnectFailed,
+    Disconnected,
+    ManualReconnect,
+}
+
+#[derive(Clone, Serialize, ToSchema, SimpleObject)]
+struct ConnectionEvent {
+    at_ms: u64,
+    kind: ConnectionEventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Upstream connection state plus a bounded history of connection events.
+#[derive(Clone, Default, Serialize, ToSchema, SimpleObject)]
+#[graphql(name = "UpstreamStatus")]
+struct UpstreamState {
+    connected: bool,
+    connected_since_ms: Option<u64>,
+    /// Start of the current outage (first failed connect or the disconnect); null while connected
+    disconnected_since_ms: Option<u64>,
+    /// Text and binary messages received since startup
+    messages: u64,
+    /// Payload bytes of those messages
+    bytes: u64,
+    last_message_ms: Option<u64>,
+    /// Successful connects after the first one
+    reconnects: u64,
+    #[serde(skip)]
+    #[graphql(skip)]
+    ever_connected: bool,
+    #[schema(value_type = Vec<ConnectionEvent>)]
+    history: VecDeque<ConnectionEvent>,
+}
+
+impl UpstreamState {
+    fn record(&mut self, kind: ConnectionEventKind, detail: Option<String>) {
+        let at_ms = now_ms();
+        match kind {
+            ConnectionEventKind::Connected => {
+                if self.ever_connected {
+                    self.reconnects += 1;
+                }
+                self.ever_connected = true;
+                self.connected = true;
+                self.connected_since_ms = Some(at_ms);
+                self.disconnected_since_ms = None;
+            }
+            ConnectionEventKind::ConnectFailed | ConnectionEventKind::Disconnected => {
+                self.connected = false;
+                self.connected_since_ms = None;
+                self.disconnected_since_ms.get_or_insert(at_ms);
+            }
+            ConnectionEventKind::ManualReconnect => {}
+        }
+        if self.history.len() >= MAX_CONNECTION_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(ConnectionEvent { at_ms, kind, detail });
+    }
+
+    fn record_message(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        self.last_message_ms = Some(now_ms());
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Args>,
+    start_time: Instant,
+    buffer: Arc<RwLock<MessageBuffer>>,
+    /// Messages that failed processing, capped at --dead-letter-bytes
+    dead_letter: Arc<RwLock<MessageBuffer>>,
+    tx: broadcast::Sender<LiveMessage>,
+    upstream: Arc<RwLock<UpstreamState>>,
+    // Cancelled on Ctrl+C/SIGTERM; the server, /ws sessions and the upstream task wind down
+    shutdown: CancellationToken,
+    // Live /ws sessions, awaited during shutdown so their close frames get sent
+    ws_sessions: TaskTracker,
+    // Set on the first successful upstream connect; gates /readyz
+    has_ever_connected: Arc<AtomicBool>,
+    // Wakes run_upstream_ws to drop the current connection (or attempt) and retry immediately;
+    // a request made while it is busy is kept until it next waits
+    reconnect: Arc<Notify>,
+    // Last `_seq` assigned with --inject-seq (0 before the first message)
+    seq: Arc<AtomicU64>,
+    /// Messages live subscribers of any kind skipped because they fell behind the broadcast
+    lag_dropped: Arc<AtomicU64>,
+    rate: Arc<RwLock<RateCounter>>,
+    ws_clients: Arc<RwLock<WsClients>>,
+    ua_stats: Arc<RwLock<HashMap<String, UaStat>>>,
+    timelines: Arc<RwLock<Timelines>>,
+    sample_rates: Arc<RwLock<SampleRates>>,
+    clock_offsets: Arc<RwLock<ClockOffsets>>,
+    intensities: Arc<RwLock<Intensities>>,
+    pga: Arc<RwLock<PeakAccelerations>>,
+    noise: Arc<RwLock<NoiseStats>>,
+    /// Under --highpass
+    highpass: Option<Arc<RwLock<HighPass>>>,
+    /// Under --outlier-filter
+    outliers: Option<Arc<RwLock<OutlierFilter>>>,
+    /// Under --upstream-seq-field
+    seq_filter: Option<Arc<RwLock<SeqFilter>>>,
+    /// Under --upstream-rate-limit-rps
+    ingest_bucket: Option<Arc<RwLock<TokenBucket>>>,
+    /// Upstream messages --upstream-rate-limit-rps held back, since startup
+    ingest_throttled: Arc<AtomicU64>,
+    /// Microseconds spent waiting for --upstream-rate-limit-rps, since startup
+    ingest_throttled_us: Arc<AtomicU64>,
+    /// Under --sign-key
+    signer: Option<Arc<MessageSigner>>,
+    /// Under --sta-lta
+    shake_detector: Option<Arc<RwLock<ShakeDetector>>>,
+    /// Under --correlate-devices
+    correlator: Option<Arc<RwLock<Correlator>>>,
+    /// `event_start`/`event_end` messages for /ws
+    shake_notices: broadcast::Sender<Transition>,
+    /// Under --record-dir
+    recorder: Option<Arc<RwLock<Recorder>>>,
+    /// Under --webhook-url
+    webhook: Option<Arc<Notifier>>,
+    /// Under --discord-webhook or --slack-webhook
+    alerts: Option<Arc<Alerter>>,
+    rate_limits: Arc<RateLimits>,
+    request_limits: Arc<RequestLimits>,
+    api_tokens: Arc<ApiTokens>,
+    basic_auth: Option<Arc<BasicCredentials>>,
+    jwt: Option<Arc<JwtVerifier>>,
+    audit: Arc<AuditLog>,
+    /// Changed through `POST /api/log-level`
+    log_level: Arc<LogLevel>,
+    ip_filter: Arc<IpFilter>,
+    // INDEX_HTML with the base path and uPlot URLs filled in
+    index_html: Bytes,
+    // Where the page loads uPlot from, also filled into a --ui-dir index.html
+    uplot: Arc<UplotUrls>,
+}
+
+/// What the states of every namespace share: one set of limits, credentials and listeners.
+struct SharedState {
+    shutdown: CancellationToken,
+    ws_sessions: TaskTracker,
+    signer: Option<Arc<MessageSigner>>,
+    webhook: Option<Arc<Notifier>>,
+    alerts: Option<Arc<Alerter>>,
+    rate_limits: Arc<RateLimits>,
+    request_limits: Arc<RequestLimits>,
+    api_tokens: Arc<ApiTokens>,
+    ip_filter: Arc<IpFilter>,
+    basic_auth: Option<Arc<BasicCredentials>>,
+    jwt: Option<Arc<JwtVerifier>>,
+    audit: Arc<AuditLog>,
+    log_level: Arc<LogLevel>,
+    // Load uPlot from unpkg.com rather than the binary
+    uplot_cdn: bool,
+}
+
+impl AppState {
+    /// A namespace's state (the whole server's when not namespaced), with nothing ingested yet.
+    fn new(config: Args, recorder: Option<Arc<RwLock<Recorder>>>, shared: &SharedState) -> Self {
+        let uplot = UplotUrls::new(&config.ui_path(), shared.uplot_cdn);
+        Self {
+            start_time: Instant::now(),
+            buffer: Arc::new(RwLock::new(MessageBuffer::new(MAX_BUFFER_BYTES))),
+            dead_letter: Arc::new(RwLock::new(MessageBuffer::new(config.dead_letter_bytes))),
+            tx: broadcast::channel(1024).0,
+            upstream: Arc::new(RwLock::new(UpstreamState::default())),
+            shutdown: shared.shutdown.clone(),
+            ws_sessions: shared.ws_sessions.clone(),
+            has_ever_connected: Arc::new(AtomicBool::new(false)),
+            reconnect: Arc::new(Notify::new()),
+            seq: Arc::new(AtomicU64::new(0)),
+            lag_dropped: Arc::new(AtomicU64::new(0)),
+            rate: Arc::new(RwLock::new(RateCounter::new())),
+            ws_clients: Arc::new(RwLock::new(WsClients::default())),
+            ua_stats: Arc::new(RwLock::new(HashMap::new())),
+            timelines: Arc::new(RwLock::new(Timelines::default())),
+            sample_rates: Arc::new(RwLock::new(SampleRates::default())),
+            clock_offsets: Arc::new(RwLock::new(ClockOffsets::default())),
+            intensities: Arc::new(RwLock::new(Intensities::default())),
+            pga: Arc::new(RwLock::new(PeakAccelerations::default())),
+            noise: Arc::new(RwLock::new(NoiseStats::new(config.rms_window))),
+            highpass: config
+                .highpass
+                .then(|| Arc::new(RwLock::new(HighPass::new(config.highpass_cutoff, config.highpass_max_gap)))),
+            outliers: config
+                .outlier_filter
+                .then(|| Arc::new(RwLock::new(OutlierFilter::new(config.outlier_max, config.outlier_mads)))),
+            seq_filter: config
+                .upstream_seq_field
+                .clone()
+                .map(|field| Arc::new(RwLock::new(SeqFilter::new(field)))),
+            ingest_bucket: (config.upstream_rate_limit_rps > 0).then(|| {
+                let rps = config.upstream_rate_limit_rps as f64;
+                Arc::new(RwLock::new(TokenBucket::new(rps, rps)))
+            }),
+            ingest_throttled: Arc::new(AtomicU64::new(0)),
+            ingest_throttled_us: Arc::new(AtomicU64::new(0)),
+            signer: shared.signer.clone(),
+            shake_detector: config.sta_lta.then(|| {
+                Arc::new(RwLock::new(ShakeDetector::new(StaLtaConfig {
+                    sta_window: config.sta_window,
+                    lta_window: config.lta_window,
+                    trigger_ratio: config.trigger_ratio,
+                    detrigger_ratio: config.detrigger_ratio,
+                    min_event_duration: config.min_event_duration,
+                })))
+            }),
+            correlator: config.correlate_devices.map(|min_devices| {
+                Arc::new(RwLock::new(Correlator::new(CorrelationConfig {
+                    min_devices,
+                    window: config.correlate_window,
+                })))
+            }),
+            shake_notices: broadcast::channel(256).0,
+            recorder,
+            webhook: shared.webhook.clone(),
+            alerts: shared.alerts.clone(),
+            rate_limits: shared.rate_limits.clone(),
+            request_limits: shared.request_limits.clone(),
+            api_tokens: shared.api_tokens.clone(),
+            ip_filter: shared.ip_filter.clone(),
+            basic_auth: shared.basic_auth.clone(),
+            jwt: shared.jwt.clone(),
+            audit: shared.audit.clone(),
+            log_level: shared.log_level.clone(),
+            index_html: Bytes::from(render_index(INDEX_HTML, &config, &uplot)),
+            uplot: Arc::new(uplot),
+            config: Arc::new(config),
+        }
+    }
+}
+
+// Sample spacing that counts as a gap unless `/api/gaps?min_gap=` says otherwise
+const DEFAULT_MIN_GAP_MS: u64 = 5_000;
+
+// Largest `/ws?backfill=`
+const MAX_WS_BACKFILL: usize = 10_000;
+
+// Largest `/ws?max_hz=`, the timer's millisecond resolution
+const MAX_WS_HZ: f64 = 1000.0;
+
+// Longest `/ws?batch_ms=`
+const MAX_WS_BATCH_MS: u64 = 10_000;
+
+// `/api/poll` waits this long when no `timeout_ms` is given, and at most `MAX_POLL_TIMEOUT_MS`
+const DEFAULT_POLL_TIMEOUT_MS: u64 = 25_000;
+const MAX_POLL_TIMEOUT_MS: u64 = 60_000;
+
+const LIMIT_CONSTRAINT: &str = "`limit` must be a non-negative integer";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListParams {
+    /// Number of newest messages to return (default 500); must be a non-negative integer
+    #[param(minimum = 0)]
+    limit: Option<usize>,
+    /// Only messages from the upstream with this label, as in `/api/sources`
+    source: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeadLetterParams {
+    /// Number of newest messages to return (default 500); must be a non-negative integer
+    #[param(minimum = 0)]
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StreamParams {
+    /// Keep the response open and append live messages after the buffered ones
+    follow: Option<bool>,
+    /// Only messages with a `_seq` greater than this (requires --inject-seq)
+    after_seq: Option<u64>,
+    /// Only messages with a `_seq` up to and including this (requires --inject-seq; not with `follow`)
+    until_seq: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PollParams {
+    /// Return the buffered messages with a `_seq` greater than this (default 0)
+    since_seq: Option<u64>,
+    /// How long to wait for such a message, in milliseconds (default 25000, at most 60000)
+    #[param(maximum = 60000)]
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WsParams {
+    /// Send this many of the newest buffered messages (oldest first) before the live ones, at
+    /// most 10000
+    #[param(maximum = 10000)]
+    backfill: Option<usize>,
+    /// Resume after this `_seq`: replay the buffered messages with a greater `_seq` before the
+    /// live ones (requires --inject-seq; not with `backfill`)
+    after_seq: Option<u64>,
+    /// Frame encoding: `json` text frames (default), or `msgpack`/`cbor` binary frames
+    format: Option<WireFormat>,
+    /// Send at most this many updates per second, each with only the newest message of every
+    /// device (by userAgents) since the previous one
+    #[param(exclusive_minimum = 0, maximum = 1000)]
+    max_hz: Option<f64>,
+    /// Collect live messages for up to this many milliseconds and send them as one frame: a
+    /// JSON array, or `{"messages":[...]}` with `envelope=1`. At most 10000
+    #[param(minimum = 1, maximum = 10000)]
+    batch_ms: Option<u64>,
+    /// Only forward messages for which this expression holds, e.g. `abs(x) > 0.5 or type ==
+    /// 'event'` (at most 1024 bytes); non-JSON messages only pass when it tests `format()`
+    filter: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SampleParams {
+    /// Number of messages to sample (default 100); must be a non-negative integer
+    #[param(minimum = 0)]
+    n: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchParams {
+    /// Case-insensitive search term
+    q: String,
+    /// Interpret `q` as a regular expression
+    regex: Option<bool>,
+    /// Only search the newest `limit` entries (default: the whole buffer)
+    #[param(minimum = 0)]
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AggregateParams {
+    /// Numeric field to aggregate; `ax`/`ay`/`az` accept the same aliases as the web UI
+    field: String,
+    /// Bucket width in milliseconds (default 1000); must be positive
+    #[param(minimum = 1)]
+    bucket_ms: Option<u64>,
+    /// Only include samples from this userAgent
+    ua: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GapsParams {
+    /// Only report this userAgent
+    ua: Option<String>,
+    /// Ignore samples before this time (UNIX milliseconds)
+    since: Option<u64>,
+    /// Ignore samples after this time (UNIX milliseconds)
+    until: Option<u64>,
+    /// Minimum spacing that counts as a gap, e.g. `500ms`, `5s`, `2m` (default `5s`)
+    min_gap: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SpectrumParams {
+    /// Device (userAgent) to analyse
+    ua: String,
+    /// Ignore samples before this time (UNIX milliseconds)
+    since: Option<u64>,
+    /// Ignore samples after this time (UNIX milliseconds)
+    until: Option<u64>,
+    /// `x`, `y`, `z` or `mag` (default), the vector's length
+    axis: Option<SpectrumAxis>,
+    /// Rate to resample to (default: the device's own, from the median sample spacing)
+    #[param(exclusive_minimum = 0, maximum = 10000)]
+    rate_hz: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ConfigResponse {
+    version: &'static str,
+    base_path: String,
+    /// --namespace, or the --namespaces-file entry this is; null when not namespaced
+    namespace: Option<String>,
+    upstream: UpstreamConfig,
+    buffer: BufferConfig,
+    chart: ChartConfig,
+    auth: AuthConfig,
+    limits: LimitsConfig,
+    inject_seq: bool,
+    /// Unit of `x`/`y`/`z` under --intensity (`g`, `m/s2` or `gal`); null when intensity is not
+    /// computed
+    intensity_unit: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct UpstreamConfig {
+    /// Upstream URL with the password and query values masked
+    url: String,
+    connect_timeout_ms: u64,
+    /// 0 when stall detection is disabled
+    read_timeout_ms: u64,
+    /// 0 when pinging is disabled
+    ping_interval_secs: u64,
+    pong_timeout_secs: u64,
+    /// Messages are rewritten by a --transform-script
+    transform_script: bool,
+    /// Messages are rewritten by a --wasm-plugin
+    wasm_plugin: bool,
+    /// Field whose value at or below the highest seen marks a message as a duplicate; null
+    /// when duplicates are kept
+    seq_field: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BufferConfig {
+    max_bytes: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ChartConfig {
+    window_seconds: u64,
+    max_points: usize,
+    initial_limit: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct AuthConfig {
+    admin_token_configured: bool,
+    api_token_configured: bool,
+    basic_auth_configured: bool,
+    jwt_configured: bool,
+    public_ui: bool,
+}
+
+/// Server request limits; 0 means the limit is disabled.
+#[derive(Serialize, ToSchema)]
+struct LimitsConfig {
+    max_concurrent_requests: usize,
+    request_timeout_secs: u64,
+    max_body_bytes: u64,
+    max_ws_clients: usize,
+}
+
+/// Printed by `--dry-run`.
+#[derive(Serialize)]
+struct DryRunSummary {
+    config: ConfigResponse,
+    server: ServerConfig,
+}
+
+/// Settings that only matter to the running server, so `/api/config` leaves them out.
+#[derive(Serialize)]
+struct ServerConfig {
+    listen: Vec<String>,
+    /// --namespaces-file entries and their upstream URLs, masked as in `config.upstream.url`
+    namespaces: BTreeMap<String, String>,
+    /// Listening on sockets passed by systemd instead of binding `--bind`
+    socket_activation: bool,
+    /// Address of the gRPC API; null when --grpc-port is unset
+    grpc_listen: Option<String>,
+    bind_best_effort: bool,
+    tls: bool,
+    ui_dir: Option<String>,
+    uplot_cdn: bool,
+    cors_origins: Vec<String>,
+    /// Origins allowed to open WebSockets: --allowed-origins, or `cors_origins` when unset
+    ws_origins: Vec<String>,
+    access_log_exclude: Vec<String>,
+    trust_proxy: bool,
+    /// Per-IP requests per minute; 0 when disabled
+    rate_limit_cheap: u32,
+    rate_limit_expensive: u32,
+    rate_limit_ws: u32,
+    shutdown_timeout_secs: u64,
+    /// 0 when /ws clients are not pinged
+    ws_ping_interval_secs: u64,
+    ws_pong_timeout_secs: u64,
+    /// 0 when /healthz ignores upstream outages
+    health_stale_secs: u64,
+    health_require_data: bool,
+}
+
+#[derive(Serialize, ToSchema, SimpleObject)]
+#[graphql(name = "Stats")]
+struct StatsResponse {
+    messages: usize,
+    buffer_bytes: usize,
+    buffer_limit_bytes: usize,
+    /// Last assigned `_seq`, or null when --inject-seq is disabled
+    seq: Option<u64>,
+    messages_per_second: f64,
+    unique_user_agents: usize,
+    /// Requests rejected by the server limits since startup
+    rejected_requests: RejectionCounts,
+    /// Live /ws connections, counted against --max-ws-clients
+    ws_client_count: usize,
+    /// Messages /ws clients skipped because they fell behind, since startup
+    ws_missed_messages: u64,
+    /// Messages dropped from /ws send queues that overflowed, since startup
+    ws_dropped_messages: u64,
+    /// /ws clients disconnected by --ws-strict for overflowing their send queue, since startup
+    ws_slow_disconnects: u64,
+    /// /ws clients disconnected by --ws-disconnect-on-lag, since startup
+    ws_lag_disconnects: u64,
+    /// Messages live subscribers (/ws, /api/events, /api/messages/stream?follow=1, gRPC and
+    /// GraphQL streams) skipped because they fell behind the broadcast, since startup
+    messages_dropped_lag: u64,
+    /// Upstream messages held back by --upstream-rate-limit-rps, since startup
+    upstream_throttled_messages: u64,
+    /// Text and binary frames sent to /ws clients, since startup
+    ws_messages_sent: u64,
+    /// Payload bytes of those frames
+    ws_bytes_sent: u64,
+    /// Live /ws connections with their lag and send queue counters
+    ws_clients: Vec<WsClientLag>,
+    /// SHA-256 of the --sign-key the messages' `_sig` is an HMAC with, in hex; null when
+    /// messages are not signed
+    sign_key_id: Option<String>,
+    /// --webhook-url deliveries since startup; null without it
+    webhook: Option<WebhookStats>,
+    /// --discord-webhook and --slack-webhook deliveries since startup; null without either
+    alerts: Option<AlertStats>,
+}
+
+/// One upstream and what it has contributed.
+#[derive(Serialize, ToSchema)]
+struct SourceInfo {
+    /// Host of the upstream URL
+    label: String,
+    /// Upstream URL with the password and query values masked
+    url: String,
+    connected: bool,
+    connected_since_ms: Option<u64>,
+    messages: u64,
+    bytes: u64,
+    last_message_ms: Option<u64>,
+    reconnects: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ProcessResponse {
+    uptime_secs: u64,
+    /// Resident set size, or null where it cannot be read
+    rss_bytes: Option<u64>,
+    pid: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LivenessResponse {
+    alive: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReadinessResponse {
+    /// Whether the upstream WebSocket has connected at least once since startup
+    ready: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HealthResponse {
+    healthy: bool,
+    /// Why the check failed; empty when healthy
+    reasons: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LogLevelRequest {
+    /// `trace`, `debug`, `info`, `warn` or `error`
+    level: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LogLevelResponse {
+    level: String,
+    /// The filter replaced: a level, or the `RUST_LOG` directives the server started with
+    previous: String,
+    /// Seconds until the startup filter comes back under --log-level-timeout-secs; null when the
+    /// level stays
+    reset_in_secs: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReconnectResponse {
+    /// Whether an upstream connection was open when the reconnect was requested
+    was_connected: bool,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "yurecollect"),
+    paths(
+        index,
+        list_messages,
+        stream_messages,
+        events,
+        poll,
+        sample_messages,
+        search_messages,
+        message_schema,
+        dead_letter_messages,
+        aggregate,
+        config,
+        stats,
+        ua_stats,
+        devices,
+        gaps,
+        spectrum,
+        sample_rate,
+        pga,
+        noise,
+        intensity,
+        shake_events,
+        event_recording,
+        process_info,
+        upstream_status,
+        sources,
+        livez,
+        readyz,
+        healthz,
+        set_log_level,
+        admin_reconnect,
+        admin_ws_clients,
+        graphql::execute,
+        graphql::sdl,
+        graphql::subscribe,
+        ws_handler,
+        ws_source_handler,
+    ),
+    components(schemas(Envelope, ConfirmedEvent))
+)]
+struct ApiDoc;
+
+fn main() {
+    // WebSocket URL comes from the CLI arg or the WS_URL env var
+    let args = Args::parse();
+    if args.build_info {
+        println!("{}", build_info());
+        return;
+    }
+    // Installed before forking, so the starting process reports the way the daemon logs; the
+    // other subcommands write to the terminal themselves
+    let log_level = matches!(args.command, None | Some(Command::Watch { .. })).then(|| {
+        LogLevel::init((args.log_level_timeout_secs > 0).then(|| Duration::from_secs(args.log_level_timeout_secs)))
+    });
+    // Forking is only safe before the runtime starts its worker threads
+    if args.daemonize && args.command.is_none() {
+        daemon::detach(&args);
+    }
+    // Edits the environment, so it has to happen before the runtime's threads exist
+    let activated = systemd::listeners();
+    tokio::runtime::Runtime::new()
+        .expect("failed to start the tokio runtime")
+        .block_on(run(args, activated, log_level));
+}
+
+async fn run(args: Args, activated: Result<Vec<std::net::TcpListener>, String>, log_level: Option<LogLevel>) {
+    match args.command {
+        Some(Command::Completions { shell }) => {
+            let mut cmd = Args::command();
+            let name = cmd.get_name().to_string();
+            clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+            return;
+        }
+        Some(Command::Man) => {
+            print!("{}", MAN_PAGE);
+            return;
+        }
+        Some(Command::Watch { server, token, format, filter }) => {
+            if let Err(err) = watch::run(&server, token.as_deref(), format, filter.as_deref()).await {
+                tracing::error!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Tui { server, token }) => {
+            if let Err(err) = tui::run(&server, token.as_deref()).await {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let log_level = log_level.expect("installed for the server");
+    trace_context::init();
+    // Configuration problems are collected so they can all be reported at once
+    let mut config_errors = Vec::new();
+    let namespaces = match &args.namespaces_file {
+        Some(path) => namespace::load(path).unwrap_or_else(|err| {
+            config_errors.push(format!("Invalid --namespaces-file: {}", err));
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    for entry in &namespaces {
+        if let Err(err) = check_upstream_url(&entry.url) {
+            config_errors.push(format!("Invalid upstream URL of namespace {}: {}", entry.name, err));
+        }
+    }
+    if args.namespaces_file.is_none()
+        && let Err(err) = check_upstream_url(args.url())
+    {
+        config_errors.push(format!("Invalid upstream URL: {}", err));
+    }
+    // Each namespace runs with a copy of the arguments naming its own upstream, recording
+    // under its own subdirectory of --record-dir
+    let configs: Vec<Args> = match namespaces.is_empty() {
+        true => vec![args.clone()],
+        false => namespaces
+            .iter()
+            .map(|entry| Args {
+                url: Some(entry.url.clone()),
+                namespace: Some(entry.name.clone()),
+                record_dir: args.record_dir.as_ref().map(|dir| dir.join(&entry.name)),
+                ..args.clone()
+            })
+            .collect(),
+    };
+    let rate_limits = RateLimits {
+        cheap: RateLimiter::new(args.rate_limit_cheap),
+        expensive: RateLimiter::new(args.rate_limit_expensive),
+        ws: RateLimiter::new(args.rate_limit_ws),
+        auth_failures: RateLimiter::with_burst(AUTH_FAILURES_PER_MINUTE, AUTH_FAILURES_PER_MINUTE),
+    };
+    let api_tokens = ApiTokens::load(&args.api_tokens, args.api_token_file.as_deref())
+        .unwrap_or_else(|err| {
+            config_errors.push(format!("Invalid --api-token-file: {}", err));
+            ApiTokens::default()
+        });
+    let mut parse_nets = |flag: &str, raw: &[String]| -> Vec<ipnet::IpNet> {
+        raw.iter()
+            .filter_map(|net| {
+                ipfilter::parse_net(net)
+                    .map_err(|err| config_errors.push(format!("Invalid {}: {}", flag, err)))
+                    .ok()
+            })
+            .collect()
+    };
+    let allow_nets = parse_nets("--allow-ip", &args.allow_ips);
+    let block_nets = parse_nets("--block-ip", &args.block_ips);
+    let ip_filter = IpFilter::load(allow_nets, block_nets, args.allow_ip_file.clone(), args.block_ip_file.clone())
+        .unwrap_or_else(|err| {
+            config_errors.push(format!("Invalid --allow-ip-file/--block-ip-file: {}", err));
+            IpFilter::load(Vec::new(), Vec::new(), None, None).expect("empty lists load")
+        });
+    let basic_auth = load_basic_auth(&args).unwrap_or_else(|err| {
+        config_errors.push(format!("Invalid Basic auth credentials: {}", err));
+        None
+    });
+    let jwt = args.jwt_secret.as_deref().and_then(|secret| {
+        JwtVerifier::new(secret)
+            .map_err(|err| config_errors.push(format!("Invalid --jwt-secret: {}", err)))
+            .ok()
+    });
+    let signer = args.sign_key.as_deref().and_then(|key| {
+        MessageSigner::new(key)
+            .map(Arc::new)
+            .map_err(|err| config_errors.push(format!("Invalid --sign-key: {}", err)))
+            .ok()
+    });
+    if args.sta_lta && args.sta_window >= args.lta_window {
+        config_errors.push("Invalid --sta-window: must be shorter than --lta-window".to_string());
+    }
+    if args.sta_lta && args.detrigger_ratio >= args.trigger_ratio {
+        config_errors.push("Invalid --detrigger-ratio: must be lower than --trigger-ratio".to_string());
+    }
+    let webhook = args.webhook_url.as_deref().and_then(|url| {
+        if args.webhook_threshold.is_none() && !args.sta_lta {
+            config_errors.push("Invalid --webhook-url: nothing to notify of without --webhook-threshold or --sta-lta".to_string());
+        }
+        let url = webhook::parse_url(url)
+            .map_err(|err| config_errors.push(format!("Invalid --webhook-url: {}", err)))
+            .ok();
+        let signer = args.webhook_secret.as_deref().and_then(|key| {
+            MessageSigner::new(key)
+                .map_err(|err| config_errors.push(format!("Invalid --webhook-secret: {}", err)))
+                .ok()
+        });
+        Some(WebhookConfig {
+            url: url?,
+            signer,
+            instance: args.instance_name.clone(),
+            threshold: args.webhook_threshold,
+            cooldown: args.webhook_cooldown,
+        })
+    });
+    let chat_targets: Vec<(ChatService, reqwest::Url)> = [
+        (ChatService::Discord, "--discord-webhook", &args.discord_webhook),
+        (ChatService::Slack, "--slack-webhook", &args.slack_webhook),
+    ]
+    .into_iter()
+    .filter_map(|(service, flag, url)| {
+        let url = alerts::parse_url(url.as_deref()?)
+            .map_err(|err| config_errors.push(format!("Invalid {}: {}", flag, err)))
+            .ok()?;
+        Some((service, url))
+    })
+    .collect();
+    let public_url = args.public_url.as_deref().and_then(|url| {
+        webhook::parse_url(url)
+            .map(|url| url.as_str().trim_end_matches('/').to_string())
+            .map_err(|err| config_errors.push(format!("Invalid --public-url: {}", err)))
+            .ok()
+    });
+    let audit = match &args.audit_log {
+        Some(path) => AuditLog::open(path).unwrap_or_else(|err| {
+            config_errors.push(format!("Invalid --audit-log: {}", err));
+            AuditLog::default()
+        }),
+        None => AuditLog::default(),
+    };
+    if let Some(dir) = &args.ui_dir
+        && let Err(err) = check_ui_dir(dir)
+    {
+        config_errors.push(format!("Invalid --ui-dir: {}", err));
+    }
+    let activated = activated.unwrap_or_else(|err| {
+        config_errors.push(format!("Invalid systemd socket activation: {}", err));
+        Vec::new()
+    });
+    // Every namespace gets its own script or plugin instance and recordings
+    let mut transformers = Vec::new();
+    let mut recorders = Vec::new();
+    for config in &configs {
+        match load_transformer(config) {
+            Ok(transformer) => transformers.push(transformer),
+            // The same file for every namespace, so one report is enough
+            Err(err) => {
+                config_errors.push(err);
+                break;
+            }
+        }
+        let recorder = config.record_dir.as_deref().and_then(|dir| {
+            Recorder::new(dir, config.record_pre_trigger, config.record_post_roll)
+                .map(|recorder| Arc::new(RwLock::new(recorder)))
+                .map_err(|err| config_errors.push(format!("Invalid --record-dir: {}", err)))
+                .ok()
+        });
+        recorders.push(recorder);
+    }
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => match tls::load(cert, key).await {
+            Ok(config) => Some(config),
+            Err(err) => {
+                config_errors.push(format!("Invalid TLS certificate or key: {}", err));
+                None
+            }
+        },
+        _ => None,
+    };
+    if !config_errors.is_empty() {
+        for err in &config_errors {
+            tracing::error!("{}", err);
+        }
+        daemon::failed(2, &config_errors.join("\n"));
+        std::process::exit(2);
+    }
+    if args.dry_run {
+        let summary = dry_run_summary(
+            &args,
+            &namespaces,
+            &activated,
+            !api_tokens.is_empty(),
+            basic_auth.is_some(),
+            tls.is_some(),
+        );
+        println!("{}", serde_json::to_string_pretty(&summary).expect("summary serializes"));
+        return;
+    }
+    if let (Some(config), Some(cert), Some(key)) = (&tls, &args.tls_cert, &args.tls_key) {
+        tls::reload_on_sighup(config.clone(), cert.clone(), key.clone());
+    }
+    if let Some(path) = &args.log_file {
+        logrotate::spawn(LogRotation::new(path.clone(), args.log_max_size_bytes, args.log_max_files));
+    }
+    let ip_filter = Arc::new(ip_filter);
+    if ip_filter.has_files() {
+        ipfilter::reload_on_sighup(ip_filter.clone());
+    }
+    let uplot_cdn = args.cdn || !assets::uplot_vendored();
+    if uplot_cdn && !args.cdn {
+        tracing::warn!("uPlot {} is not embedded in this build; the web UI will load it from unpkg.com", assets::UPLOT_VERSION);
+    }
+
+    // Shared by every namespace: one set of limits, credentials and listeners
+    let shutdown = CancellationToken::new();
+    let ws_sessions = TaskTracker::new();
+    let webhook = webhook.map(|config| Arc::new(Notifier::spawn(config)));
+    let alerts = (!chat_targets.is_empty()).then(|| {
+        Arc::new(Alerter::spawn(AlertConfig {
+            targets: chat_targets,
+            instance: args.instance_name.clone(),
+            min_intensity: args.alert_min_intensity,
+            public_url,
+        }))
+    });
+    let shared = SharedState {
+        shutdown: shutdown.clone(),
+        ws_sessions: ws_sessions.clone(),
+        signer,
+        webhook,
+        alerts,
+        rate_limits: Arc::new(rate_limits),
+        request_limits: Arc::new(RequestLimits::new(
+            args.max_concurrent_requests,
+            args.request_timeout_secs,
+            args.max_body_bytes,
+            args.max_ws_clients,
+        )),
+        api_tokens: Arc::new(api_tokens),
+        ip_filter,
+        basic_auth: basic_auth.map(Arc::new),
+        jwt: jwt.map(Arc::new),
+        audit: Arc::new(audit),
+        log_level: Arc::new(log_level),
+        uplot_cdn,
+    };
+
+    let mut states = Vec::new();
+    let mut upstreams = Vec::new();
+    for ((config, transformer), recorder) in configs.into_iter().zip(transformers).zip(recorders) {
+        let state = AppState::new(config, recorder, &shared);
+        if state.recorder.is_some() {
+            tokio::spawn(close_recordings(state.clone()));
+        }
+        upstreams.push(run_upstream_ws(state.config.url().to_string(), state.clone(), transformer));
+        states.push(state);
+    }
+
+    // Spawn HTTP server for web UI
+    let states_for_http = states.clone();
+    let mut http_task = tokio::spawn(async move { run_http_server(states_for_http, tls, activated).await });
+
+    // Connect to every upstream websocket and stream messages
+    let mut ws_task = tokio::spawn(async move {
+        futures_util::future::join_all(upstreams).await;
+    });
+
+    tokio::select! {
+        _ = shutdown_signal() => {
+            let deadline_secs = args.shutdown_timeout_secs;
+            tracing::info!("shutting down, draining connections for up to {}s", deadline_secs);
+            shutdown.cancel();
+            ws_sessions.close();
+            let drained = async {
+                let _ = (&mut http_task).await;
+                let _ = (&mut ws_task).await;
+                ws_sessions.wait().await;
+                // Nothing is ingested any more, so the post-roll would only be silence
+                for state in &states {
+                    close_recording(state).await;
+                }
+            };
+            if tokio::time::timeout(Duration::from_secs(deadline_secs), drained).await.is_err() {
+                tracing::warn!("connections still open after {}s, forcing exit", deadline_secs);
+                http_task.abort();
+                ws_task.abort();
+                std::process::exit(EXIT_FORCED_SHUTDOWN);
+            }
+            tracing::info!("shutdown complete");
+        }
+        res = &mut http_task => {
+            ws_task.abort();
+            match res {
+                Ok(Ok(())) => tracing::error!("HTTP task ended, shutting down"),
+                Ok(Err(err)) => {
+                    tracing::error!("HTTP server error, shutting down: {}", err);
+                    daemon::failed(1, &format!("HTTP server error: {}", err));
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    tracing::error!("HTTP task failed, shutting down: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ = &mut ws_task => {
+            tracing::error!("upstream task ended, shutting down");
+            http_task.abort();
+        }
+    }
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM where available (e.g. `docker stop`, Kubernetes).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Identifies the exact build; the fields besides the version are set by build.rs.
+fn build_info() -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": env!("YURECOLLECT_GIT_COMMIT"),
+        "build_date": env!("YURECOLLECT_BUILD_DATE"),
+        "rustc_version": env!("YURECOLLECT_RUSTC_VERSION"),
+    })
+}
+
+/// Accepts absolute `ws://` and `wss://` URLs with a host.
+fn check_upstream_url(url: &str) -> Result<(), String> {
+    let uri: axum::http::Uri = url.parse().map_err(|e| format!("{}: {}", redact_url(url), e))?;
+    if !matches!(uri.scheme_str(), Some("ws" | "wss")) {
+        return Err(format!("{}: scheme must be ws or wss", redact_url(url)));
+    }
+    if uri.host().is_none_or(str::is_empty) {
+        return Err(format!("{}: missing host", redact_url(url)));
+    }
+    Ok(())
+}
+
+/// Everything `--dry-run` resolved: the `/api/config` view plus the server-side settings.
+fn dry_run_summary(
+    args: &Args,
+    namespaces: &[NamespaceEntry],
+    activated: &[std::net::TcpListener],
+    api_token_configured: bool,
+    basic_auth_configured: bool,
+    tls: bool,
+) -> DryRunSummary {
+    DryRunSummary {
+        config: config_response(args, api_token_configured, basic_auth_configured),
+        server: ServerConfig {
+            listen: if activated.is_empty() {
+                listen_addrs(&args.bind, args.bind_ipv6)
+                    .into_iter()
+                    .map(|(addr, _)| addr.to_string())
+                    .collect()
+            } else {
+                activated
+                    .iter()
+                    .filter_map(|listener| listener.local_addr().ok())
+                    .map(|addr| addr.to_string())
+                    .collect()
+            },
+            namespaces: namespaces
+                .iter()
+                .map(|entry| (entry.name.clone(), redact_url(&entry.url)))
+                .collect(),
+            socket_activation: !activated.is_empty(),
+            grpc_listen: grpc::listen_addr(args).map(|addr| addr.to_string()),
+            bind_best_effort: args.bind_best_effort,
+            tls,
+            ui_dir: args.ui_dir.as_ref().map(|dir| dir.display().to_string()),
+            uplot_cdn: args.cdn || !assets::uplot_vendored(),
+            cors_origins: args.cors_origins.clone(),
+            ws_origins: args.ws_origins().to_vec(),
+            access_log_exclude: args.access_log_exclude.clone(),
+            trust_proxy: args.trust_proxy,
+            rate_limit_cheap: args.rate_limit_cheap,
+            rate_limit_expensive: args.rate_limit_expensive,
+            rate_limit_ws: args.rate_limit_ws,
+            shutdown_timeout_secs: args.shutdown_timeout_secs,
+            ws_ping_interval_secs: args.ws_ping_interval_secs,
+            ws_pong_timeout_secs: args.ws_pong_timeout_secs,
+            health_stale_secs: args.health_stale_secs,
+            health_require_data: args.health_require_data,
+        },
+    }
+}
+
+/// Fills the paths the page is served under and the uPlot URLs into an index page template.
+fn render_index(template: &str, config: &Args, uplot: &UplotUrls) -> String {
+    template
+        .replace("{{BASE_PATH}}", &config.ui_path())
+        .replace("{{API_PATH}}", &config.api_path())
+        .replace("{{WS_PATH}}", &config.ws_path())
+        .replace("{{UPLOT_JS}}", &uplot.js)
+        .replace("{{UPLOT_CSS}}", &uplot.css)
+}
+
+/// Requires `--ui-dir` to be a directory containing `index.html`.
+fn check_ui_dir(dir: &std::path::Path) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    if !dir.join("index.html").is_file() {
+        return Err(format!("{} has no index.html", dir.display()));
+    }
+    Ok(())
+}
+
+/// Loads the --transform-script or --wasm-plugin, if any.
+fn load_transformer(args: &Args) -> Result<Option<Transformer>, String> {
+    match (&args.transform_script, &args.wasm_plugin) {
+        (Some(path), _) => LuaScript::load(path)
+            .map(|script| Some(Transformer::Lua(script)))
+            .map_err(|err| format!("Invalid --transform-script: {}", err)),
+        (None, Some(path)) => WasmPlugin::load(path)
+            .map(|plugin| Some(Transformer::Wasm(Box::new(plugin))))
+            .map_err(|err| format!("Invalid --wasm-plugin: {}", err)),
+        (None, None) => Ok(None),
+    }
+}
+
+fn load_basic_auth(args: &Args) -> Result<Option<BasicCredentials>, String> {
+    let pair = match (&args.basic_auth, &args.basic_auth_file) {
+        (Some(pair), _) => pair.clone(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+        (None, None) => return Ok(None),
+    };
+    BasicCredentials::parse(&pair).map(Some)
+}
+
+async fn run_upstream_ws(url: String, state: AppState, mut transformer: Option<Transformer>) {
+    let mut backoff = Duration::from_secs(1);
+    let max_backoff = Duration::from_secs(30);
+    let connect_timeout_ms = state.config.upstream_connect_timeout_ms;
+    let read_timeout_ms = state.config.upstream_read_timeout_ms;
+    let read_timeout = (read_timeout_ms > 0).then(|| Duration::from_millis(read_timeout_ms));
+    let ping_interval = match state.config.upstream_ping_interval_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let pong_timeout = Duration::from_secs(state.config.upstream_pong_timeout_secs);
+    // As in /api/config, without the password and query values
+    let audit_source: Arc<str> = redact_url(&url).into();
+
+    while !state.shutdown.is_cancelled() {
+        // The OS-level TCP connect timeout can take minutes; cap the whole handshake instead
+        let connect = tokio::time::timeout(Duration::from_millis(connect_timeout_ms), connect_async(&url));
+        let connected = tokio::select! {
+            result = connect => match result {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(_) => {
+                    tracing::warn!(url = %audit_source, "connection to the upstream timed out after {}ms", connect_timeout_ms);
+                    Err(format!("timed out after {}ms", connect_timeout_ms))
+                }
+            },
+            _ = state.shutdown.cancelled() => return,
+            _ = state.reconnect.notified() => {
+                tracing::info!(url = %audit_source, "manual reconnect requested, restarting the connection");
+                backoff = Duration::from_secs(1);
+                continue;
+            }
+        };
+        let (ws_stream, _resp) = match connected {
+            Ok(pair) => {
+                tracing::info!(url = %audit_source, "connected to upstream");
+                state.upstream.write().await.record(ConnectionEventKind::Connected, None);
+                state.has_ever_connected.store(true, Ordering::Relaxed);
+                if let Some(seq_filter) = &state.seq_filter {
+                    seq_filter.write().await.connected(&audit_source);
+                }
+                backoff = Duration::from_secs(1);
+                pair
+            }
+            Err(err) => {
+                tracing::warn!(url = %audit_source, "failed to connect to upstream (retry in {:?}): {}", backoff, err);
+                state
+                    .upstream
+                    .write()
+                    .await
+                    .record(ConnectionEventKind::ConnectFailed, Some(err));
+                if sleep_or_reconnect(&state, backoff).await {
+                    backoff = Duration::from_secs(1);
+                } else {
+                    backoff = std::cmp::min(backoff * 2, max_backoff);
+                }
+                continue;
+            }
+        };
+
+        let (mut write, mut read) = ws_stream.split();
+        let mut manual_reconnect = false;
+        let mut detail = None;
+        // Any received frame, pings and pongs included, pushes the stall deadline back
+        let mut stall_deadline = read_timeout.map(|t| Instant::now() + t);
+        let mut pinger = ping_interval.map(|every| {
+            let mut pinger = tokio::time::interval_at(Instant::now() + every, every);
+            pinger.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            pinger
+        });
+        // Set while a ping is waiting for its pong
+        let mut pong_deadline: Option<Instant> = None;
+        // Messages --upstream-seq-field marked as already seen, since they were last logged
+        let mut duplicates: u64 = 0;
+        // Messages --outlier-filter holds back for their device's next sample
+        let mut held: HashMap<String, HeldMessage> = HashMap::new();
+        let mut throttled_us = state.ingest_throttled_us.load(Ordering::Relaxed);
+
+        loop {
+            // While --upstream-rate-limit-rps held a message back the upstream was not read, pongs
+            // included, so that time does not count towards the pong and stall deadlines
+            let now_throttled_us = state.ingest_throttled_us.load(Ordering::Relaxed);
+            if now_throttled_us > throttled_us {
+                let waited = Duration::from_micros(now_throttled_us - throttled_us);
+                throttled_us = now_throttled_us;
+                pong_deadline = pong_deadline.map(|deadline| deadline + waited);
+                stall_deadline = stall_deadline.map(|deadline| deadline + waited);
+            }
+            let item = tokio::select! {
+                item = read.next() => {
+                    stall_deadline = read_timeout.map(|t| Instant::now() + t);
+                    item
+                }
+                _ = tokio::time::sleep_until(stall_deadline.unwrap_or_else(Instant::now)), if stall_deadline.is_some() => {
+                    tracing::warn!(url = %audit_source, "upstream stalled for {}ms, reconnecting", read_timeout_ms);
+                    detail = Some(format!("stalled for {}ms", read_timeout_ms));
+                    break;
+                }
+                _ = async { pinger.as_mut().unwrap().tick().await }, if pinger.is_some() => {
+                    if write.send(UpstreamMessage::Ping(Vec::new())).await.is_err() {
+                        detail = Some("failed to send ping".to_string());
+                        break;
+                    }
+                    tracing::debug!(url = %audit_source, "sent ping to upstream");
+                    pong_deadline.get_or_insert_with(|| Instant::now() + pong_timeout);
+                    continue;
+                }
+                _ = tokio::time::sleep_until(pong_deadline.unwrap_or_else(Instant::now)), if pong_deadline.is_some() => {
+                    tracing::warn!(url = %audit_source, "no pong from upstream within {:?}, reconnecting", pong_timeout);
+                    detail = Some(format!("no pong within {}s", pong_timeout.as_secs()));
+                    break;
+                }
+                _ = tokio::time::sleep_until(held.values().map(|h| h.deadline).min().unwrap_or_else(Instant::now)), if !held.is_empty() => {
+                    release_held(&state, &mut held, false).await;
+                    continue;
+                }
+                _ = state.shutdown.cancelled() => {
+                    release_held(&state, &mut held, true).await;
+                    let frame = UpstreamCloseFrame {
+                        code: UpstreamCloseCode::Away,
+                        reason: "collector shutting down".into(),
+                    };
+                    let _ = write.send(UpstreamMessage::Close(Some(frame))).await;
+                    tracing::info!(url = %audit_source, "closed upstream connection");
+                    return;
+                }
+                _ = state.reconnect.notified() => {
+                    tracing::info!(url = %audit_source, "manual reconnect requested, closing upstream connection");
+                    let _ = write.send(UpstreamMessage::Close(None)).await;
+                    manual_reconnect = true;
+                    detail = Some("manual reconnect".to_string());
+                    break;
+                }
+            };
+            let Some(item) = item else { break };
+            match item {
+                Ok(msg) => {
+                    if msg.is_text() || msg.is_binary() {
+                        state.rate.write().await.record();
+                        state.upstream.write().await.record_message(msg.len());
+                        state.audit.record(AuditEvent::Ingest {
+                            source: audit_source.clone(),
+                            bytes: msg.len(),
+                        });
+                    }
+                    if msg.is_text() {
+                        let text = msg.into_text().unwrap_or_default();
+                        // Judged on the message as the upstream numbered it, before any rewrite
+                        if let Some(seq_filter) = &state.seq_filter {
+                            let mut seq_filter = seq_filter.write().await;
+                            let max_seen = seq_filter.max_seen(&audit_source);
+                            match seq_filter.admit(&audit_source, &text) {
+                                Admission::Duplicate => {
+                                    duplicates += 1;
+                                    continue;
+                                }
+                                Admission::Reset { max_seen } => tracing::warn!(
+                                    url = %audit_source,
+                                    max_seen,
+                                    "upstream started numbering messages again; counting from there"
+                                ),
+                                Admission::New => {}
+                            }
+                            if duplicates > 0 {
+                                log_duplicates(&audit_source, duplicates, max_seen);
+                                duplicates = 0;
+                            }
+                        }
+                        let text = match &mut transformer {
+                            Some(transformer) => match transformer.apply(text) {
+                                Transformed::Message(text) => text,
+                                Transformed::Dropped => continue,
+                                Transformed::Failed { text, error } => {
+                                    dead_letter(&state, &text, format!("transform failed: {}", error)).await;
+                                    text
+                                }
+                            },
+                            None => text,
+                        };
+
+                        // Print raw message to stdout
+                        println!("{}", text);
+
+                        // Try to parse JSON to validate, then tell form data and CSV from garbage
+                        let parsed = serde_json::from_str::<Value>(&text);
+                        let format = MessageFormat::detect(&text, parsed.is_ok());
+                        if let (Err(e), MessageFormat::Raw) = (&parsed, format) {
+                            tracing::warn!("JSON parse error: {}", e);
+                            dead_letter(&state, &text, format!("JSON parse error: {}", e)).await;
+                        }
+                        let message = ScreenedMessage {
+                            text,
+                            value: parsed.ok(),
+                            format,
+                            outliers: Vec::new(),
+                        };
+                        for message in screen_outliers(&state, &mut held, message).await {
+                            ingest_screened(&state, message).await;
+                        }
+                    } else if msg.is_binary() {
+                        let bin = msg.into_data();
+                        println!("<binary message: {} bytes>", bin.len());
+                        let text = format!("<binary {} bytes>", bin.len());
+                        throttle_ingest(&state).await;
+                        let received_at_ms = now_ms();
+                        let (id, evicted) = state
+                            .buffer
+                            .write()
+                            .await
+                            .push(text.clone(), None, MessageFormat::Raw, None, None, received_at_ms);
+                        audit_eviction(&state, evicted);
+                        let _ = state.tx.send(LiveMessage {
+                            id,
+                            received_at_ms,
+                            seq: None,
+                            format: MessageFormat::Raw,
+                            t_corrected: None,
+                            intensity: None,
+                            text,
+                            user_agents: Arc::new([]),
+                            encoded: Arc::default(),
+                        });
+                    } else if msg.is_close() {
+                        tracing::warn!(url = %audit_source, "upstream WebSocket closed, reconnecting");
+                        break;
+                    } else if msg.is_pong() {
+                        tracing::debug!(url = %audit_source, "pong from upstream");
+                        pong_deadline = None;
+                    } else {
+                        // ignore
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(url = %audit_source, "upstream read error (reconnect in {:?}): {}", backoff, err);
+                    break;
+                }
+            }
+        }
+
+        release_held(&state, &mut held, true).await;
+        if duplicates > 0 {
+            let max_seen = match &state.seq_filter {
+                Some(seq_filter) => seq_filter.read().await.max_seen(&audit_source),
+                None => None,
+            };
+            log_duplicates(&audit_source, duplicates, max_seen);
+        }
+        state
+            .upstream
+            .write()
+            .await
+            .record(ConnectionEventKind::Disconnected, detail);
+
+        if manual_reconnect || sleep_or_reconnect(&state, backoff).await {
+            backoff = Duration::from_secs(1);
+        } else {
+            backoff = std::cmp::min(backoff * 2, max_backoff);
+        }
+    }
+}
+
+/// Ingests an upstream text message once transformed and parsed: per-device processing,
+/// `_seq` and signing, then the buffer and live subscribers.
+async fn ingest_text(state: &AppState, mut text: String, mut parsed: Option<Value>, format: MessageFormat) {
+    let mut user_agents = Vec::new();
+    let mut t_corrected = None;
+    let mut intensity = None;
+    // Filtered first, so everything after sees and keeps the filtered values
+    if let Some(value) = &mut parsed
+        && apply_highpass(state, value).await
+    {
+        text = value.to_string();
+    }
+    if let Some(value) = &parsed {
+        record_user_agents(state, value).await;
+        user_agents = message_user_agents(value);
+        t_corrected = corrected_sample_time(state, value).await;
+        record_pga(state, value).await;
+        record_noise(state, value).await;
+        intensity = record_intensity(state, value).await;
+        notify_intensity(state, value).await;
+        detect_shaking(state, value).await;
+    }
+
+    // Tag JSON objects with a global sequence number for gap detection, and sign the message as
+    // stored, `_seq` included
+    let (text, seq) = match parsed {
+        Some(Value::Object(mut obj)) if state.config.inject_seq || state.signer.is_some() => {
+            let seq = state.config.inject_seq.then(|| {
+                let seq = state.seq.fetch_add(1, Ordering::Relaxed) + 1;
+                obj.insert("_seq".to_string(), Value::from(seq));
+                seq
+            });
+            let text = match &state.signer {
+                Some(signer) => signer.sign_object(obj),
+                None => Value::Object(obj).to_string(),
+            };
+            (text, seq)
+        }
+        Some(_) => {
+            if let Some(signer) = &state.signer {
+                signer.warn_unsigned();
+            }
+            (text, None)
+        }
+        None => match &state.signer {
+            Some(signer) => (signer.sign_text(&text), None),
+            None => (text, None),
+        },
+    };
+
+    throttle_ingest(state).await;
+    // Store message in in-memory buffer capped at ~1GB
+    let received_at_ms = now_ms();
+    let (id, evicted) = state
+        .buffer
+        .write()
+        .await
+        .push(text.clone(), seq, format, t_corrected, intensity, received_at_ms);
+    audit_eviction(state, evicted);
+    if let Some(recorder) = &state.recorder {
+        let source = url_host(state.config.url());
+        let line = Envelope::new(id, received_at_ms, source, format, t_corrected, intensity, &text).to_json();
+        match recorder.write().await.record(line, received_at_ms) {
+            Ok(closed) => log_recording(closed),
+            Err(err) => tracing::error!("failed to write an event recording: {}", err),
+        }
+    }
+
+    // Publish to subscribers
+    let _ = state.tx.send(LiveMessage {
+        id,
+        received_at_ms,
+        seq,
+        format,
+        t_corrected,
+        intensity,
+        text,
+        user_agents: user_agents.into(),
+        encoded: Arc::default(),
+    });
+}
+
+/// How long --outlier-filter holds a message with a suspect sample for the device's next one.
+const OUTLIER_HOLD: Duration = Duration::from_secs(1);
+
+/// An upstream text message on its way to `ingest_text`, with the indices of the samples
+/// --outlier-filter rejected.
+struct ScreenedMessage {
+    text: String,
+    value: Option<Value>,
+    format: MessageFormat,
+    outliers: Vec<usize>,
+}
+
+/// A message held back because one of its samples is suspect, until the device's next sample
+/// tells whether it was an isolated spike.
+struct HeldMessage {
+    message: ScreenedMessage,
+    // Index of the suspect sample
+    suspect: usize,
+    deadline: Instant,
+}
+
+/// Runs a message's samples through --outlier-filter. Returns the messages ready to ingest, in
+/// order: the held ones its samples settled, then the message itself, unless one of its samples
+/// is suspect now, in which case it is held under that device instead.
+async fn screen_outliers(
+    state: &AppState,
+    held: &mut HashMap<String, HeldMessage>,
+    mut message: ScreenedMessage,
+) -> Vec<ScreenedMessage> {
+    let (Some(filter), Some(value)) = (&state.outliers, &message.value) else {
+        return vec![message];
+    };
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let samples: Vec<(usize, String, [f64; 3])> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let (ua, accel) = sample_acceleration(item)?;
+            Some((index, ua.to_string(), accel))
+        })
+        .collect();
+    let mut ready = Vec::new();
+    // Suspects whose device has no later sample in the message yet
+    let mut suspects: Vec<(String, usize)> = Vec::new();
+    let mut rejected = Vec::new();
+    {
+        let mut filter = filter.write().await;
+        for (index, ua, accel) in samples {
+            let screened = filter.screen(&ua, accel);
+            if let Some(spike) = screened.previous_spike {
+                if let Some(pos) = suspects.iter().position(|(suspect_ua, _)| *suspect_ua == ua) {
+                    let (_, suspect) = suspects.remove(pos);
+                    if spike {
+                        message.outliers.push(suspect);
+                        rejected.push(ua.clone());
+                    }
+                } else if let Some(mut earlier) = held.remove(&ua) {
+                    if spike {
+                        earlier.message.outliers.push(earlier.suspect);
+                        rejected.push(ua.clone());
+                    }
+                    ready.push(earlier.message);
+                }
+            }
+            match screened.verdict {
+                Verdict::Pass => {}
+                Verdict::Outlier => {
+                    message.outliers.push(index);
+                    rejected.push(ua);
+                }
+                Verdict::Suspect => suspects.push((ua, index)),
+            }
+        }
+        // A message cannot wait for two devices at once; their suspects are let through
+        if suspects.len() > 1 {
+            for (ua, _) in suspects.drain(..) {
+                filter.release(&ua);
+            }
+        }
+    }
+    if !rejected.is_empty() {
+        let now = now_ms();
+        let mut ua_stats = state.ua_stats.write().await;
+        for ua in rejected {
+            ua_stats
+                .entry(ua)
+                .or_insert(UaStat { count: 0, last_seen_ms: now, outliers: 0 })
+                .outliers += 1;
+        }
+    }
+    match suspects.pop() {
+        Some((ua, suspect)) => {
+            let deadline = Instant::now() + OUTLIER_HOLD;
+            held.insert(ua, HeldMessage { message, suspect, deadline });
+        }
+        None => ready.push(message),
+    }
+    ready
+}
+
+/// Lets held messages through without their device's next sample: those past their deadline,
+/// or all of them with `all`, when the connection goes away.
+async fn release_held(state: &AppState, held: &mut HashMap<String, HeldMessage>, all: bool) {
+    let Some(filter) = &state.outliers else {
+        return;
+    };
+    let now = Instant::now();
+    let due: Vec<String> = held
+        .iter()
+        .filter(|(_, message)| all || message.deadline <= now)
+        .map(|(ua, _)| ua.clone())
+        .collect();
+    let mut released = Vec::with_capacity(due.len());
+    {
+        let mut filter = filter.write().await;
+        for ua in due {
+            filter.release(&ua);
+            released.extend(held.remove(&ua));
+        }
+    }
+    released.sort_by_key(|message| message.deadline);
+    for message in released {
+        ingest_screened(state, message.message).await;
+    }
+}
+
+/// Marks or removes the samples --outlier-filter rejected, by --outlier-mode, and ingests what
+/// is left of the message.
+async fn ingest_screened(state: &AppState, message: ScreenedMessage) {
+    let ScreenedMessage {
+        mut text,
+        mut value,
+        format,
+        mut outliers,
+    } = message;
+    if let Some(value) = &mut value
+        && !outliers.is_empty()
+    {
+        match (state.config.outlier_mode, &mut *value) {
+            (OutlierMode::Flag, value) => {
+                let items = match value {
+                    Value::Array(items) => items.as_mut_slice(),
+                    other => std::slice::from_mut(other),
+                };
+                for index in &outliers {
+                    if let Some(obj) = items.get_mut(*index).and_then(Value::as_object_mut) {
+                        obj.insert("outlier".to_string(), Value::Bool(true));
+                    }
+                }
+            }
+            (OutlierMode::Drop, Value::Array(items)) => {
+                outliers.sort_unstable();
+                for index in outliers.iter().rev() {
+                    items.remove(*index);
+                }
+                if items.is_empty() {
+                    return;
+                }
+            }
+            // The message was the one sample
+            (OutlierMode::Drop, _) => return,
+        }
+        text = value.to_string();
+    }
+    ingest_text(state, text, value, format).await;
+}
+
+/// Waits for a token of --upstream-rate-limit-rps before a message is ingested. The upstream is
+/// not read meanwhile, so a flood backs up into the connection instead of the buffer; the wait
+/// is added to `ingest_throttled_us` so the read loop does not count it against the upstream.
+async fn throttle_ingest(state: &AppState) {
+    let Some(bucket) = &state.ingest_bucket else {
+        return;
+    };
+    let mut throttled_since = None;
+    loop {
+        let wait = match bucket.write().await.try_consume(1) {
+            Ok(()) => break,
+            Err(wait) => wait,
+        };
+        if throttled_since.is_none() {
+            state.ingest_throttled.fetch_add(1, Ordering::Relaxed);
+            throttled_since = Some(Instant::now());
+        }
+        tokio::select! {
+            _ = sleep(wait) => {}
+            // Let the message through rather than hold up shutdown
+            _ = state.shutdown.cancelled() => break,
+        }
+    }
+    if let Some(since) = throttled_since {
+        let waited = since.elapsed();
+        tracing::debug!("--upstream-rate-limit-rps held a message back for {:?}", waited);
+        state.ingest_throttled_us.fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Keeps a copy of a message that failed processing in the dead-letter buffer, unless
+/// --dead-letter-bytes is 0.
+async fn dead_letter(state: &AppState, text: &str, error: String) {
+    if state.config.dead_letter_bytes == 0 {
+        return;
+    }
+    let format = MessageFormat::detect(text, serde_json::from_str::<Value>(text).is_ok());
+    state
+        .dead_letter
+        .write()
+        .await
+        .push_failed(text.to_string(), format, error, now_ms());
+}
+
+/// Reports the messages --upstream-seq-field discarded, once the upstream has moved on to new
+/// ones or disconnected: typically the history it replayed on reconnect.
+fn log_duplicates(url: &str, count: u64, max_seen: Option<u64>) {
+    tracing::info!(
+        url,
+        "discarded {} duplicate messages (numbered {} or below)",
+        count,
+        max_seen.map_or_else(|| "?".to_string(), |seq| seq.to_string())
+    );
+}
+
+/// Distinct `userAgent`s of a message, which is one sample object or an array of them.
+fn message_user_agents(value: &Value) -> Vec<String> {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut agents: Vec<String> = Vec::new();
+    for ua in items.iter().filter_map(|item| item.get("userAgent").and_then(Value::as_str)) {
+        if !agents.iter().any(|a| a == ua) {
+            agents.push(ua.to_string());
+        }
+    }
+    agents
+}
+
+/// Counts samples per `userAgent` for a parsed message (a single object or an array of them).
+async fn record_user_agents(state: &AppState, value: &Value) {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let now = now_ms();
+    // Sample time from `t`, falling back to the receive time
+    let samples: Vec<(&str, u64, Option<f64>)> = items
+        .iter()
+        .filter_map(|item| {
+            let ua = item.get("userAgent").and_then(Value::as_str)?;
+            let raw_t = aggregation::extract_field(item, "t");
+            let t = raw_t.filter(|t| *t >= 0.0).map_or(now, |t| t as u64);
+            Some((ua, t, raw_t.and_then(clockskew::sample_time_ms)))
+        })
+        .collect();
+    if samples.is_empty() {
+        return;
+    }
+    let mut ua_stats = state.ua_stats.write().await;
+    let mut timelines = state.timelines.write().await;
+    let mut sample_rates = state.sample_rates.write().await;
+    let mut clock_offsets = state.clock_offsets.write().await;
+    for (ua, t, t_ms) in samples {
+        let stat = ua_stats
+            .entry(ua.to_string())
+            .or_insert(UaStat { count: 0, last_seen_ms: now, outliers: 0 });
+        stat.count += 1;
+        stat.last_seen_ms = now;
+        timelines.record(ua, t);
+        sample_rates.record(ua, 1, now);
+        if let Some(t_ms) = t_ms {
+            clock_offsets.record(ua, t_ms, now);
+        }
+    }
+}
+
+/// `t_corrected` of a message that is a single sample: its `t` on the server's clock, using the
+/// device's offset as just updated by `record_user_agents`.
+async fn corrected_sample_time(state: &AppState, value: &Value) -> Option<u64> {
+    let ua = value.get("userAgent").and_then(Value::as_str)?;
+    let t_ms = aggregation::extract_field(value, "t").and_then(clockskew::sample_time_ms)?;
+    state.clock_offsets.read().await.corrected(ua, t_ms)
+}
+
+fn audit_eviction(state: &AppState, evicted: Evicted) {
+    if evicted.count > 0 {
+        state.audit.record(AuditEvent::Eviction {
+            count: evicted.count,
+            bytes: evicted.bytes,
+        });
+    }
+}
+
+/// Under --highpass, filters every sample's `x`/`y`/`z` in `value`, in place or as
+/// `x_hp`/`y_hp`/`z_hp` by --highpass-output. Returns whether anything was filtered.
+async fn apply_highpass(state: &AppState, value: &mut Value) -> bool {
+    let Some(highpass) = &state.highpass else {
+        return false;
+    };
+    let now = now_ms();
+    let items = match value {
+        Value::Array(items) => items.as_mut_slice(),
+        other => std::slice::from_mut(other),
+    };
+    let mut highpass = highpass.write().await;
+    let mut filtered = false;
+    for item in items {
+        let Some((ua, [x, y, z])) = sample_acceleration(item).map(|(ua, accel)| (ua.to_string(), accel)) else {
+            continue;
+        };
+        let t_ms = aggregation::extract_field(item, "t")
+            .and_then(clockskew::sample_time_ms)
+            .unwrap_or(now as f64);
+        let output = highpass.filter(&ua, t_ms, [x, y, z]);
+        for (axis, value) in ["x", "y", "z"].into_iter().zip(output) {
+            match state.config.highpass_output {
+                HighpassOutput::Alongside => {
+                    if let Some(obj) = item.as_object_mut() {
+                        obj.insert(format!("{}_hp", axis), Value::from(value));
+                    }
+                }
+                HighpassOutput::Replace => {
+                    if let Some(slot) = axis_slot(item, axis) {
+                        *slot = Value::from(value);
+                    }
+                }
+            }
+        }
+        filtered = true;
+    }
+    filtered
+}
+
+/// Where `aggregation::extract_field` found an axis: `x`, `ax`, `accelerationX` or
+/// `acceleration.x`.
+fn axis_slot<'a>(item: &'a mut Value, axis: &str) -> Option<&'a mut Value> {
+    let obj = item.as_object_mut()?;
+    let keys = [axis.to_string(), format!("a{}", axis), format!("acceleration{}", axis.to_uppercase())];
+    match keys.iter().find(|key| obj.contains_key(key.as_str())) {
+        Some(key) => obj.get_mut(key.as_str()),
+        None => obj.get_mut("acceleration")?.get_mut(axis),
+    }
+}
+
+/// Feeds every sample's `x`/`y`/`z` into its device's peak ground acceleration.
+async fn record_pga(state: &AppState, value: &Value) {
+    let now = now_ms();
+    let mut pga = state.pga.write().await;
+    for (ua, t_ms, accel) in acceleration_samples(value, now) {
+        pga.record(ua, t_ms, accel, now);
+    }
+}
+
+/// Feeds every sample's `x`/`y`/`z` into its device's RMS and noise floor.
+async fn record_noise(state: &AppState, value: &Value) {
+    let mut noise = state.noise.write().await;
+    for (ua, t_ms, accel) in acceleration_samples(value, now_ms()) {
+        noise.record(ua, t_ms, accel);
+    }
+}
+
+/// Feeds every sample's `x`/`y`/`z` into its device's intensity under --intensity, returning
+/// the device's intensity for a message that is a single sample.
+async fn record_intensity(state: &AppState, value: &Value) -> Option<f64> {
+    let unit = state.config.intensity?;
+    let now = now_ms();
+    let mut intensities = state.intensities.write().await;
+    // Resampled on the device's clock, which spaces its samples better than their arrival
+    for (ua, t_ms, accel) in acceleration_samples(value, now) {
+        intensities.record(ua, t_ms, accel.map(|v| v * unit.to_gal()), now);
+    }
+    let ua = value.get("userAgent").and_then(Value::as_str)?;
+    intensities.current(ua)
+}
+
+/// Follows the intensity of every device in a message against --webhook-threshold.
+async fn notify_intensity(state: &AppState, value: &Value) {
+    let (Some(webhook), Some(_)) = (&state.webhook, state.config.webhook_threshold) else {
+        return;
+    };
+    let now = now_ms();
+    let intensities = state.intensities.read().await;
+    for ua in message_user_agents(value) {
+        if let Some(intensity) = intensities.current(&ua) {
+            webhook.intensity(state.config.namespace.as_deref(), &ua, intensity, now);
+        }
+    }
+}
+
+/// Feeds every sample's `x`/`y`/`z` to its device's --sta-lta trigger, announcing the events
+/// that start and end on /ws and --webhook-url (and, once over, in chat alerts) and opening or
+/// winding down their --record-dir recording. Under --correlate-devices the events are
+/// correlated first, so announcements carry their confirmation.
+async fn detect_shaking(state: &AppState, value: &Value) {
+    let Some(detector) = &state.shake_detector else {
+        return;
+    };
+    let now = now_ms();
+    let transitions: Vec<Transition> = {
+        let intensities = state.intensities.read().await;
+        let mut detector = detector.write().await;
+        acceleration_samples(value, now)
+            .into_iter()
+            .filter_map(|(ua, t_ms, accel)| detector.record(ua, t_ms, accel, intensities.current(ua), now))
+            .collect()
+    };
+    for mut transition in transitions {
+        let confirmed_end = match &state.correlator {
+            Some(correlator) => correlate(state, &mut *correlator.write().await, &mut transition),
+            None => None,
+        };
+        if let Some(recorder) = &state.recorder {
+            let mut recorder = recorder.write().await;
+            match &transition {
+                Transition::Start(event) => {
+                    if let Err(err) = recorder.start(event) {
+                        tracing::error!(event = event.id, "failed to start an event recording: {}", err);
+                    }
+                }
+                Transition::End(event) => recorder.end(event, now),
+            }
+        }
+        if let Some(webhook) = &state.webhook {
+            webhook.event(state.config.namespace.as_deref(), &transition);
+        }
+        if let Some(alerts) = &state.alerts {
+            let ui_path = state.config.ui_path();
+            if let Some(confirmed) = &confirmed_end {
+                alerts.confirmed(state.config.namespace.as_deref(), &ui_path, confirmed);
+            }
+            if let Transition::End(event) = &transition {
+                alerts.event(state.config.namespace.as_deref(), &ui_path, event);
+            }
+        }
+        let _ = state.shake_notices.send(transition);
+    }
+}
+
+/// Places a --sta-lta event in its --correlate-devices group and marks it with the confirmed
+/// event it is part of. Returns the confirmed event when this was the last of its events to
+/// end.
+fn correlate(state: &AppState, correlator: &mut Correlator, transition: &mut Transition) -> Option<ConfirmedEvent> {
+    let (event, confirmed_end) = match transition {
+        Transition::Start(event) => {
+            match correlator.start(event) {
+                Some(Confirmation::New(confirmed)) => tracing::info!(
+                    namespace = state.config.namespace.as_deref(),
+                    confirmed = confirmed.id,
+                    devices = ?confirmed.devices,
+                    "shaking confirmed by {} devices",
+                    confirmed.devices.len()
+                ),
+                Some(Confirmation::Joined(confirmed)) => tracing::info!(
+                    namespace = state.config.namespace.as_deref(),
+                    confirmed = confirmed.id,
+                    device = %event.user_agent,
+                    "device joined confirmed shaking, now {} devices",
+                    confirmed.devices.len()
+                ),
+                None => {}
+            }
+            (event, None)
+        }
+        Transition::End(event) => {
+            let confirmed_end = correlator.end(event);
+            (event, confirmed_end)
+        }
+    };
+    event.confirmed_id = correlator.confirmed_id(event.id);
+    confirmed_end
+}
+
+/// Closes --record-dir recordings whose post-roll ran out while no messages arrived.
+async fn close_recordings(state: AppState) {
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    while !state.shutdown.is_cancelled() {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = state.shutdown.cancelled() => return,
+        }
+        if let Some(recorder) = &state.recorder {
+            match recorder.write().await.expire(now_ms()) {
+                Ok(closed) => log_recording(closed),
+                Err(err) => tracing::error!("failed to close an event recording: {}", err),
+            }
+        }
+    }
+}
+
+/// Closes the open --record-dir recording at shutdown, without the rest of its post-roll.
+async fn close_recording(state: &AppState) {
+    if let Some(recorder) = &state.recorder {
+        match recorder.write().await.close() {
+            Ok(closed) => log_recording(closed),
+            Err(err) => tracing::error!("failed to close an event recording: {}", err),
+        }
+    }
+}
+
+fn log_recording(closed: Option<std::path::PathBuf>) {
+    if let Some(path) = closed {
+        tracing::info!(path = %path.display(), "saved event recording");
+    }
+}
+
+/// `(userAgent, t in ms, [x, y, z])` of every sample in a message that has all of them, with
+/// the receive time standing in for a missing `t`.
+fn acceleration_samples(value: &Value, now_ms: u64) -> Vec<(&str, f64, [f64; 3])> {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let (ua, accel) = sample_acceleration(item)?;
+            let t_ms = aggregation::extract_field(item, "t")
+                .and_then(clockskew::sample_time_ms)
+                .unwrap_or(now_ms as f64);
+            Some((ua, t_ms, accel))
+        })
+        .collect()
+}
+
+/// A sample's `userAgent` and `x`/`y`/`z`, unless --outlier-filter flagged it.
+fn sample_acceleration(item: &Value) -> Option<(&str, [f64; 3])> {
+    if item.get("outlier").and_then(Value::as_bool) == Some(true) {
+        return None;
+    }
+    let ua = item.get("userAgent").and_then(Value::as_str)?;
+    let [Some(x), Some(y), Some(z)] = ["x", "y", "z"].map(|axis| aggregation::extract_field(item, axis)) else {
+        return None;
+    };
+    Some((ua, [x, y, z]))
+}
+
+/// Sleeps for `backoff`, returning early with `true` if a manual reconnect is requested.
+///
+/// Also returns early on shutdown, which the caller's loop condition picks up.
+async fn sleep_or_reconnect(state: &AppState, backoff: Duration) -> bool {
+    tokio::select! {
+        _ = sleep(backoff) => false,
+        _ = state.reconnect.notified() => true,
+        _ = state.shutdown.cancelled() => false,
+    }
+}
+
+/// Serves on every `--bind` address, or on the sockets systemd passed in `activated` instead.
+///
+/// `states` holds one state per namespace, or the single unnamespaced one.
+async fn run_http_server(
+    states: Vec<AppState>,
+    tls: Option<RustlsConfig>,
+    activated: Vec<std::net::TcpListener>,
+) -> std::io::Result<()> {
+    // Everything below that is not per namespace is shared, so any state will do
+    let state = states[0].clone();
+    let base_path = state.config.base_path.clone();
+    // --bind is ignored when systemd passed the sockets
+    let bind_addrs = match activated.is_empty() {
+        true => listen_addrs(&state.config.bind, state.config.bind_ipv6),
+        false => Vec::new(),
+    };
+    let best_effort = state.config.bind_best_effort;
+    let state_shutdown = state.shutdown.clone();
+    let grpc_state = state.clone();
+    let cors = cors::layer(&state.config.cors_origins)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("--cors-origin: {}", e)))?;
+    let ui_paths: Vec<String> = states.iter().map(|state| state.config.ui_path()).collect();
+    let app = if state.config.namespace.is_none() {
+        app_router(state)
+    } else {
+        let apps: HashMap<String, Router> = states
+            .into_iter()
+            .map(|state| (state.config.namespace.clone().unwrap_or_default(), app_router(state)))
+            .collect();
+        Router::new().fallback(dispatch_namespace).with_state(Arc::new(apps))
+    };
+    let app = if base_path.is_empty() {
+        app
+    } else {
+        // The nested index only matches "/yure"; send "/yure/" there as well
+        let index_path = base_path.clone();
+        Router::new()
+            .route(
+                &format!("{}/", base_path),
+                get(move || async move { Redirect::permanent(&index_path) }),
+            )
+            .nest(&base_path, app)
+            .fallback(not_found)
+    };
+    // Outermost so preflight requests are answered before auth and rate limiting
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let mut listeners = Vec::new();
+    for listener in activated {
+        let addr = listener.local_addr()?;
+        for path in &ui_paths {
+            tracing::info!("web UI available at {}://{}{}/ (socket from systemd)", scheme, addr, path);
+        }
+        listeners.push(listener);
+    }
+    for (addr, v6_only) in bind_addrs {
+        match bind_listener(addr, v6_only) {
+            Ok(listener) => {
+                let addr = listener.local_addr()?;
+                for path in &ui_paths {
+                    tracing::info!("web UI available at {}://{}{}/", scheme, addr, path);
+                }
+                listeners.push(listener);
+            }
+            Err(err) if best_effort => tracing::warn!("skipping {}, failed to bind: {}", addr, err),
+            Err(err) => return Err(std::io::Error::new(err.kind(), format!("failed to bind {}: {}", addr, err))),
+        }
+    }
+    if listeners.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::AddrNotAvailable, "no --bind address could be bound"));
+    }
+    let grpc_listener = match grpc::listen_addr(&grpc_state.config) {
+        Some(addr) => {
+            let listener = bind_listener(addr, false)
+                .map_err(|err| std::io::Error::new(err.kind(), format!("failed to bind gRPC {}: {}", addr, err)))?;
+            tracing::info!("gRPC API available at {}", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+    daemon::ready();
+
+    // Dropping the set (when this task is aborted on shutdown) aborts every listener
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        servers.spawn(serve_on(listener, app.clone(), tls.clone(), state_shutdown.clone()));
+    }
+    if let Some(listener) = grpc_listener {
+        servers.spawn(grpc::serve(listener, grpc_state));
+    }
+    while let Some(result) = servers.join_next().await {
+        result.map_err(std::io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// The routes of one namespace, or of the whole server when not namespaced.
+fn app_router(state: AppState) -> Router {
+    let body_limit = match state.config.max_body_bytes {
+        0 => DefaultBodyLimit::disable(),
+        n => DefaultBodyLimit::max(usize::try_from(n).unwrap_or(usize::MAX)),
+    };
+
+    // Admin routes, both POST and GET; the body limit only affects requests that have a body
+    let admin = Router::new()
+        .route("/api/admin/reconnect", post(admin_reconnect))
+        .route("/api/admin/ws-clients", get(admin_ws_clients))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin))
+        .layer(body_limit);
+
+    let log_level = Router::new()
+        .route("/api/log-level", post(set_log_level))
+        .layer(body_limit);
+
+    let graphql = Router::new()
+        .route("/api/graphql", post(graphql::execute))
+        .route("/api/graphql/schema", get(graphql::sdl))
+        .route("/api/graphql/ws", get(graphql::subscribe))
+        .layer(Extension(graphql::schema(state.clone())))
+        .layer(body_limit);
+
+    Router::new()
+        .route("/", get(index))
+        .route("/assets/:name", get(assets::serve))
+        .route("/api/messages", get(list_messages))
+        .route("/api/messages/stream", get(stream_messages))
+        .route("/api/events", get(events))
+        .route("/api/poll", get(poll))
+        .route("/api/messages/sample", get(sample_messages))
+        .route("/api/messages/search", get(search_messages))
+        .route("/api/messages/schema", get(message_schema))
+        .route("/api/messages/dead-letter", get(dead_letter_messages))
+        .route("/api/aggregate", get(aggregate))
+        .route("/api/config", get(config))
+        .route("/api/stats", get(stats))
+        .route("/api/stats/ua", get(ua_stats))
+        .route("/api/devices", get(devices))
+        .route("/api/gaps", get(gaps))
+        .route("/api/spectrum", get(spectrum))
+        .route("/api/samplerate", get(sample_rate))
+        .route("/api/pga", get(pga))
+        .route("/api/noise", get(noise))
+        .route("/api/intensity", get(intensity))
+        .route("/api/shake-events", get(shake_events))
+        .route("/api/events/:id/recording", get(event_recording))
+        .route("/api/process", get(process_info))
+        .route("/api/status", get(upstream_status))
+        .route("/api/sources", get(sources))
+        .route("/api/openapi.json", get(openapi_json))
+        // Swagger UI loads its assets relative to the trailing slash, which also keeps it working
+        // under --base-path
+        .route("/api/docs", get(|| async { Redirect::permanent("docs/") }))
+        .route("/api/docs/", get(api_docs))
+        .route("/api/docs/*file", get(api_docs))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/healthz", get(healthz))
+        .route("/ws", get(ws_handler))
+        .route("/ws/:source", get(ws_source_handler))
+        .merge(admin)
+        .merge(log_level)
+        .merge(graphql)
+        .fallback(ui_fallback)
+        .layer(middleware::from_fn_with_state(state.clone(), require_auth))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), request_limits))
+        .layer(middleware::from_fn_with_state(state.clone(), ip_filter))
+        .layer(middleware::from_fn_with_state(state.clone(), access_log))
+        // Outside the access log so its lines carry the trace ID
+        .layer(middleware::from_fn(trace_context::propagate))
+        .layer(compression_layer())
+        .with_state(state)
+}
+
+/// Hands a request to its namespace's routes, with the namespace taken out of the path.
+async fn dispatch_namespace(State(apps): State<Arc<HashMap<String, Router>>>, mut req: Request) -> Response {
+    let Some((name, path)) = namespace::split(req.uri().path()) else {
+        return not_found().await.into_response();
+    };
+    let Some(app) = apps.get(name) else {
+        return ApiError::NotFound(format!("no namespace `{}`", name)).into_response();
+    };
+    let uri = match req.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    match uri.parse() {
+        Ok(uri) => *req.uri_mut() = uri,
+        Err(_) => return not_found().await.into_response(),
+    }
+    // The router's error type is Infallible
+    match tower::ServiceExt::oneshot(app.clone(), req).await {
+        Ok(res) => res,
+        Err(never) => match never {},
+    }
+}
+
+/// Expands `--bind` (plus `[::]` per IPv4 port with `--bind-ipv6`) into addresses to listen
+/// on, each with whether its socket must be IPv6-only.
+///
+/// An IPv6 socket sharing a port with an IPv4 one is made IPv6-only so the two do not clash.
+fn listen_addrs(binds: &[SocketAddr], bind_ipv6: bool) -> Vec<(SocketAddr, bool)> {
+    let extra_v6 = binds
+        .iter()
+        .filter(|a| bind_ipv6 && a.is_ipv4())
+        .map(|a| SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), a.port()));
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for addr in binds.iter().copied().chain(extra_v6) {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs
+        .iter()
+        .map(|addr| {
+            let v6_only = addr.is_ipv6() && binds.iter().any(|b| b.is_ipv4() && b.port() == addr.port());
+            (*addr, v6_only)
+        })
+        .collect()
+}
+
+/// Serves `app` on one listener, over TLS when a config is given, until `shutdown` is
+/// cancelled and the open requests have completed.
+///
+/// HTTP/1.1 and HTTP/2 are both accepted: negotiated via ALPN under TLS, by prior knowledge
+/// in cleartext. /ws stays on HTTP/1.1 since hyper does not offer WebSocket over HTTP/2
+/// (RFC 8441).
+async fn serve_on(
+    listener: std::net::TcpListener,
+    app: Router,
+    tls: Option<RustlsConfig>,
+    shutdown: CancellationToken,
+) -> std::io::Result<()> {
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(config) => {
+            let handle = axum_server::Handle::new();
+            let on_shutdown = handle.clone();
+            tokio::spawn(async move {
+                shutdown.cancelled().await;
+                on_shutdown.graceful_shutdown(None);
+            });
+            axum_server::from_tcp_rustls(listener, config)
+                .handle(handle)
+                .serve(service)
+                .await
+        }
+        None => {
+            axum::serve(tokio::net::TcpListener::from_std(listener)?, service)
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await
+        }
+    }
+}
+
+/// Binds a non-blocking listener; `v6_only` keeps an IPv6 socket off the IPv4-mapped range.
+fn bind_listener(addr: SocketAddr, v6_only: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// gzip/zstd per `Accept-Encoding`, except for the NDJSON stream, whose lines must reach
+/// followers as soon as they are written rather than sitting in the encoder's buffer.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new()
+        .no_br()
+        .no_deflate()
+        .compress_when(DefaultPredicate::new().and(NotForContentType::const_new("application/x-ndjson")))
+}
+
+async fn access_log(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let ip = client_ip::client_ip(&req, state.config.trust_proxy);
+    let path = req.uri().path();
+    let excluded = state.config.access_log_exclude.iter().any(|p| p == path);
+    // --access-log-exclude only quiets the access log; the audit log records every request
+    let audited = state
+        .audit
+        .enabled()
+        .then(|| (req.method().to_string(), path.to_string()));
+    let response = access_log::log_request(req, next, ip, excluded).await;
+    if let Some((method, path)) = audited {
+        let request_id = response
+            .headers()
+            .get(access_log::REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        state.audit.record(AuditEvent::Http {
+            method,
+            path,
+            client_ip: ip,
+            status: response.status().as_u16(),
+            request_id,
+        });
+    }
+    response
+}
+
+/// Requires a valid API token or JWT (bearer header or `?token=`) or Basic credentials once
+/// any of them is configured. The claims of an accepted JWT are added to the request's
+/// extensions as [`JwtClaims`].
+///
+/// Admin routes are exempt because they carry their own, separate token, and the Kubernetes
+/// probes because kubelet sends no credentials.
+async fn require_auth(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let path = req.uri().path();
+    if (state.api_tokens.is_empty() && state.basic_auth.is_none() && state.jwt.is_none())
+        || path.starts_with("/api/admin/")
+        || PROBE_PATHS.contains(&path)
+        || (state.config.public_ui && is_ui_path(path))
+    {
+        return Ok(next.run(req).await);
+    }
+
+    let ip = client_ip::client_ip(&req, state.config.trust_proxy);
+    let failures = state.rate_limits.auth_failures.as_ref();
+    if let (Some(limiter), Some(ip)) = (failures, ip)
+        && let Some(wait) = limiter.retry_after(ip)
+    {
+        return Err(ApiError::TooManyRequests {
+            retry_after_secs: wait.as_secs_f64().ceil() as u64,
+        }
+        .into_response());
+    }
+    let token_ok = !state.api_tokens.is_empty() && state.api_tokens.authorize(req.headers(), req.uri());
+    let basic_ok = state.basic_auth.as_ref().is_some_and(|b| b.authorize(req.headers()));
+    if token_ok || basic_ok {
+        return Ok(next.run(req).await);
+    }
+    if let Some(claims) = state.jwt.as_ref().and_then(|jwt| jwt.authorize(req.headers(), req.uri())) {
+        req.extensions_mut().insert(claims);
+        return Ok(next.run(req).await);
+    }
+
+    let source = ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    tracing::warn!("rejected unauthenticated request from {}: {} {}", source, req.method(), path);
+    if let (Some(limiter), Some(ip)) = (failures, ip) {
+        let _ = limiter.check(ip);
+    }
+    let mut response = ApiError::Unauthorized("missing or invalid credentials".to_string()).into_response();
+    if state.basic_auth.is_some() {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"yurecollect\""),
+        );
+    }
+    Err(response)
+}
+
+/// Applies the per-IP budget for the request's endpoint class, answering 429 when exhausted.
+async fn rate_limit(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(limiter) = state.rate_limits.for_path(req.uri().path())
+        && let Some(ip) = client_ip::client_ip(&req, state.config.trust_proxy)
+        && let Err(wait) = limiter.check(ip)
+    {
+        return Err(ApiError::TooManyRequests {
+            retry_after_secs: wait.as_secs_f64().ceil() as u64,
+        });
+    }
+    Ok(next.run(req).await)
+}
+
+/// Answers 403 to clients outside --allow-ip or inside --block-ip, before anything else is
+/// spent on them.
+async fn ip_filter(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if !state.ip_filter.admits(client_ip::client_ip(&req, state.config.trust_proxy)) {
+        return Err(ApiError::Forbidden("client address not allowed".to_string()));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Applies the concurrency, timeout and body size limits, answering 503/408/413.
+async fn request_limits(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    state.request_limits.enforce(req, next).await
+}
+
+/// The page and its static files, i.e. everything outside the API and `/ws`.
+fn is_ui_path(path: &str) -> bool {
+    !path.starts_with("/api/") && path != "/ws" && !path.starts_with("/ws/")
+}
+
+async fn not_found() -> ApiError {
+    ApiError::NotFound("no such route".to_string())
+}
+
+/// Serves files from `--ui-dir`, answering unknown UI paths with its index.html so
+/// client-side routes work; API paths keep the JSON 404.
+async fn ui_fallback(State(state): State<AppState>, req: Request) -> Response {
+    let Some(dir) = state.config.ui_dir.clone() else {
+        return not_found().await.into_response();
+    };
+    if !is_ui_path(req.uri().path()) {
+        return not_found().await.into_response();
+    }
+    // ServeDir rejects `..` and other components that would escape the directory
+    let served = ServeDir::new(dir)
+        .append_index_html_on_directories(false)
+        .try_call(req)
+        .await;
+    match served {
+        Ok(res) if res.status() != StatusCode::NOT_FOUND => {
+            let mut res = res.map(Body::new);
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static(UI_DIR_CACHE_CONTROL));
+            res
+        }
+        _ => index(State(state)).await.into_response(),
+    }
+}
+
+#[utoipa::path(get, path = "/", responses((status = 200, description = "Web UI", content_type = "text/html")))]
+async fn index(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let Some(dir) = &state.config.ui_dir else {
+        return Ok(Html(state.index_html.clone()).into_response());
+    };
+    // Read on every request so frontend edits need no restart
+    let path = dir.join("index.html");
+    let template = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| ApiError::Internal(format!("failed to read {}: {}", path.display(), e)))?;
+    let html = render_index(&template, &state.config, &state.uplot);
+    Ok(([(header::CACHE_CONTROL, UI_DIR_CACHE_CONTROL)], Html(html)).into_response())
+}
+
+async fn openapi_json(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let mut doc = ApiDoc::openapi();
+    if !state.config.base_path.is_empty() {
+        doc.servers = Some(vec![utoipa::openapi::Server::new(state.config.base_path.clone())]);
+    }
+    let body = doc
+        .to_json()
+        .map_err(|e| ApiError::Internal(format!("failed to render OpenAPI document: {}", e)))?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+/// Swagger UI (bundled into the binary) for /api/openapi.json.
+async fn api_docs(file: Option<axum::extract::Path<String>>) -> Result<Response, ApiError> {
+    let file = file.map(|axum::extract::Path(file)| file).unwrap_or_default();
+    // Relative to /api/docs/, so the prefix from --base-path is kept
+    let config = Arc::new(utoipa_swagger_ui::Config::new(["../openapi.json"]));
+    match utoipa_swagger_ui::serve(&file, config) {
+        Ok(Some(asset)) => Ok(([(header::CONTENT_TYPE, asset.content_type)], asset.bytes.into_owned()).into_response()),
+        Ok(None) => Err(ApiError::NotFound("no such file".to_string())),
+        Err(err) => Err(ApiError::Internal(format!("failed to serve Swagger UI: {}", err))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/messages",
+    params(
+        ListParams,
+        ("envelope" = Option<bool>, Query,
+            description = "Wrap each message with its ingest id, receive time and source (`1`/`true`)"),
+    ),
+    responses(
+        (status = 200, description = "Newest buffered messages, oldest first; `Envelope` objects instead of the bare messages with `envelope=1`", body = Vec<String>,
+            headers(
+                ("X-Total-Count" = usize, description = "Number of buffered messages (from `source`) before applying `limit`"),
+                ("X-Buffer-Bytes" = usize, description = "Bytes currently held by the buffer"),
+                ("X-Buffer-Limit-Bytes" = usize, description = "Buffer capacity in bytes"),
+            )
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn list_messages(
+    State(state): State<AppState>,
+    query: Result<Query<ListParams>, QueryRejection>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(p) = query.map_err(|rejection| {
+        ApiError::BadRequest(format!("{} ({})", LIMIT_CONSTRAINT, rejection.body_text()))
+    })?;
+    let envelope = query_flag(&pairs, "envelope", false)?;
+    let limit = p.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    let source = url_host(state.config.url());
+    let buf = state.buffer.read().await;
+    // Only one upstream, so a source filter either keeps everything or nothing
+    let total = match p.source {
+        Some(label) if label != source => 0,
+        _ => buf.len(),
+    };
+    let start = buf.len() - limit.min(total);
+    let headers = [
+        ("x-total-count", total.to_string()),
+        ("x-buffer-bytes", buf.total_bytes.to_string()),
+        ("x-buffer-limit-bytes", MAX_BUFFER_BYTES.to_string()),
+    ];
+    if envelope {
+        let slice: Vec<Envelope> = buf
+            .iter()
+            .skip(start)
+            .map(|m| Envelope::new(m.id, m.received_at_ms, source, m.format, m.t_corrected, m.intensity, &m.text))
+            .collect();
+        return Ok((headers, Json(slice)).into_response());
+    }
+    let slice: Vec<String> = buf.iter().skip(start).map(|m| m.text.clone()).collect();
+    Ok((headers, Json(slice)).into_response())
+}
+
+/// Streams the whole buffer as NDJSON without holding the buffer lock while writing.
+///
+/// With `follow=true` the response continues with live messages. The subscription is taken
+/// before the snapshot, so a message arriving at that moment may appear twice but is never lost.
+///
+/// `after_seq`/`until_seq` restrict the output to a `_seq` range, which is identical on every
+/// request while those messages are buffered, so an interrupted download can be resumed by
+/// passing the last received `_seq` as `after_seq`. Messages without a `_seq` are left out.
+#[utoipa::path(
+    get,
+    path = "/api/messages/stream",
+    params(StreamParams),
+    responses(
+        (status = 200, description = "One buffered message per line, oldest first", content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid query parameters or `_seq` range", body = ErrorBody),
+    )
+)]
+async fn stream_messages(
+    State(state): State<AppState>,
+    query: Result<Query<StreamParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let follow = p.follow.unwrap_or(false);
+    let ranged = p.after_seq.is_some() || p.until_seq.is_some();
+    if ranged && !state.config.inject_seq {
+        return Err(ApiError::BadRequest("`after_seq`/`until_seq` require --inject-seq".to_string()));
+    }
+    if follow && p.until_seq.is_some() {
+        return Err(ApiError::BadRequest("`until_seq` cannot be combined with `follow`".to_string()));
+    }
+    if let (Some(after), Some(until)) = (p.after_seq, p.until_seq)
+        && until < after
+    {
+        return Err(ApiError::BadRequest("`until_seq` must not be less than `after_seq`".to_string()));
+    }
+
+    let live = follow.then(|| state.tx.subscribe());
+    let in_range = |seq: Option<u64>| {
+        !ranged
+            || seq.is_some_and(|seq| {
+                p.after_seq.is_none_or(|after| seq > after) && p.until_seq.is_none_or(|until| seq <= until)
+            })
+    };
+    let snapshot: Vec<String> = {
+        let buf = state.buffer.read().await;
+        buf.iter()
+            .filter(|m| in_range(m.seq))
+            .map(|m| m.text.clone())
+            .collect()
+    };
 
-const MAX_BUFFER_BYTES: usize = 1024 * 1024 * 1024 * 1; // 1GB
+    let buffered = futures_util::stream::iter(snapshot);
+    let lag_dropped = state.lag_dropped.clone();
+    let live = futures_util::stream::unfold(live, move |rx| {
+        let lag_dropped = lag_dropped.clone();
+        async move {
+            let mut rx = rx?;
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => return Some((msg.text, Some(rx))),
+                    // The stream has no way to tell the client, so it only shows in the counter
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        record_lag(&lag_dropped, "/api/messages/stream", missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    // End a follow stream on shutdown so graceful draining is not held up by it
+    let live = live.take_until(state.shutdown.clone().cancelled_owned());
+    let lines = buffered
+        .chain(live)
+        .map(|msg| Ok::<_, std::convert::Infallible>(format!("{}\n", msg)));
 
-struct MessageBuffer {
-    total_bytes: usize,
-    entries: VecDeque<String>,
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
 }
 
-impl MessageBuffer {
-    fn new() -> Self {
-        Self {
-            total_bytes: 0,
-            entries: VecDeque::new(),
+/// Counts messages a live subscriber skipped because it fell behind the broadcast, in
+/// `messages_dropped_lag` of /api/stats, and logs them.
+fn record_lag(lag_dropped: &AtomicU64, subscriber: &str, missed: u64) {
+    tracing::warn!(subscriber, missed, "live subscriber lagged behind the broadcast");
+    lag_dropped.fetch_add(missed, Ordering::Relaxed);
+}
+
+/// Server-Sent Events stream of live messages, one `message` event per received message.
+///
+/// A client that falls behind gets a `lagged` event with `{"missed":n}` and keeps receiving.
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    responses((status = 200, description = "Live messages", content_type = "text/event-stream"))
+)]
+async fn events(
+    State(state): State<AppState>,
+) -> Sse<impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    let rx = state.tx.subscribe();
+    let lag_dropped = state.lag_dropped.clone();
+    let live = futures_util::stream::unfold(rx, move |mut rx| {
+        let lag_dropped = lag_dropped.clone();
+        async move {
+            let event = match rx.recv().await {
+                Ok(msg) => Event::default().data(msg.text),
+                Err(RecvError::Lagged(missed)) => {
+                    record_lag(&lag_dropped, "/api/events", missed);
+                    Event::default()
+                        .event("lagged")
+                        .data(json!({ "missed": missed }).to_string())
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            Some((Ok(event), rx))
         }
+    });
+    // End on shutdown so graceful draining is not held up by it
+    let live = live.take_until(state.shutdown.clone().cancelled_owned());
+    Sse::new(live).keep_alive(KeepAlive::default())
+}
+
+/// Long-polling fallback for clients that cannot open a WebSocket: returns the buffered
+/// messages with a `_seq` greater than `since_seq` (oldest first, at most 500) as soon as there
+/// are any, or `204 No Content` once `timeout_ms` passes without one.
+///
+/// Poll again with the last returned `_seq`. As with `/ws?after_seq=`, the array starts with
+/// `{"type":"gap","evicted_before":n}` when messages after `since_seq` have already left the
+/// buffer; with nothing else returned, continue from `n - 1`. Requires --inject-seq.
+#[utoipa::path(
+    get,
+    path = "/api/poll",
+    params(PollParams),
+    responses(
+        (status = 200, description = "Messages after `since_seq`, oldest first", body = Vec<String>),
+        (status = 204, description = "No new message within `timeout_ms`"),
+        (status = 400, description = "Invalid query parameters, or --inject-seq is off", body = ErrorBody),
+    )
+)]
+async fn poll(
+    State(state): State<AppState>,
+    query: Result<Query<PollParams>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    if !state.config.inject_seq {
+        return Err(ApiError::BadRequest("`/api/poll` requires --inject-seq".to_string()));
+    }
+    let timeout_ms = p.timeout_ms.unwrap_or(DEFAULT_POLL_TIMEOUT_MS);
+    if timeout_ms > MAX_POLL_TIMEOUT_MS {
+        return Err(ApiError::BadRequest(format!("`timeout_ms` must be at most {}", MAX_POLL_TIMEOUT_MS)));
     }
+    let since = p.since_seq.unwrap_or(0);
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
 
-    fn push(&mut self, msg: String) {
-        let msg_len = msg.len();
-        while self.total_bytes + msg_len > MAX_BUFFER_BYTES {
-            if let Some(front) = self.entries.pop_front() {
-                self.total_bytes = self.total_bytes.saturating_sub(front.len());
-            } else {
-                break;
-            }
+    // Subscribed before the buffer is read, so a message arriving in between still wakes us
+    let mut rx = state.tx.subscribe();
+    loop {
+        let mut newer = {
+            let buf = state.buffer.read().await;
+            let latest_seq = state.seq.load(Ordering::Relaxed);
+            resume_backlog(&buf, since, latest_seq, |_| true, |m| m.text.clone())
+        };
+        if !newer.is_empty() {
+            newer.truncate(DEFAULT_LIST_LIMIT);
+            return Ok(Json(newer).into_response());
+        }
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            _ = tokio::time::sleep_until(deadline) => break,
+            _ = state.shutdown.cancelled() => break,
         }
-        self.total_bytes += msg_len;
-        self.entries.push_back(msg);
     }
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
 
-    fn len(&self) -> usize { self.entries.len() }
-    fn iter(&self) -> impl DoubleEndedIterator<Item=&String> { self.entries.iter() }
+/// Returns messages drawn uniformly from the whole buffer rather than its tail.
+#[utoipa::path(
+    get,
+    path = "/api/messages/sample",
+    params(SampleParams),
+    responses(
+        (status = 200, description = "Randomly sampled messages", body = Vec<String>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn sample_messages(
+    State(state): State<AppState>,
+    query: Result<Query<SampleParams>, QueryRejection>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let Query(p) = query.map_err(|rejection| {
+        ApiError::BadRequest(format!("`n` must be a non-negative integer ({})", rejection.body_text()))
+    })?;
+    let buf = state.buffer.read().await;
+    let sample = buf.sample(p.n.unwrap_or(100), &mut rand::thread_rng());
+    Ok(Json(sample.into_iter().cloned().collect()))
 }
 
-#[derive(Clone)]
-struct AppState {
-    buffer: Arc<RwLock<MessageBuffer>>,
-    tx: broadcast::Sender<String>,
+/// Returns the newest buffered messages containing `q`, oldest first, capped at
+/// `--search-max-results`.
+#[utoipa::path(
+    get,
+    path = "/api/messages/search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matching messages, oldest first", body = Vec<String>),
+        (status = 400, description = "Invalid query parameters or regex", body = ErrorBody),
+    )
+)]
+async fn search_messages(
+    State(state): State<AppState>,
+    query: Result<Query<SearchParams>, QueryRejection>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let pattern = if p.regex.unwrap_or(false) {
+        p.q
+    } else {
+        regex::escape(&p.q)
+    };
+    let re = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .size_limit(1 << 20)
+        .build()
+        .map_err(|e| ApiError::BadRequest(format!("invalid regex: {}", e)))?;
+
+    let buf = state.buffer.read().await;
+    let limit = p.limit.unwrap_or(usize::MAX);
+    let mut matches: Vec<String> = buf
+        .iter()
+        .rev()
+        .take(limit)
+        .filter(|m| re.is_match(&m.text))
+        .take(state.config.search_max_results)
+        .map(|m| m.text.clone())
+        .collect();
+    matches.reverse();
+    Ok(Json(matches))
+}
+
+/// Infers the top-level JSON keys and value types of the newest buffered messages.
+#[utoipa::path(
+    get,
+    path = "/api/messages/schema",
+    responses((status = 200, description = "Observed keys and type frequencies", body = InferredSchema))
+)]
+async fn message_schema(State(state): State<AppState>) -> Json<InferredSchema> {
+    let mut inferrer = SchemaInferrer::new();
+    let buf = state.buffer.read().await;
+    for msg in buf.iter().rev().take(SCHEMA_SAMPLE_SIZE) {
+        inferrer.observe(&msg.text);
+    }
+    Json(inferrer.finish())
 }
 
-#[derive(Deserialize)]
-struct ListParams { limit: Option<usize> }
+/// A message that failed processing.
+#[derive(Serialize, ToSchema)]
+struct DeadLetter {
+    /// Counts the dead-letter buffer's messages from 1
+    id: u64,
+    received_at_ms: u64,
+    format: MessageFormat,
+    /// What failed: JSON parsing of a message in no other known format, or the
+    /// --transform-script/--wasm-plugin
+    error: String,
+    text: String,
+}
 
-#[tokio::main]
-async fn main() {
-    // Get WebSocket URL from CLI arg or env var
-    let url = env::args().nth(1).or_else(|| env::var("WS_URL").ok());
-    let Some(url) = url else {
-        eprintln!("Usage: yurecollect <ws-url>\nAlternatively set WS_URL env var.");
-        std::process::exit(2);
+#[derive(Serialize, ToSchema)]
+struct DeadLetterResponse {
+    /// Messages in the dead-letter buffer before applying `limit`
+    total: usize,
+    bytes: usize,
+    /// --dead-letter-bytes; 0 when failed messages are not kept
+    limit_bytes: usize,
+    /// Newest messages, oldest first
+    messages: Vec<DeadLetter>,
+}
+
+/// Messages that failed processing, for diagnosing upstream protocol issues. They are still
+/// buffered and delivered as usual (non-JSON as `raw`, failed transforms unchanged); this keeps
+/// a copy with the error until --dead-letter-bytes of newer failures push it out.
+#[utoipa::path(
+    get,
+    path = "/api/messages/dead-letter",
+    params(DeadLetterParams),
+    responses(
+        (status = 200, body = DeadLetterResponse),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn dead_letter_messages(
+    State(state): State<AppState>,
+    query: Result<Query<DeadLetterParams>, QueryRejection>,
+) -> Result<Json<DeadLetterResponse>, ApiError> {
+    let Query(p) = query.map_err(|rejection| {
+        ApiError::BadRequest(format!("{} ({})", LIMIT_CONSTRAINT, rejection.body_text()))
+    })?;
+    let limit = p.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    let buf = state.dead_letter.read().await;
+    let mut messages: Vec<DeadLetter> = buf
+        .iter()
+        .rev()
+        .take(limit)
+        .map(|m| DeadLetter {
+            id: m.id,
+            received_at_ms: m.received_at_ms,
+            format: m.format,
+            error: m.error.as_deref().unwrap_or_default().to_string(),
+            text: m.text.clone(),
+        })
+        .collect();
+    messages.reverse();
+    Ok(Json(DeadLetterResponse {
+        total: buf.len(),
+        bytes: buf.total_bytes,
+        limit_bytes: buf.max_bytes,
+        messages,
+    }))
+}
+
+/// Buckets a numeric field of the buffered samples by receive time.
+#[utoipa::path(
+    get,
+    path = "/api/aggregate",
+    params(AggregateParams),
+    responses(
+        (status = 200, description = "Buckets sorted by start time", body = Vec<Bucket>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn aggregate(
+    State(state): State<AppState>,
+    query: Result<Query<AggregateParams>, QueryRejection>,
+) -> Result<Json<Vec<Bucket>>, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let bucket_ms = p.bucket_ms.unwrap_or(1000);
+    if bucket_ms == 0 {
+        return Err(ApiError::BadRequest("`bucket_ms` must be positive".to_string()));
+    }
+
+    let mut aggregator = Aggregator::new(bucket_ms);
+    let buf = state.buffer.read().await;
+    for msg in buf.iter() {
+        let Ok(value) = serde_json::from_str::<Value>(&msg.text) else {
+            continue;
+        };
+        let items = match &value {
+            Value::Array(items) => items.as_slice(),
+            other => std::slice::from_ref(other),
+        };
+        for item in items {
+            if let Some(ua) = p.ua.as_deref()
+                && item.get("userAgent").and_then(Value::as_str) != Some(ua)
+            {
+                continue;
+            }
+            if let Some(v) = aggregation::extract_field(item, &p.field) {
+                aggregator.add(msg.received_at_ms, v);
+            }
+        }
+    }
+    Ok(Json(aggregator.finish()))
+}
+
+/// Sanitized view of the resolved configuration; secrets are only reported as configured or not.
+#[utoipa::path(get, path = "/api/config", responses((status = 200, body = ConfigResponse)))]
+async fn config(State(state): State<AppState>) -> Json<ConfigResponse> {
+    Json(config_response(
+        &state.config,
+        !state.api_tokens.is_empty(),
+        state.basic_auth.is_some(),
+    ))
+}
+
+fn config_response(cfg: &Args, api_token_configured: bool, basic_auth_configured: bool) -> ConfigResponse {
+    ConfigResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        base_path: cfg.base_path.clone(),
+        namespace: cfg.namespace.clone(),
+        upstream: UpstreamConfig {
+            url: redact_url(cfg.url()),
+            connect_timeout_ms: cfg.upstream_connect_timeout_ms,
+            read_timeout_ms: cfg.upstream_read_timeout_ms,
+            ping_interval_secs: cfg.upstream_ping_interval_secs,
+            pong_timeout_secs: cfg.upstream_pong_timeout_secs,
+            transform_script: cfg.transform_script.is_some(),
+            wasm_plugin: cfg.wasm_plugin.is_some(),
+            seq_field: cfg.upstream_seq_field.clone(),
+        },
+        buffer: BufferConfig {
+            max_bytes: MAX_BUFFER_BYTES,
+        },
+        chart: ChartConfig {
+            window_seconds: cfg.chart_window_secs,
+            max_points: cfg.chart_max_points,
+            initial_limit: DEFAULT_LIST_LIMIT,
+        },
+        auth: AuthConfig {
+            admin_token_configured: cfg.admin_token.is_some(),
+            api_token_configured,
+            basic_auth_configured,
+            jwt_configured: cfg.jwt_secret.is_some(),
+            public_ui: cfg.public_ui,
+        },
+        limits: LimitsConfig {
+            max_concurrent_requests: cfg.max_concurrent_requests,
+            request_timeout_secs: cfg.request_timeout_secs,
+            max_body_bytes: cfg.max_body_bytes,
+            max_ws_clients: cfg.max_ws_clients,
+        },
+        inject_seq: cfg.inject_seq,
+        intensity_unit: cfg
+            .intensity
+            .and_then(|unit| unit.to_possible_value())
+            .map(|value| value.get_name().to_string()),
+    }
+}
+
+/// Masks the password in the userinfo and every query value of a URL.
+fn redact_url(url: &str) -> String {
+    let (base, query) = match url.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (url, None),
+    };
+    let mut out = match base.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = match rest.find('/') {
+                Some(i) => rest.split_at(i),
+                None => (rest, ""),
+            };
+            let authority = match authority.rsplit_once('@') {
+                Some((userinfo, host)) => {
+                    let user = userinfo.split(':').next().unwrap_or_default();
+                    format!("{}:***@{}", user, host)
+                }
+                None => authority.to_string(),
+            };
+            format!("{}://{}{}", scheme, authority, path)
+        }
+        None => base.to_string(),
     };
+    if let Some(query) = query {
+        let redacted: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) => format!("{}=***", key),
+                None => pair.to_string(),
+            })
+            .collect();
+        out.push('?');
+        out.push_str(&redacted.join("&"));
+    }
+    out
+}
+
+#[utoipa::path(get, path = "/api/stats", responses((status = 200, body = StatsResponse)))]
+async fn stats(State(state): State<AppState>) -> Json<StatsResponse> {
+    Json(stats_response(&state).await)
+}
 
-    let state = AppState {
-        buffer: Arc::new(RwLock::new(MessageBuffer::new())),
-        tx: broadcast::channel(1024).0,
+async fn stats_response(state: &AppState) -> StatsResponse {
+    let (messages, buffer_bytes) = {
+        let buf = state.buffer.read().await;
+        (buf.len(), buf.total_bytes)
     };
+    let seq = state
+        .config
+        .inject_seq
+        .then(|| state.seq.load(Ordering::Relaxed));
+    let messages_per_second = state.rate.read().await.rate_per_second();
+    let unique_user_agents = state.ua_stats.read().await.len();
+    let ws_clients = state.ws_clients.read().await;
+    let (ws_messages_sent, ws_bytes_sent) = ws_clients.sent_total();
+    StatsResponse {
+        messages,
+        buffer_bytes,
+        buffer_limit_bytes: MAX_BUFFER_BYTES,
+        seq,
+        messages_per_second,
+        unique_user_agents,
+        rejected_requests: state.request_limits.rejections(),
+        ws_client_count: state.request_limits.ws_client_count(),
+        ws_missed_messages: ws_clients.missed_total,
+        ws_dropped_messages: ws_clients.dropped_total(),
+        ws_slow_disconnects: ws_clients.slow_disconnects,
+        ws_lag_disconnects: ws_clients.lag_disconnects,
+        messages_dropped_lag: state.lag_dropped.load(Ordering::Relaxed),
+        upstream_throttled_messages: state.ingest_throttled.load(Ordering::Relaxed),
+        ws_messages_sent,
+        ws_bytes_sent,
+        ws_clients: ws_clients.snapshot(),
+        sign_key_id: state.signer.as_ref().map(|signer| signer.key_id().to_string()),
+        webhook: state.webhook.as_ref().map(|webhook| webhook.stats()),
+        alerts: state.alerts.as_ref().map(|alerts| alerts.stats()),
+    }
+}
 
-    // Spawn HTTP server for web UI
-    let state_for_http = state.clone();
-    let mut http_task = tokio::spawn(async move {
-        run_http_server(state_for_http).await;
-    });
+#[utoipa::path(
+    get,
+    path = "/api/stats/ua",
+    responses((status = 200, description = "Stats keyed by userAgent", body = HashMap<String, UaStat>))
+)]
+async fn ua_stats(State(state): State<AppState>) -> Json<HashMap<String, UaStat>> {
+    Json(state.ua_stats.read().await.clone())
+}
 
-    // Connect to upstream websocket and stream messages
-    let state_for_ws = state.clone();
-    let mut ws_task = tokio::spawn(async move {
-        run_upstream_ws(url, state_for_ws).await;
-    });
+/// Every device seen so far and whether it has gone silent.
+#[utoipa::path(get, path = "/api/devices", responses((status = 200, body = DevicesResponse)))]
+async fn devices(State(state): State<AppState>) -> Json<DevicesResponse> {
+    let now = now_ms();
+    let sample_rates = state.sample_rates.read().await;
+    let clock_offsets = state.clock_offsets.read().await;
+    let pga = state.pga.read().await;
+    let noise = state.noise.read().await;
+    let mut devices: Vec<DeviceInfo> = state
+        .ua_stats
+        .read()
+        .await
+        .iter()
+        .map(|(ua, stat)| {
+            let silent_for_ms = now.saturating_sub(stat.last_seen_ms);
+            let clock = clock_offsets.estimate(ua);
+            DeviceInfo {
+                user_agent: ua.clone(),
+                count: stat.count,
+                last_seen_ms: stat.last_seen_ms,
+                outliers: stat.outliers,
+                silent_for_ms,
+                samples_per_second: sample_rates.samples_per_second(ua, now).unwrap_or(0.0),
+                silent: silent_for_ms > DEFAULT_MIN_GAP_MS,
+                clock_offset_ms: clock.as_ref().map(|clock| clock.offset_ms),
+                clock_jitter_ms: clock.as_ref().map(|clock| clock.jitter_ms),
+                clock_steps: clock.map_or(0, |clock| clock.steps),
+                pga: pga.reading(ua, now),
+                noise: noise.reading(ua),
+            }
+        })
+        .collect();
+    devices.sort_by(|a, b| a.user_agent.cmp(&b.user_agent));
+    Json(DevicesResponse {
+        open_gaps: devices.iter().filter(|d| d.silent).count(),
+        open_gap_threshold_ms: DEFAULT_MIN_GAP_MS,
+        devices,
+    })
+}
 
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            eprintln!("Received Ctrl+C, shutting down...");
-            http_task.abort();
-            ws_task.abort();
-        }
-        _ = &mut http_task => {
-            eprintln!("HTTP task ended, shutting down...");
-            ws_task.abort();
+/// Estimated samples per second of each device, with one-minute averages of the last 10 minutes.
+#[utoipa::path(
+    get,
+    path = "/api/samplerate",
+    responses((status = 200, description = "Rates keyed by userAgent", body = BTreeMap<String, SampleRate>))
+)]
+async fn sample_rate(State(state): State<AppState>) -> Json<BTreeMap<String, SampleRate>> {
+    Json(state.sample_rates.write().await.snapshot(now_ms()))
+}
+
+/// The peak ground acceleration of each device over the last minute, the last 10 minutes and
+/// since startup: the largest vector acceleration with the device's offset (gravity, sensor
+/// bias) removed, with when it was received.
+#[utoipa::path(
+    get,
+    path = "/api/pga",
+    responses((status = 200, description = "Peaks keyed by userAgent", body = BTreeMap<String, PgaReading>))
+)]
+async fn pga(State(state): State<AppState>) -> Json<BTreeMap<String, PgaReading>> {
+    Json(state.pga.read().await.snapshot(now_ms()))
+}
+
+/// The RMS acceleration of each device over the last --rms-window of its samples, per axis and
+/// as a vector, and its noise floor: the long-term RMS while it is at rest.
+#[utoipa::path(
+    get,
+    path = "/api/noise",
+    responses((status = 200, description = "Noise keyed by userAgent", body = BTreeMap<String, NoiseReading>))
+)]
+async fn noise(State(state): State<AppState>) -> Json<BTreeMap<String, NoiseReading>> {
+    Json(state.noise.read().await.snapshot())
+}
+
+/// The JMA instrumental seismic intensity of each device, with its peak of the last 10 minutes.
+/// Requires --intensity.
+#[utoipa::path(
+    get,
+    path = "/api/intensity",
+    responses(
+        (status = 200, description = "Intensities keyed by userAgent", body = BTreeMap<String, IntensityReading>),
+        (status = 400, description = "--intensity is off", body = ErrorBody),
+    )
+)]
+async fn intensity(State(state): State<AppState>) -> Result<Json<BTreeMap<String, IntensityReading>>, ApiError> {
+    if state.config.intensity.is_none() {
+        return Err(ApiError::BadRequest("`/api/intensity` requires --intensity".to_string()));
+    }
+    Ok(Json(state.intensities.write().await.snapshot(now_ms())))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ShakeEventsParams {
+    /// Only the events of this userAgent; with `confirmed=1`, the confirmed events it is part of
+    ua: Option<String>,
+}
+
+/// Shaking detected by --sta-lta, oldest first, including ongoing events (without `end_ms`).
+/// The last 1000 events are kept.
+///
+/// Under --correlate-devices each event carries the confirmed event it is part of, and
+/// `confirmed=1` lists the confirmed events themselves: shaking that several devices picked up
+/// at once.
+#[utoipa::path(
+    get,
+    path = "/api/shake-events",
+    params(
+        ShakeEventsParams,
+        ("confirmed" = Option<bool>, Query,
+            description = "List the --correlate-devices confirmed events instead (`1`/`true`)"),
+    ),
+    responses(
+        (status = 200, description = "Events, oldest first; `ConfirmedEvent` objects with `confirmed=1`", body = Vec<ShakeEvent>),
+        (status = 400, description = "Invalid query parameters, --sta-lta is off, or `confirmed=1` without --correlate-devices", body = ErrorBody),
+    )
+)]
+async fn shake_events(
+    State(state): State<AppState>,
+    query: Result<Query<ShakeEventsParams>, QueryRejection>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Result<Response, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let Some(detector) = &state.shake_detector else {
+        return Err(ApiError::BadRequest("`/api/shake-events` requires --sta-lta".to_string()));
+    };
+    let confirmed = query_flag(&pairs, "confirmed", false)?;
+    let Some(correlator) = &state.correlator else {
+        if confirmed {
+            return Err(ApiError::BadRequest("`confirmed=1` requires --correlate-devices".to_string()));
         }
-        _ = &mut ws_task => {
-            eprintln!("Upstream task ended, shutting down...");
-            http_task.abort();
+        return Ok(Json(detector.read().await.events(p.ua.as_deref())).into_response());
+    };
+    let detector = detector.read().await;
+    let correlator = correlator.read().await;
+    if confirmed {
+        let mut confirmed = correlator.events(&detector.events(None));
+        if let Some(ua) = &p.ua {
+            confirmed.retain(|event| event.devices.contains(ua));
         }
+        return Ok(Json(confirmed).into_response());
     }
+    let mut events = detector.events(p.ua.as_deref());
+    for event in &mut events {
+        event.confirmed_id = correlator.confirmed_id(event.id);
+    }
+    Ok(Json(events).into_response())
 }
 
-async fn run_upstream_ws(url: String, state: AppState) {
-    let mut backoff = Duration::from_secs(1);
-    let max_backoff = Duration::from_secs(30);
+/// Downloads the --record-dir recording of a --sta-lta event: one envelope (as with
+/// `?envelope=1`) per line for every message from --record-pre-trigger before the event to
+/// --record-post-roll after it. Overlapping events share one recording.
+#[utoipa::path(
+    get,
+    path = "/api/events/{id}/recording",
+    params(("id" = u64, Path, description = "Event id, as in `/api/shake-events`")),
+    responses(
+        (status = 200, description = "The recording, named by the event's start time and peak intensity", content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid event id, or --record-dir is off", body = ErrorBody),
+        (status = 404, description = "No recording of this event", body = ErrorBody),
+        (status = 409, description = "The event is still being recorded", body = ErrorBody),
+    )
+)]
+async fn event_recording(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Response, ApiError> {
+    let Some(recorder) = &state.recorder else {
+        return Err(ApiError::BadRequest("`/api/events/{id}/recording` requires --record-dir".to_string()));
+    };
+    let id: u64 = id
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("invalid event id `{}`", id)))?;
+    let path = match recorder.read().await.lookup(id) {
+        Lookup::Finished(path) => path,
+        Lookup::Recording => {
+            return Err(ApiError::Conflict(format!("event {} is still being recorded", id)));
+        }
+        Lookup::Unknown => return Err(ApiError::NotFound(format!("no recording of event {}", id))),
+    };
+    let contents = tokio::fs::read(&path)
+        .await
+        .map_err(|err| ApiError::Internal(format!("reading {}: {}", path.display(), err)))?;
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+        ],
+        contents,
+    )
+        .into_response())
+}
 
-    loop {
-        let (ws_stream, _resp) = match connect_async(&url).await {
-            Ok(pair) => {
-                eprintln!("Connected to upstream: {}", url);
-                backoff = Duration::from_secs(1);
-                pair
-            }
-            Err(err) => {
-                eprintln!(
-                    "Failed to connect to {}: {} (retry in {:?})",
-                    url, err, backoff
-                );
-                sleep(backoff).await;
-                backoff = std::cmp::min(backoff * 2, max_backoff);
+/// Reports intervals in which a device's sample spacing exceeded `min_gap`.
+#[utoipa::path(
+    get,
+    path = "/api/gaps",
+    params(GapsParams),
+    responses(
+        (status = 200, description = "Gaps keyed by userAgent, sorted by time; devices without gaps are omitted", body = BTreeMap<String, Vec<Gap>>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+async fn gaps(
+    State(state): State<AppState>,
+    query: Result<Query<GapsParams>, QueryRejection>,
+) -> Result<Json<BTreeMap<String, Vec<Gap>>>, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let min_gap_ms = match p.min_gap.as_deref() {
+        Some(s) => gaps::parse_duration_ms(s).map_err(ApiError::BadRequest)?,
+        None => DEFAULT_MIN_GAP_MS,
+    };
+    if min_gap_ms == 0 {
+        return Err(ApiError::BadRequest("`min_gap` must be positive".to_string()));
+    }
+    let timelines = state.timelines.read().await;
+    Ok(Json(timelines.gaps(p.ua.as_deref(), p.since, p.until, min_gap_ms)))
+}
+
+/// Magnitude spectrum of one axis of a device's buffered samples, e.g. to tell a resonance
+/// (a washing machine, a fan) from ground motion.
+///
+/// The samples in the range are linearly resampled to a uniform rate, their mean removed and a
+/// Hann window applied; a sine of amplitude `A` reads `A` at its frequency. Times are the
+/// samples' `t`, or their receive time without one. Silences are interpolated across.
+#[utoipa::path(
+    get,
+    path = "/api/spectrum",
+    params(SpectrumParams),
+    responses(
+        (status = 200, description = "One-sided amplitude spectrum", body = Spectrum),
+        (status = 400, description = "Invalid query parameters, or too few samples in the range", body = ErrorBody),
+        (status = 413, description = "The range would resample to more than --spectrum-max-points", body = ErrorBody),
+    )
+)]
+async fn spectrum(
+    State(state): State<AppState>,
+    query: Result<Query<SpectrumParams>, QueryRejection>,
+) -> Result<Json<Spectrum>, ApiError> {
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    if p.rate_hz.is_some_and(|rate| !(rate > 0.0 && rate <= spectrum::MAX_RATE_HZ)) {
+        return Err(ApiError::BadRequest(format!(
+            "`rate_hz` must be positive and at most {}",
+            spectrum::MAX_RATE_HZ
+        )));
+    }
+    let axis = p.axis.unwrap_or_default();
+    let mut samples = Vec::new();
+    {
+        let buf = state.buffer.read().await;
+        // Only parse the messages that can be from the device
+        for msg in buf.iter().filter(|msg| msg.text.contains(p.ua.as_str())) {
+            let Ok(value) = serde_json::from_str::<Value>(&msg.text) else {
                 continue;
+            };
+            for (ua, t_ms, accel) in acceleration_samples(&value, msg.received_at_ms) {
+                let in_range = p.since.is_none_or(|since| t_ms >= since as f64)
+                    && p.until.is_none_or(|until| t_ms <= until as f64);
+                if ua == p.ua && in_range {
+                    samples.push((t_ms, axis.value(accel)));
+                }
             }
-        };
+        }
+    }
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    samples.dedup_by(|a, b| a.0 == b.0);
+    if samples.len() < spectrum::MIN_SAMPLES {
+        return Err(ApiError::BadRequest(format!(
+            "found {} samples of `{}` in the range, at least {} are needed",
+            samples.len(),
+            p.ua,
+            spectrum::MIN_SAMPLES
+        )));
+    }
+    let times: Vec<f64> = samples.iter().map(|(t, _)| *t).collect();
+    let rate_hz = match p.rate_hz {
+        Some(rate) => rate,
+        None => spectrum::median_rate_hz(&times).expect("samples have distinct times"),
+    };
+    let points = spectrum::resampled_len(times[0], times[times.len() - 1], rate_hz);
+    if points > state.config.spectrum_max_points {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "the range resamples to {} points, more than --spectrum-max-points ({}); narrow `since`/`until` or lower `rate_hz`",
+            points, state.config.spectrum_max_points
+        )));
+    }
+    let resampled = spectrum::resample(&samples, rate_hz);
+    Ok(Json(spectrum::magnitude_spectrum(resampled, rate_hz, samples.len())))
+}
 
-        let (_write, mut read) = ws_stream.split();
+/// Liveness probe: answering at all means the runtime is running.
+#[utoipa::path(get, path = "/livez", responses((status = 200, body = LivenessResponse)))]
+async fn livez() -> Json<LivenessResponse> {
+    Json(LivenessResponse { alive: true })
+}
 
-        while let Some(item) = read.next().await {
-            match item {
-                Ok(msg) => {
-                    if msg.is_text() {
-                        let text = msg.into_text().unwrap_or_default();
+/// Readiness probe: ready once the upstream WebSocket has connected at least once.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Ready", body = ReadinessResponse),
+        (status = 503, description = "Upstream has not connected yet", body = ReadinessResponse),
+    )
+)]
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let ready = state.has_ever_connected.load(Ordering::Relaxed);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadinessResponse { ready }))
+}
 
-                        // Print raw message to stdout
-                        println!("{}", text);
+/// Health check for Docker's `HEALTHCHECK` and similar: 503 when the upstream has been down
+/// for more than `--health-stale-secs`, or with `--health-require-data` while nothing is buffered.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Healthy", body = HealthResponse),
+        (status = 503, description = "Upstream down too long or no data buffered", body = HealthResponse),
+    )
+)]
+async fn healthz(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let mut reasons = Vec::new();
+    let stale_secs = state.config.health_stale_secs;
+    if stale_secs > 0
+        && let Some(since) = state.upstream.read().await.disconnected_since_ms
+    {
+        let down_secs = now_ms().saturating_sub(since) / 1000;
+        if down_secs > stale_secs {
+            reasons.push(format!("upstream disconnected for {}s (limit {}s)", down_secs, stale_secs));
+        }
+    }
+    if state.config.health_require_data && state.buffer.read().await.total_bytes == 0 {
+        reasons.push("no messages buffered".to_string());
+    }
+    let healthy = reasons.is_empty();
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(HealthResponse { healthy, reasons }))
+}
 
-                        // Store message in in-memory buffer capped at ~1GB
-                        {
-                            let mut buf = state.buffer.write().await;
-                            buf.push(text.clone());
-                        }
+/// Uptime and memory use of the collector process, for sizing the buffer limit.
+#[utoipa::path(get, path = "/api/process", responses((status = 200, body = ProcessResponse)))]
+async fn process_info(State(state): State<AppState>) -> Json<ProcessResponse> {
+    Json(ProcessResponse {
+        uptime_secs: state.start_time.elapsed().as_secs(),
+        rss_bytes: process::rss_bytes(),
+        pid: std::process::id(),
+    })
+}
 
-                        // Publish to subscribers
-                        let _ = state.tx.send(text.clone());
+#[utoipa::path(get, path = "/api/status", responses((status = 200, body = UpstreamState)))]
+async fn upstream_status(State(state): State<AppState>) -> Json<UpstreamState> {
+    Json(state.upstream.read().await.clone())
+}
 
-                        // Try to parse JSON to validate
-                        let _ = serde_json::from_str::<Value>(&text).map_err(|e| {
-                            eprintln!("JSON parse error: {}", e);
-                        });
-                    } else if msg.is_binary() {
-                        let bin = msg.into_data();
-                        println!("<binary message: {} bytes>", bin.len());
-                        {
-                            let mut buf = state.buffer.write().await;
-                            buf.push(format!("<binary {} bytes>", bin.len()));
-                        }
-                        let _ = state.tx.send(format!("<binary {} bytes>", bin.len()));
-                    } else if msg.is_close() {
-                        eprintln!("Upstream WebSocket closed. reconnecting...");
-                        break;
-                    } else {
-                        // ignore
+/// Changes the log level of every target at runtime, e.g. to `debug` while investigating,
+/// returning the previous filter. Only served when API credentials are configured.
+#[utoipa::path(
+    post,
+    path = "/api/log-level",
+    request_body = LogLevelRequest,
+    responses(
+        (status = 200, body = LogLevelResponse),
+        (status = 400, description = "Invalid body or unknown level", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "No --api-token, --basic-auth or --jwt-secret is configured", body = ErrorBody),
+    )
+)]
+async fn set_log_level(
+    State(state): State<AppState>,
+    body: Result<Json<LogLevelRequest>, JsonRejection>,
+) -> Result<Json<LogLevelResponse>, ApiError> {
+    // Anyone could flood the logs otherwise
+    if state.api_tokens.is_empty() && state.basic_auth.is_none() && state.jwt.is_none() {
+        return Err(ApiError::NotFound(
+            "`/api/log-level` requires --api-token, --basic-auth or --jwt-secret".to_string(),
+        ));
+    }
+    let Json(body) = body.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let change = state.log_level.set(&body.level).map_err(ApiError::BadRequest)?;
+    tracing::warn!(level = %body.level, previous = %change.previous, "log level changed via API");
+    Ok(Json(LogLevelResponse {
+        level: body.level,
+        previous: change.previous,
+        reset_in_secs: change.resets_in.map(|timeout| timeout.as_secs()),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/reconnect",
+    responses(
+        (status = 200, body = ReconnectResponse),
+        (status = 401, description = "Missing or wrong admin bearer token", body = ErrorBody),
+        (status = 404, description = "Admin API disabled (no --admin-token)", body = ErrorBody),
+    )
+)]
+async fn admin_reconnect(State(state): State<AppState>) -> Json<ReconnectResponse> {
+    let was_connected = {
+        let mut upstream = state.upstream.write().await;
+        upstream.record(
+            ConnectionEventKind::ManualReconnect,
+            Some("requested via API".to_string()),
+        );
+        upstream.connected
+    };
+    // Stores a permit when the upstream task is not waiting right now (connecting, or busy
+    // with a message), so the request is not lost
+    state.reconnect.notify_one();
+    Json(ReconnectResponse { was_connected })
+}
+
+/// Live /ws connections with their peer address and delivery counters.
+#[utoipa::path(
+    get,
+    path = "/api/admin/ws-clients",
+    responses(
+        (status = 200, body = Vec<WsClientDetail>),
+        (status = 401, description = "Missing or wrong admin bearer token", body = ErrorBody),
+        (status = 404, description = "Admin API disabled (no --admin-token)", body = ErrorBody),
+    )
+)]
+async fn admin_ws_clients(State(state): State<AppState>) -> Json<Vec<WsClientDetail>> {
+    Json(state.ws_clients.read().await.details())
+}
+
+/// Per-upstream connection state and counters; a single entry, as one upstream is supported.
+#[utoipa::path(get, path = "/api/sources", responses((status = 200, body = Vec<SourceInfo>)))]
+async fn sources(State(state): State<AppState>) -> Json<Vec<SourceInfo>> {
+    let upstream = state.upstream.read().await;
+    Json(vec![SourceInfo {
+        label: url_host(state.config.url()).to_string(),
+        url: redact_url(state.config.url()),
+        connected: upstream.connected,
+        connected_since_ms: upstream.connected_since_ms,
+        messages: upstream.messages,
+        bytes: upstream.bytes,
+        last_message_ms: upstream.last_message_ms,
+        reconnects: upstream.reconnects,
+    }])
+}
+
+/// Host (with port, if any) of a URL, without scheme, userinfo or path.
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    authority.rsplit_once('@').map_or(authority, |(_, host)| host)
+}
+
+/// Rejects admin requests unless `--admin-token` is set and presented as a bearer token.
+async fn require_admin(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(expected) = state.config.admin_token.as_deref() else {
+        return Err(ApiError::NotFound("admin API is disabled".to_string()));
+    };
+    if !auth::bearer_matches(req.headers(), expected) {
+        return Err(ApiError::Unauthorized("missing or invalid admin token".to_string()));
+    }
+    Ok(next.run(req).await)
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Collects the repeatable `ua` and the `include_unknown` flag (`1`/`true`/`0`/`false`).
+fn ua_filter(pairs: &[(String, String)]) -> Result<UaFilter, ApiError> {
+    let mut filter = UaFilter {
+        agents: Vec::new(),
+        include_unknown: false,
+    };
+    for (key, value) in pairs {
+        match key.as_str() {
+            "ua" => filter.agents.push(value.clone()),
+            "include_unknown" => {
+                filter.include_unknown = match value.as_str() {
+                    "1" | "true" => true,
+                    "0" | "false" => false,
+                    _ => {
+                        return Err(ApiError::BadRequest(
+                            "`include_unknown` must be 1, 0, true or false".to_string(),
+                        ))
                     }
                 }
-                Err(err) => {
-                    eprintln!(
-                        "WebSocket read error: {} (reconnect in {:?})",
-                        err, backoff
-                    );
-                    break;
-                }
             }
+            _ => {}
         }
+    }
+    Ok(filter)
+}
 
-        sleep(backoff).await;
-        backoff = std::cmp::min(backoff * 2, max_backoff);
+/// A `1`/`0`/`true`/`false` switch such as `envelope=1`; the last occurrence wins.
+fn query_flag(pairs: &[(String, String)], name: &str, default: bool) -> Result<bool, ApiError> {
+    match pairs.iter().rev().find(|(key, _)| key == name) {
+        None => Ok(default),
+        Some((_, value)) => match value.as_str() {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(ApiError::BadRequest(format!("`{}` must be 1, 0, true or false", name))),
+        },
     }
 }
 
-async fn run_http_server(state: AppState) {
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/api/messages", get(list_messages))
-        .route("/ws", get(ws_handler))
-        .with_state(state);
+/// Buffered messages after `_seq` `after`, oldest first, each turned into a frame by `render`.
+///
+/// Starts with a `gap` notice when messages right after `after` have already been evicted, so
+/// the client knows to resynchronize; `evicted_before` is the first `_seq` still available.
+fn resume_backlog(
+    buf: &MessageBuffer,
+    after: u64,
+    latest_seq: u64,
+    keep: impl Fn(&BufferedMessage) -> bool,
+    render: impl Fn(&BufferedMessage) -> String,
+) -> Vec<String> {
+    let oldest = buf.iter().find_map(|m| m.seq);
+    let evicted_before = match oldest {
+        Some(oldest) => oldest,
+        // Nothing with a `_seq` is buffered any more
+        None => latest_seq + 1,
+    };
+    let mut backlog = Vec::new();
+    if after + 1 < evicted_before {
+        backlog.push(json!({ "type": "gap", "evicted_before": evicted_before }).to_string());
+    }
+    // `_seq`s grow along the buffer, so only its tail has to be scanned
+    let mut newer: Vec<&BufferedMessage> = buf
+        .iter()
+        .rev()
+        .take_while(|m| m.seq.is_none_or(|seq| seq > after))
+        .filter(|m| m.seq.is_some() && keep(m))
+        .collect();
+    newer.reverse();
+    backlog.extend(newer.into_iter().map(render));
+    backlog
+}
 
-    let addr: SocketAddr = ([0, 0, 0, 0], 3000).into();
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    println!("Web UI available at http://{}/", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+/// The flush period for a /ws `max_hz`, from the query or a `set_rate` control message.
+fn ws_throttle_period(hz: f64) -> Result<Duration, String> {
+    // Also rejects zero, negative and NaN rates, whose period is infinite or invalid
+    match Duration::try_from_secs_f64(1.0 / hz) {
+        Ok(period) if hz <= MAX_WS_HZ => Ok(period),
+        _ => Err(format!("`max_hz` must be a positive number up to {}", MAX_WS_HZ)),
+    }
 }
 
-async fn index() -> impl IntoResponse {
-    Html(INDEX_HTML)
+fn ws_throttle(period: Duration) -> tokio::time::Interval {
+    let mut throttle = tokio::time::interval(period);
+    throttle.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    throttle
 }
 
-async fn list_messages(State(state): State<AppState>, Query(p): Query<ListParams>) -> impl IntoResponse {
-    let limit = p.limit.unwrap_or(500);
-    let buf = state.buffer.read().await;
-    let total = buf.len();
-    let start = total.saturating_sub(limit);
-    let slice: Vec<String> = buf.iter().skip(start).cloned().collect();
-    axum::Json(slice)
+/// One message as a /ws frame in the client's `format`; `encoded` shares the binary encodings
+/// of a live message between clients.
+fn ws_frame(text: String, encoded: Option<&Encoded>, format: WireFormat) -> WsMessage {
+    match encoded.and_then(|encoded| encoded.get(&text, format)) {
+        Some(bytes) => WsMessage::Binary(bytes),
+        None if format == WireFormat::Json => WsMessage::Text(text),
+        None => WsMessage::Binary(encoding::encode(&text, format)),
+    }
 }
 
-async fn ws_handler(State(state): State<AppState>, ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(move |mut socket| async move {
-        let mut rx = state.tx.subscribe();
-        while let Ok(msg) = rx.recv().await {
-            if socket.send(WsMessage::Text(msg)).await.is_err() {
-                break;
-            }
+/// Fans received messages out to a WebSocket client.
+///
+/// With `backfill` the newest buffered messages are sent first, and with `after_seq` those
+/// following a given `_seq`. The subscription is taken before
+/// the buffer is read and live messages already covered by the backfill are skipped by ingest
+/// id, so nothing is lost or repeated at the boundary.
+///
+/// With `envelope=1` every message, buffered or live, is sent as an `Envelope`; the `lagged`
+/// and `gap` notices are not wrapped.
+///
+/// `max_hz` and `batch_ms` only shape live delivery: the former keeps the newest message per
+/// device between flushes, the latter combines messages into one frame, in arrival order.
+///
+/// The client can change its filter and rate or pause delivery at runtime with `Control`
+/// messages, each answered with an `ack` or an `error`. Under --inject-seq it may also
+/// acknowledge what it received with `{"ack":<seq>}`, tracked per connection in `/api/stats`.
+///
+/// Clients are pinged every `--ws-ping-interval-secs` and dropped when they close, error or miss
+/// a pong, so dead connections stop counting as subscribers.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    params(
+        WsParams,
+        ("ua" = Option<Vec<String>>, Query,
+            description = "Only forward messages from these userAgents (repeatable)"),
+        ("include_unknown" = Option<bool>, Query,
+            description = "With `ua`, also forward messages without a userAgent (`1`/`true`)"),
+        ("envelope" = Option<bool>, Query,
+            description = "Wrap each message with its ingest id, receive time and source (`1`/`true`); notices are not wrapped"),
+        ("heartbeat" = Option<bool>, Query,
+            description = "`0`/`false` turns off the --ws-heartbeat messages for this connection"),
+    ),
+    responses(
+        (status = 101, description = "WebSocket upgrade; each frame is one received message, as text (`json`) or binary (`msgpack`/`cbor`)"),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 403, description = "Origin not in the --cors-origin allow-list", body = ErrorBody),
+        (status = 503, description = "--max-ws-clients are already connected", body = ErrorBody),
+    )
+)]
+async fn ws_handler(
+    State(state): State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    claims: Option<Extension<JwtClaims>>,
+    headers: HeaderMap,
+    query: Result<Query<WsParams>, QueryRejection>,
+    Query(pairs): Query<Vec<(String, String)>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    if !cors::ws_origin_allowed(state.config.ws_origins(), &headers) {
+        return Err(ApiError::Forbidden("origin not allowed".to_string()));
+    }
+    let Query(p) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let filter = ua_filter(&pairs)?;
+    let envelope = query_flag(&pairs, "envelope", false)?;
+    let heartbeat_every = match query_flag(&pairs, "heartbeat", true)? {
+        true => state.config.ws_heartbeat,
+        false => None,
+    };
+    let backfill = p.backfill.unwrap_or(0);
+    if backfill > MAX_WS_BACKFILL {
+        return Err(ApiError::BadRequest(format!(
+            "`backfill` must be at most {}",
+            MAX_WS_BACKFILL
+        )));
+    }
+    if p.after_seq.is_some() && !state.config.inject_seq {
+        return Err(ApiError::BadRequest("`after_seq` requires --inject-seq".to_string()));
+    }
+    if p.after_seq.is_some() && p.backfill.is_some() {
+        return Err(ApiError::BadRequest("`after_seq` cannot be combined with `backfill`".to_string()));
+    }
+    let throttle_period = p.max_hz.map(ws_throttle_period).transpose().map_err(ApiError::BadRequest)?;
+    if p.batch_ms.is_some_and(|ms| ms == 0 || ms > MAX_WS_BATCH_MS) {
+        return Err(ApiError::BadRequest(format!(
+            "`batch_ms` must be between 1 and {}",
+            MAX_WS_BATCH_MS
+        )));
+    }
+    let batch_window = p.batch_ms.map(Duration::from_millis);
+    let expr = p
+        .filter
+        .as_deref()
+        .map(wsfilter::Filter::parse)
+        .transpose()
+        .map_err(|err| ApiError::BadRequest(format!("invalid `filter`: {}", err)))?;
+    let peer = peer.map(|ConnectInfo(addr)| addr);
+    let remote_addr = client_ip::resolve(&headers, peer, state.config.trust_proxy);
+    // A JWT only vouches for the client until `exp`, so the connection ends there too
+    let (subject, expires) = match claims {
+        Some(Extension(claims)) => {
+            let left = Duration::from_millis(claims.exp.saturating_mul(1000).saturating_sub(now_ms()));
+            (claims.sub, Some(Instant::now() + left))
         }
-    })
+        None => (None, None),
+    };
+    // Taken before the upgrade so a full server answers with a JSON 503 instead of a 101
+    let slot = state.request_limits.ws_slot()?;
+    let sessions = state.ws_sessions.clone();
+    // Browsers fail the handshake unless the server selects one of the offered subprotocols
+    let ws = ws.protocols([auth::BEARER_PROTOCOL]);
+    Ok(ws.on_upgrade(move |socket| {
+        sessions.track_future(async move {
+            let _slot = slot;
+            let mut rx = state.tx.subscribe();
+            let mut shake_rx = state.shake_notices.subscribe();
+            let format = p.format.unwrap_or_default();
+            let queue = Arc::new(SendQueue::new(state.config.ws_queue_size));
+            let acks = Arc::new(AckTracker::new(state.config.ws_ack_warn_threshold));
+            let client = state.ws_clients.write().await.connect(remote_addr, subject, queue.clone(), acks.clone());
+            let connected_at = Instant::now();
+            state.audit.record(AuditEvent::WsConnect {
+                client,
+                client_ip: remote_addr,
+            });
+            let source = url_host(state.config.url());
+            // Every message handed to the client, so its acks can be matched up
+            let track = |seq: Option<u64>| {
+                if let Some(unacked) = acks.sent(seq) {
+                    tracing::warn!(client, unacked, "/ws client has stopped acknowledging messages");
+                }
+            };
+            let render = |m: &BufferedMessage| {
+                track(m.seq);
+                match envelope {
+                    true => Envelope::new(m.id, m.received_at_ms, source, m.format, m.t_corrected, m.intensity, &m.text).to_json(),
+                    false => m.text.clone(),
+                }
+            };
+            // `?filter=` is fixed for the connection; `set_filter` only replaces the userAgents
+            let keep = |m: &BufferedMessage, filter: &UaFilter| {
+                filter.matches_text(&m.text) && expr.as_ref().is_none_or(|expr| expr.matches(&m.text, m.format))
+            };
+            let (backlog, mut last_sent) = {
+                let buf = state.buffer.read().await;
+                let backlog = match p.after_seq {
+                    Some(after) => {
+                        let latest_seq = state.seq.load(Ordering::Relaxed);
+                        resume_backlog(&buf, after, latest_seq, |m| keep(m, &filter), render)
+                    }
+                    None if filter.is_empty() && expr.is_none() => {
+                        let start = buf.len().saturating_sub(backfill);
+                        buf.iter().skip(start).map(render).collect()
+                    }
+                    // The newest `backfill` messages of the requested devices
+                    None => {
+                        let mut backlog: Vec<String> = buf
+                            .iter()
+                            .rev()
+                            .filter(|m| keep(m, &filter))
+                            .take(backfill)
+                            .map(render)
+                            .collect();
+                        backlog.reverse();
+                        backlog
+                    }
+                };
+                (backlog, buf.last_id)
+            };
+            let (mut sink, mut stream) = socket.split();
+            for text in backlog {
+                let frame = ws_frame(text, None, format);
+                queue.record_sent(&frame);
+                if sink.send(frame).await.is_err() {
+                    state.ws_clients.write().await.disconnect(client);
+                    return;
+                }
+            }
+            // Sends at the client's pace, so the loop below keeps up with the broadcast however
+            // slow the link is; only this client's queue fills up
+            let mut writer = tokio::spawn({
+                let queue = queue.clone();
+                async move {
+                    while let Some(message) = queue.pop().await {
+                        if sink.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+            let drop_oldest = !state.config.ws_strict;
+            let ping_interval = match state.config.ws_ping_interval_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            };
+            let pong_timeout = Duration::from_secs(state.config.ws_pong_timeout_secs);
+            let mut pinger = ping_interval.map(|every| {
+                let mut pinger = tokio::time::interval_at(Instant::now() + every, every);
+                pinger.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                pinger
+            });
+            // Set while a ping is waiting for its pong
+            let mut pong_deadline: Option<Instant> = None;
+            let mut heartbeat = heartbeat_every.map(|every| {
+                let mut heartbeat = tokio::time::interval_at(Instant::now() + every, every);
+                heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                heartbeat
+            });
+            let render = |msg: LiveMessage| match envelope {
+                true => Envelope::new(msg.id, msg.received_at_ms, source, msg.format, msg.t_corrected, msg.intensity, &msg.text).to_json(),
+                false => msg.text,
+            };
+            let live_frame = |msg: LiveMessage| match envelope {
+                true => ws_frame(render(msg), None, format),
+                false => ws_frame(msg.text, Some(&msg.encoded), format),
+            };
+            // With batch_ms, live messages collected for the next combined frame
+            let mut batch = batch_window.map(|window| Batch::new(window, envelope));
+            // With max_hz, the newest message of each device (by userAgents) waiting for the
+            // next flush
+            let mut throttle = throttle_period.map(ws_throttle);
+            let mut latest: BTreeMap<Arc<[String]>, LiveMessage> = BTreeMap::new();
+            // The throttled messages in arrival order, through the batch if there is one
+            let flush_latest = |latest: &mut BTreeMap<Arc<[String]>, LiveMessage>, batch: &mut Option<Batch>| {
+                let mut flushed: Vec<LiveMessage> = std::mem::take(latest).into_values().collect();
+                flushed.sort_unstable_by_key(|msg| msg.id);
+                flushed.iter().for_each(|msg| track(msg.seq));
+                match batch.as_mut() {
+                    Some(batch) => flushed
+                        .into_iter()
+                        .filter_map(|msg| batch.push(&render(msg)))
+                        .map(|full| ws_frame(full, None, format))
+                        .collect(),
+                    None => flushed.into_iter().map(live_frame).collect::<Vec<_>>(),
+                }
+            };
+            let mut filter = filter;
+            // Set by a `pause` control message; live messages are skipped, and counted, until
+            // `resume`
+            let mut paused = false;
+            let mut skipped: u64 = 0;
+            loop {
+                let batch_due = batch.as_ref().and_then(Batch::deadline);
+                let outgoing = tokio::select! {
+                    msg = rx.recv() => match msg {
+                        // Already sent as part of the backfill
+                        Ok(msg) if msg.id <= last_sent => continue,
+                        Ok(msg) if !filter.matches(&msg.user_agents) => continue,
+                        Ok(msg) if expr.as_ref().is_some_and(|expr| !expr.matches(&msg.text, msg.format)) => continue,
+                        Ok(msg) if paused => {
+                            last_sent = msg.id;
+                            skipped += 1;
+                            continue;
+                        }
+                        Ok(msg) if throttle.is_some() => {
+                            last_sent = msg.id;
+                            latest.insert(msg.user_agents.clone(), msg);
+                            continue;
+                        }
+                        Ok(msg) => {
+                            last_sent = msg.id;
+                            track(msg.seq);
+                            match batch.as_mut() {
+                                Some(batch) => match batch.push(&render(msg)) {
+                                    Some(full) => vec![ws_frame(full, None, format)],
+                                    None => continue,
+                                },
+                                None => vec![live_frame(msg)],
+                            }
+                        }
+                        // Only if this loop itself falls behind; slow links overflow the send
+                        // queue instead
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!(client, missed, "/ws client lagged behind the broadcast");
+                            state.lag_dropped.fetch_add(missed, Ordering::Relaxed);
+                            state.ws_clients.write().await.lagged(client, missed);
+                            if state.config.ws_disconnect_on_lag {
+                                tracing::info!(client, "disconnecting /ws client that lagged behind the broadcast (--ws-disconnect-on-lag)");
+                                state.ws_clients.write().await.lag_disconnected();
+                                let frame = CloseFrame {
+                                    code: close_code::POLICY,
+                                    reason: "lagged".into(),
+                                };
+                                queue.close_with(Some(WsMessage::Close(Some(frame))));
+                                break;
+                            }
+                            let notice = json!({ "type": "lagged", "missed": missed }).to_string();
+                            // After the batched messages that arrived before the skip
+                            let mut frames: Vec<WsMessage> = batch
+                                .as_mut()
+                                .and_then(Batch::take)
+                                .map(|pending| ws_frame(pending, None, format))
+                                .into_iter()
+                                .collect();
+                            frames.push(ws_frame(notice, None, format));
+                            frames
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    // Under --sta-lta; like the other notices, sent while paused and never batched
+                    notice = shake_rx.recv() => match notice {
+                        Ok(transition) if filter.matches(std::slice::from_ref(&transition.event().user_agent)) => {
+                            vec![ws_frame(transition.to_json(), None, format)]
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    // Only polled while something is waiting, so the first message after a quiet
+                    // spell goes out at once
+                    _ = async { throttle.as_mut().unwrap().tick().await }, if !latest.is_empty() => {
+                        flush_latest(&mut latest, &mut batch)
+                    }
+                    _ = tokio::time::sleep_until(batch_due.unwrap_or_else(Instant::now)), if batch_due.is_some() => {
+                        batch
+                            .as_mut()
+                            .and_then(Batch::take)
+                            .map(|pending| ws_frame(pending, None, format))
+                            .into_iter()
+                            .collect()
+                    }
+                    // Reading is what notices closes and dead peers on an otherwise idle stream;
+                    // pings from the client are answered by the WebSocket layer
+                    incoming = stream.next() => match incoming {
+                        Some(Ok(WsMessage::Pong(_))) => {
+                            pong_deadline = None;
+                            continue;
+                        }
+                        Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                        // The connection passed `require_auth` on upgrade, and a control message
+                        // only changes what this client receives
+                        Some(Ok(WsMessage::Text(text))) => {
+                            let mut frames = Vec::new();
+                            let reply = match Incoming::parse(&text) {
+                                Err(err) => wscontrol::error(&err),
+                                Ok(Incoming::Ack(_)) if !state.config.inject_seq => {
+                                    wscontrol::error("`ack` requires --inject-seq")
+                                }
+                                // Not answered, so acking every message does not double the traffic
+                                Ok(Incoming::Ack(seq)) => {
+                                    acks.ack(seq);
+                                    continue;
+                                }
+                                Ok(Incoming::Control(Control::SetFilter { ua, include_unknown })) => {
+                                    let applied = json!({ "ua": ua, "include_unknown": include_unknown });
+                                    filter = UaFilter {
+                                        agents: ua,
+                                        include_unknown,
+                                    };
+                                    wscontrol::ack(applied)
+                                }
+                                Ok(Incoming::Control(Control::SetRate { max_hz })) => {
+                                    match max_hz.map(ws_throttle_period).transpose() {
+                                        Err(err) => wscontrol::error(&err),
+                                        Ok(period) => {
+                                            throttle = period.map(ws_throttle);
+                                            // Messages held for the old rate go out now rather
+                                            // than wait for a flush that may no longer come
+                                            frames = flush_latest(&mut latest, &mut batch);
+                                            wscontrol::ack(json!({ "max_hz": max_hz }))
+                                        }
+                                    }
+                                }
+                                Ok(Incoming::Control(Control::Pause)) => {
+                                    paused = true;
+                                    wscontrol::ack(json!({ "paused": true }))
+                                }
+                                Ok(Incoming::Control(Control::Resume)) => {
+                                    paused = false;
+                                    let applied = json!({ "paused": false, "skipped": skipped });
+                                    skipped = 0;
+                                    wscontrol::ack(applied)
+                                }
+                            };
+                            frames.push(ws_frame(reply, None, format));
+                            frames
+                        }
+                        Some(Ok(WsMessage::Binary(_))) => {
+                            let reply = wscontrol::error("control messages must be JSON text frames");
+                            vec![ws_frame(reply, None, format)]
+                        }
+                        Some(Ok(_)) => continue,
+                    },
+                    _ = async { pinger.as_mut().unwrap().tick().await }, if pinger.is_some() => {
+                        pong_deadline.get_or_insert_with(|| Instant::now() + pong_timeout);
+                        vec![WsMessage::Ping(Vec::new())]
+                    }
+                    // Not data: left out of the sent counters, and skipped rather than evicting a
+                    // message when the queue is full, since the link is busy then anyway
+                    _ = async { heartbeat.as_mut().unwrap().tick().await }, if heartbeat.is_some() => {
+                        let last_seq = state.config.inject_seq.then(|| state.seq.load(Ordering::Relaxed));
+                        let beat = json!({ "type": "heartbeat", "server_time": now_ms(), "last_seq": last_seq });
+                        let _ = queue.push_uncounted(ws_frame(beat.to_string(), None, format), false);
+                        continue;
+                    }
+                    _ = tokio::time::sleep_until(pong_deadline.unwrap_or_else(Instant::now)), if pong_deadline.is_some() => {
+                        tracing::info!(client, "dropping /ws client that did not answer a ping within {:?}", pong_timeout);
+                        break;
+                    }
+                    _ = tokio::time::sleep_until(expires.unwrap_or_else(Instant::now)), if expires.is_some() => {
+                        tracing::info!(client, "closing /ws client whose JWT expired");
+                        let frame = CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "token expired".into(),
+                        };
+                        queue.close_with(Some(WsMessage::Close(Some(frame))));
+                        break;
+                    }
+                    // A send failed, so the connection is gone
+                    _ = &mut writer => break,
+                    _ = state.shutdown.cancelled() => {
+                        let frame = CloseFrame {
+                            code: close_code::AWAY,
+                            reason: "server shutting down".into(),
+                        };
+                        queue.close_with(Some(WsMessage::Close(Some(frame))));
+                        break;
+                    }
+                };
+                if outgoing.into_iter().any(|message| queue.push(message, drop_oldest).is_err()) {
+                    tracing::info!(client, "disconnecting /ws client whose send queue overflowed (--ws-strict)");
+                    state.ws_clients.write().await.too_slow();
+                    let frame = CloseFrame {
+                        code: close_code::POLICY,
+                        reason: "too slow".into(),
+                    };
+                    queue.close_with(Some(WsMessage::Close(Some(frame))));
+                    break;
+                }
+            }
+            queue.close_with(None);
+            if !writer.is_finished()
+                && tokio::time::timeout(WS_CLOSE_TIMEOUT, &mut writer).await.is_err()
+            {
+                writer.abort();
+            }
+            state.ws_clients.write().await.disconnect(client);
+            state.audit.record(AuditEvent::WsDisconnect {
+                client,
+                client_ip: remote_addr,
+                duration_ms: connected_at.elapsed().as_millis() as u64,
+            });
+        })
+    }))
+}
+
+/// `/ws` limited to the upstream with the given label (as in `/api/sources`); the query
+/// parameters are the same.
+#[utoipa::path(
+    get,
+    path = "/ws/{source}",
+    params(
+        ("source" = String, Path, description = "Upstream label, as in `/api/sources`"),
+        WsParams,
+    ),
+    responses(
+        (status = 101, description = "WebSocket upgrade, as for `/ws`"),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 403, description = "Origin not in the --cors-origin allow-list", body = ErrorBody),
+        (status = 404, description = "No upstream with this label", body = ErrorBody),
+        (status = 503, description = "--max-ws-clients are already connected", body = ErrorBody),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+async fn ws_source_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(source): axum::extract::Path<String>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    claims: Option<Extension<JwtClaims>>,
+    headers: HeaderMap,
+    query: Result<Query<WsParams>, QueryRejection>,
+    pairs: Query<Vec<(String, String)>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // Every message comes from the one upstream, so its label gets the whole stream
+    if source != url_host(state.config.url()) {
+        return Err(ApiError::NotFound(format!("no upstream `{}`", source)));
+    }
+    ws_handler(State(state), peer, claims, headers, query, pairs, ws).await
 }
 
 // Simple embedded HTML for the frontend
@@ -221,7 +4658,7 @@ const INDEX_HTML: &str = r#"<!doctype html>
     <meta charset="utf-8" />
     <meta name="viewport" content="width=device-width, initial-scale=1" />
     <title>yurecollect</title>
-    <link rel="stylesheet" href="https://unpkg.com/uplot@1.6.27/dist/uPlot.min.css" />
+    <link rel="stylesheet" href="{{UPLOT_CSS}}" />
     <style>
         :root { color-scheme: light dark; }
         body { font-family: system-ui, sans-serif; margin: 0; }
@@ -231,8 +4668,37 @@ const INDEX_HTML: &str = r#"<!doctype html>
         .item { padding: 6px 8px; border: 1px solid #8884; border-radius: 6px; white-space: pre-wrap; font-family: ui-monospace, Menlo, monospace; font-size: 12px; }
         .meta { color: #888; font-size: 12px; }
     </style>
-    <script src="https://unpkg.com/uplot@1.6.27/dist/uPlot.iife.min.js"></script>
+    <script src="{{UPLOT_JS}}"></script>
     <script>
+        // Where the API and the live stream are, under --base-path and --namespace
+        const API_PATH = '{{API_PATH}}';
+        const WS_PATH = '{{WS_PATH}}';
+        // API token for a server with --api-token whose page is public (--public-ui): taken from
+        // a `#token=` fragment, which is then dropped from the address bar, or remembered from
+        // an earlier visit
+        const TOKEN = (() => {
+            const params = new URLSearchParams(location.hash.slice(1));
+            const fromHash = params.get('token');
+            if (fromHash) {
+                localStorage.setItem('yurecollect.token', fromHash);
+                params.delete('token');
+                const rest = params.toString();
+                history.replaceState(null, '', location.pathname + location.search + (rest ? '#' + rest : ''));
+                return fromHash;
+            }
+            return localStorage.getItem('yurecollect.token');
+        })();
+        // `#from=<ms>&to=<ms>` (UNIX ms, as in chat alert links) pins the chart to that range
+        // instead of following the last WINDOW_SECONDS
+        const PINNED_RANGE = (() => {
+            const params = new URLSearchParams(location.hash.slice(1));
+            const from = Number(params.get('from'));
+            const to = Number(params.get('to'));
+            if (!params.has('from') || !params.has('to') || !(from < to)) return null;
+            return [from / 1000, to / 1000];
+        })();
+        const AUTH_HEADERS = TOKEN ? { Authorization: 'Bearer ' + TOKEN } : {};
+
         async function boot() {
             // let logEl = document.getElementById('log');
             let chartEl = document.getElementById('chart');
@@ -257,9 +4723,19 @@ const INDEX_HTML: &str = r#"<!doctype html>
             const tArr = [];  // timestamps (seconds)
             const uaSeries = new Map(); // ua -> { ax:[], ay:[], az:[] }
             const uaOrder = [];
-            const MAX_POINTS = 20000;
-            const WINDOW_SECONDS = 3600; // show last 60s; right edge anchored to now
+            let MAX_POINTS = 20000;
+            let WINDOW_SECONDS = 3600; // show last hour; right edge anchored to now
+            let INITIAL_LIMIT = 500;
             const UPDATE_INTERVAL_MS = 100; // throttle graph updates
+
+            // Server-provided chart settings (defaults above are used if unavailable)
+            try {
+                const res = await fetch(API_PATH + '/config', { headers: AUTH_HEADERS });
+                const cfg = await res.json();
+                MAX_POINTS = cfg.chart?.max_points ?? MAX_POINTS;
+                WINDOW_SECONDS = cfg.chart?.window_seconds ?? WINDOW_SECONDS;
+                INITIAL_LIMIT = cfg.chart?.initial_limit ?? INITIAL_LIMIT;
+            } catch (e) { console.error(e); }
             let updateScheduled = false;
 
             function scheduleUpdate() {
@@ -317,10 +4793,11 @@ const INDEX_HTML: &str = r#"<!doctype html>
                     scales: {
                         x: {
                             time: true,
-                            // Left edge: auto (data min), Right edge: browser now
+                            // Left edge: data min within the window, Right edge: browser now
                             range: (u, min, _max) => {
+                                if (PINNED_RANGE) return PINNED_RANGE;
                                 const now = Date.now() / 1000;
-                                return [min, now];
+                                return [Math.max(min, now - WINDOW_SECONDS), now];
                             },
                         },
                         // y: { range: [-2.0, 2.0] },
@@ -374,16 +4851,12 @@ const INDEX_HTML: &str = r#"<!doctype html>
                 scheduleUpdate();
             }
 
-            // Initial fetch of recent messages
-            try {
-                const res = await fetch('/api/messages?limit=500');
-                const arr = await res.json();
-                arr.forEach(addItem);
-            } catch (e) { console.error(e); }
-
-            // Live updates via WebSocket
+            // Live updates via WebSocket; the first connection starts with the recent messages
             const proto = location.protocol === 'https:' ? 'wss' : 'ws';
-            const wsUrl = proto + '://' + location.host + '/ws';
+            const wsUrl = proto + '://' + location.host + WS_PATH;
+            // A pinned range is usually in the past, so start with as much of the buffer as
+            // the chart holds and /ws backfills (10000)
+            let backfill = PINNED_RANGE ? Math.min(MAX_POINTS, 10000) : INITIAL_LIMIT;
             let ws = null;
             let reconnectTimer = null;
             let reconnectDelayMs = 500;
@@ -405,7 +4878,15 @@ const INDEX_HTML: &str = r#"<!doctype html>
             function connectWs() {
                 if (manuallyClosed) return;
                 try {
-                    ws = new WebSocket(wsUrl);
+                    const params = new URLSearchParams();
+                    if (backfill > 0) params.set('backfill', backfill);
+                    // The token goes in the `bearer` subprotocol, or in the query if it has
+                    // characters a subprotocol cannot carry
+                    const asProtocol = TOKEN && /^[!#$%&'*+.^_`|~0-9A-Za-z-]+$/.test(TOKEN);
+                    if (TOKEN && !asProtocol) params.set('token', TOKEN);
+                    const query = params.toString();
+                    ws = new WebSocket(query ? wsUrl + '?' + query : wsUrl, asProtocol ? ['bearer', TOKEN] : []);
+                    backfill = 0;
                 } catch (e) {
                     console.error(e);
                     scheduleReconnect();
@@ -444,6 +4925,8 @@ const INDEX_HTML: &str = r#"<!doctype html>
                     const parsed = JSON.parse(text);
                     if (Array.isArray(parsed)) {
                         for (const item of parsed) {
+                            // Rejected by --outlier-filter
+                            if (item.outlier === true) continue;
                             const t = item.t ?? item.time ?? Date.now();
                             const x = item.x ?? item.ax ?? item.accelerationX ?? item.acceleration?.x ?? null;
                             const y = item.y ?? item.ay ?? item.accelerationY ?? item.acceleration?.y ?? null;
@@ -453,6 +4936,12 @@ const INDEX_HTML: &str = r#"<!doctype html>
                         }
                         return;
                     } else if (parsed && typeof parsed === 'object') {
+                        // Server notice that this tab fell behind and skipped messages
+                        if (parsed.type === 'lagged') {
+                            console.warn(`ws lagged, ${parsed.missed} messages skipped`);
+                            return;
+                        }
+                        if (parsed.outlier === true) return;
                         const t = parsed.t ?? parsed.time ?? Date.now();
                         const x = parsed.x ?? parsed.ax ?? parsed.accelerationX ?? parsed.acceleration?.x ?? null;
                         const y = parsed.y ?? parsed.ay ?? parsed.accelerationY ?? parsed.acceleration?.y ?? null;
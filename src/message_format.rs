@@ -0,0 +1,58 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// How a received text message is encoded, detected at ingest so non-JSON upstreams are
+/// labelled rather than treated as broken JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageFormat {
+    Json,
+    /// `key=value&...` pairs, as in an HTML form body
+    FormEncoded,
+    /// A single comma-separated line
+    Csv,
+    /// None of the above (including the placeholders for binary frames)
+    Raw,
+}
+
+impl MessageFormat {
+    /// Detects the format of a text message that did not parse as JSON (`json` tells whether
+    /// it did, since ingest parses it anyway). Tried in the order form data, then CSV.
+    pub fn detect(text: &str, json: bool) -> Self {
+        if json {
+            MessageFormat::Json
+        } else if is_form_encoded(text) {
+            MessageFormat::FormEncoded
+        } else if is_csv_line(text) {
+            MessageFormat::Csv
+        } else {
+            MessageFormat::Raw
+        }
+    }
+
+    /// The name it is serialized as.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageFormat::Json => "json",
+            MessageFormat::FormEncoded => "form_encoded",
+            MessageFormat::Csv => "csv",
+            MessageFormat::Raw => "raw",
+        }
+    }
+}
+
+/// Any string decodes as form data, so every `&`-separated pair must also have a key and `=`.
+fn is_form_encoded(text: &str) -> bool {
+    let pairs_ok = !text.is_empty()
+        && !text.contains(char::is_whitespace)
+        && text
+            .split('&')
+            .all(|pair| pair.split_once('=').is_some_and(|(key, _)| !key.is_empty()));
+    pairs_ok && serde_urlencoded::from_str::<Vec<(String, String)>>(text).is_ok()
+}
+
+fn is_csv_line(text: &str) -> bool {
+    let line = text.strip_suffix('\n').unwrap_or(text);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    line.contains(',') && !line.contains(['\n', '\r'])
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+/// A --namespaces-file entry.
+pub struct NamespaceEntry {
+    pub name: String,
+    pub url: String,
+}
+
+/// Reads a --namespaces-file: one `<name> <upstream URL>` per line, with blank lines and lines
+/// starting with `#` skipped.
+pub fn load(path: &Path) -> Result<Vec<NamespaceEntry>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut entries: Vec<NamespaceEntry> = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = |err: String| format!("{}:{}: {}", path.display(), n + 1, err);
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(url), None) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(at("expected `<name> <upstream URL>`".to_string()));
+        };
+        let name = crate::cli::parse_namespace(name).map_err(at)?;
+        if entries.iter().any(|entry| entry.name == name) {
+            return Err(at(format!("namespace `{}` is listed twice", name)));
+        }
+        entries.push(NamespaceEntry { name, url: url.to_string() });
+    }
+    if entries.is_empty() {
+        return Err(format!("{}: no namespaces", path.display()));
+    }
+    Ok(entries)
+}
+
+/// Splits a request path (below --base-path) into its namespace and the path the namespace's
+/// routes see: `/api/<ns>/x` is `/api/x`, `/ws/<ns>` is `/ws` and any other `/<ns>/x` is `/x`.
+pub fn split(path: &str) -> Option<(&str, String)> {
+    let rest = path.strip_prefix('/')?;
+    let (first, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    match first {
+        "api" | "ws" => {
+            let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+            let inner = match rest {
+                "" => format!("/{}", first),
+                rest => format!("/{}/{}", first, rest),
+            };
+            Some((name, inner)).filter(|(name, _)| !name.is_empty())
+        }
+        "" => None,
+        name => Some((name, format!("/{}", rest))),
+    }
+}
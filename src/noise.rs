@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A window whose vector RMS is at most this many times the noise floor counts as rest.
+pub const MOTION_FACTOR: f64 = 3.0;
+/// Time constant the noise floor follows rest windows with.
+const QUIET_TAU_MS: f64 = 5.0 * 60.0 * 1000.0;
+/// Pushes after which the running sums are recomputed, so rounding errors do not pile up.
+const RESUM_EVERY: u32 = 10_000;
+
+/// RMS of three-axis samples over a sliding window of sample time, kept up to date with
+/// running sums.
+///
+/// The RMS is taken about each axis' mean over the window, so gravity and sensor bias do not
+/// count. Values are summed relative to the first sample, which keeps the sums of squares
+/// small next to gravity and the variance free of cancellation.
+pub struct RollingRms {
+    window_ms: f64,
+    reference: Option<[f64; 3]>,
+    samples: VecDeque<(f64, [f64; 3])>,
+    sum: [f64; 3],
+    sum_sq: [f64; 3],
+    since_resum: u32,
+}
+
+impl RollingRms {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window_ms: window.as_secs_f64() * 1000.0,
+            reference: None,
+            samples: VecDeque::new(),
+            sum: [0.0; 3],
+            sum_sq: [0.0; 3],
+            since_resum: 0,
+        }
+    }
+
+    /// Adds a sample taken at `t_ms`, dropping those that fell out of the window. Repeated or
+    /// out-of-order `t` is ignored, and `false` returned.
+    pub fn push(&mut self, t_ms: f64, accel: [f64; 3]) -> bool {
+        if self.samples.back().is_some_and(|(last, _)| t_ms <= *last) {
+            return false;
+        }
+        let reference = *self.reference.get_or_insert(accel);
+        let value: [f64; 3] = std::array::from_fn(|axis| accel[axis] - reference[axis]);
+        self.samples.push_back((t_ms, value));
+        accumulate(&mut self.sum, &mut self.sum_sq, value, 1.0);
+        while let Some(&(t, old)) = self.samples.front() {
+            if t_ms - t < self.window_ms {
+                break;
+            }
+            self.samples.pop_front();
+            accumulate(&mut self.sum, &mut self.sum_sq, old, -1.0);
+        }
+        self.since_resum += 1;
+        if self.since_resum >= RESUM_EVERY {
+            self.resum();
+        }
+        true
+    }
+
+    fn resum(&mut self) {
+        self.since_resum = 0;
+        self.sum = [0.0; 3];
+        self.sum_sq = [0.0; 3];
+        for (_, value) in &self.samples {
+            accumulate(&mut self.sum, &mut self.sum_sq, *value, 1.0);
+        }
+    }
+
+    /// Samples in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// RMS of each axis about its mean; `None` with fewer than two samples.
+    pub fn axes(&self) -> Option<[f64; 3]> {
+        let n = self.samples.len();
+        if n < 2 {
+            return None;
+        }
+        let n = n as f64;
+        Some(std::array::from_fn(|axis| {
+            let mean = self.sum[axis] / n;
+            (self.sum_sq[axis] / n - mean * mean).max(0.0).sqrt()
+        }))
+    }
+
+    /// RMS of the vector, i.e. the root of the axes' mean squares summed.
+    pub fn vector(&self) -> Option<f64> {
+        self.axes().map(|axes| axes.iter().map(|rms| rms * rms).sum::<f64>().sqrt())
+    }
+}
+
+/// Adds `value` to the running sums, or with a `sign` of -1 takes it out.
+fn accumulate(sum: &mut [f64; 3], sum_sq: &mut [f64; 3], value: [f64; 3], sign: f64) {
+    for (axis, v) in value.into_iter().enumerate() {
+        sum[axis] += sign * v;
+        sum_sq[axis] += sign * v * v;
+    }
+}
+
+/// A device's long-term RMS at rest: follows the windows whose RMS is within `MOTION_FACTOR`
+/// of it, so motion does not raise it.
+#[derive(Default)]
+pub struct NoiseFloor {
+    floor: Option<f64>,
+}
+
+impl NoiseFloor {
+    /// Takes the RMS of a full window, `dt_ms` after the previous one, and tells whether the
+    /// device is at rest. The first window sets the floor; if the device was moving then,
+    /// quieter windows bring it down within `QUIET_TAU_MS`.
+    pub fn update(&mut self, rms: f64, dt_ms: f64) -> bool {
+        let Some(floor) = self.floor.as_mut() else {
+            self.floor = Some(rms);
+            return true;
+        };
+        if rms > MOTION_FACTOR * *floor {
+            return false;
+        }
+        *floor += (1.0 - (-dt_ms / QUIET_TAU_MS).exp()) * (rms - *floor);
+        true
+    }
+
+    pub fn get(&self) -> Option<f64> {
+        self.floor
+    }
+}
+
+/// A device's noise as reported by `/api/noise` and `/api/devices`, in the unit of its
+/// `x`/`y`/`z`.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct NoiseReading {
+    /// Samples in the window
+    pub samples: usize,
+    /// RMS of each axis about its mean over the window
+    pub rms_x: f64,
+    pub rms_y: f64,
+    pub rms_z: f64,
+    /// RMS of the vector over the window
+    pub rms: f64,
+    /// Noise floor: the long-term `rms` at rest; null until the device has sent for a whole
+    /// window
+    pub quiet_rms: Option<f64>,
+    /// Whether the last full window's `rms` was within 3 times `quiet_rms`
+    pub at_rest: bool,
+}
+
+struct DeviceNoise {
+    first_t_ms: f64,
+    last_t_ms: f64,
+    rms: RollingRms,
+    floor: NoiseFloor,
+    at_rest: bool,
+}
+
+/// Per-userAgent rolling RMS and noise floor, updated at ingest.
+pub struct NoiseStats {
+    window: Duration,
+    devices: HashMap<String, DeviceNoise>,
+}
+
+impl NoiseStats {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Records a sample of `ua` taken at `t_ms` (device clock).
+    pub fn record(&mut self, ua: &str, t_ms: f64, accel: [f64; 3]) {
+        let device = match self.devices.get_mut(ua) {
+            Some(device) => device,
+            None => self.devices.entry(ua.to_string()).or_insert_with(|| DeviceNoise {
+                first_t_ms: t_ms,
+                last_t_ms: t_ms,
+                rms: RollingRms::new(self.window),
+                floor: NoiseFloor::default(),
+                at_rest: true,
+            }),
+        };
+        if !device.rms.push(t_ms, accel) {
+            return;
+        }
+        let dt_ms = t_ms - device.last_t_ms;
+        device.last_t_ms = t_ms;
+        // A partial window would understate the RMS of slow motion
+        if t_ms - device.first_t_ms < self.window.as_secs_f64() * 1000.0 {
+            return;
+        }
+        if let Some(rms) = device.rms.vector() {
+            device.at_rest = device.floor.update(rms, dt_ms);
+        }
+    }
+
+    pub fn reading(&self, ua: &str) -> Option<NoiseReading> {
+        let device = self.devices.get(ua)?;
+        let [rms_x, rms_y, rms_z] = device.rms.axes()?;
+        Some(NoiseReading {
+            samples: device.rms.len(),
+            rms_x,
+            rms_y,
+            rms_z,
+            rms: device.rms.vector()?,
+            quiet_rms: device.floor.get(),
+            at_rest: device.at_rest,
+        })
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, NoiseReading> {
+        self.devices
+            .keys()
+            .filter_map(|ua| Some((ua.clone(), self.reading(ua)?)))
+            .collect()
+    }
+}
+
+/// Checks against synthetic signals with a known answer.
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+
+    const UA: &str = "synthetic";
+    const T0: f64 = 1_768_000_000_000.0;
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    /// Repeatable uniform noise in [-1, 1), from a xorshift.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0
+        }
+    }
+
+    fn close(actual: f64, expected: f64, tolerance: f64) -> bool {
+        (actual - expected).abs() <= expected * tolerance
+    }
+
+    /// A sine of amplitude A on top of gravity has an RMS of A / sqrt(2), whatever the offset.
+    #[test]
+    fn sine_rms_ignores_the_offset() {
+        let amplitude = 0.02;
+        let mut rms = RollingRms::new(WINDOW);
+        for i in 0..3000 {
+            let t = i as f64 * 10.0;
+            let phase = TAU * 2.0 * t / 1000.0;
+            rms.push(T0 + t, [amplitude * phase.sin(), 0.0, 980.665]);
+        }
+        let [x, y, z] = rms.axes().expect("a full window");
+        assert!(close(x, amplitude / 2f64.sqrt(), 0.001), "rms_x {:.5}", x);
+        assert!(y < 1e-9 && z < 1e-9, "still y and z: {:e}, {:e}", y, z);
+        assert_eq!(rms.len(), 1000, "10 s at 100 Hz");
+    }
+
+    /// Uniform noise of half-width w on every axis: w / sqrt(3) per axis, w on the vector.
+    #[test]
+    fn uniform_noise_rms() {
+        let width = 0.003;
+        let mut noise = Noise(0x2545_f491);
+        let mut rms = RollingRms::new(WINDOW);
+        for i in 0..20_000 {
+            let accel = [noise.next() * width, noise.next() * width, 1.0 + noise.next() * width];
+            rms.push(T0 + i as f64 * 10.0, accel);
+        }
+        let vector = rms.vector().expect("a full window");
+        assert!(close(vector, width, 0.03), "vector rms {:.5}, expected {:.5}", vector, width);
+        // Out-of-order samples are left out
+        assert!(!rms.push(T0 + 19_999.0 * 10.0, [1.0, 1.0, 1.0]));
+    }
+
+    /// Shaking well above the floor leaves it alone, and reads as motion.
+    #[test]
+    fn floor_ignores_motion() {
+        let mut floor = NoiseFloor::default();
+        floor.update(0.001, 10.0);
+        assert!(!floor.update(0.01, 10.0), "a window at 10x the floor is motion");
+        assert_eq!(floor.get(), Some(0.001));
+    }
+
+    /// 60 s of noise, 10 s of shaking, 60 s of noise again.
+    #[test]
+    fn floor_survives_a_shake() {
+        let width = 0.003;
+        let mut stats = NoiseStats::new(WINDOW);
+        let mut noise = Noise(0x1234_5678);
+        let mut quiet_before = None;
+        for i in 0..13_000 {
+            let t = i as f64 * 10.0;
+            let shaking = (6_000..7_000).contains(&i);
+            let shake = if shaking { 0.05 * (TAU * 1.5 * t / 1000.0).sin() } else { 0.0 };
+            let accel = [shake + noise.next() * width, noise.next() * width, 1.0 + noise.next() * width];
+            stats.record(UA, T0 + t, accel);
+            if i == 5_999 {
+                quiet_before = stats.reading(UA).and_then(|reading| reading.quiet_rms);
+            }
+            if i == 6_999 {
+                assert!(!stats.reading(UA).expect("a reading").at_rest, "shaking is not rest");
+            }
+        }
+        let reading = stats.reading(UA).expect("a reading");
+        assert!(quiet_before.is_some_and(|before| close(before, width, 0.05)), "before: {:?}", quiet_before);
+        assert!(reading.quiet_rms.is_some_and(|after| close(after, width, 0.05)), "after: {:?}", reading.quiet_rms);
+        assert!(reading.at_rest, "at rest again once the shaking left the window");
+    }
+}
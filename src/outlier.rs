@@ -0,0 +1,337 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Recent accepted samples per device the median and MAD are taken over.
+const WINDOW: usize = 101;
+/// Samples needed before the MAD rule applies.
+const MIN_HISTORY: usize = 20;
+/// Scales the MAD to a standard deviation for normally distributed noise.
+const MAD_TO_SIGMA: f64 = 1.4826;
+/// The noise scale is never taken below this fraction of the previous sample's magnitude
+/// (gravity, for most phones), so a quiet or coarsely quantized sensor does not turn every
+/// twitch into a spike.
+const MIN_SCALE_FRACTION: f64 = 0.01;
+
+/// What `OutlierFilter::screen` made of a sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Pass,
+    /// Above the absolute cap
+    Outlier,
+    /// Jumped away from both the recent median and the previous sample; whether it was an
+    /// isolated spike depends on the device's next sample
+    Suspect,
+}
+
+/// `screen`'s answer: the verdict on the sample, and on the device's pending suspect if the
+/// sample settled it (`Some(true)` for a spike).
+#[derive(Debug, PartialEq)]
+pub struct Screened {
+    pub previous_spike: Option<bool>,
+    pub verdict: Verdict,
+}
+
+struct Suspect {
+    accel: [f64; 3],
+    // Noise scale per axis when it was judged
+    sigma: [f64; 3],
+}
+
+#[derive(Default)]
+struct DeviceHistory {
+    recent: VecDeque<[f64; 3]>,
+    last: Option<[f64; 3]>,
+    suspect: Option<Suspect>,
+}
+
+impl DeviceHistory {
+    fn accept(&mut self, accel: [f64; 3]) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(accel);
+        self.last = Some(accel);
+    }
+
+    /// Median of each axis over the window, and the noise scale about it.
+    fn median_and_sigma(&self, floor: f64) -> ([f64; 3], [f64; 3]) {
+        let mut median = [0.0; 3];
+        let mut sigma = [0.0; 3];
+        let mut values = Vec::with_capacity(self.recent.len());
+        for axis in 0..3 {
+            values.clear();
+            values.extend(self.recent.iter().map(|sample| sample[axis]));
+            median[axis] = middle(&mut values);
+            for value in &mut values {
+                *value = (*value - median[axis]).abs();
+            }
+            sigma[axis] = (MAD_TO_SIGMA * middle(&mut values)).max(floor);
+        }
+        (median, sigma)
+    }
+}
+
+fn middle(values: &mut [f64]) -> f64 {
+    let mid = values.len() / 2;
+    *values.select_nth_unstable_by(mid, f64::total_cmp).1
+}
+
+fn magnitude(accel: [f64; 3]) -> f64 {
+    accel.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+/// Per-userAgent rejection of corrupt samples: those whose magnitude exceeds an absolute cap,
+/// and isolated single-sample spikes.
+///
+/// A spike is a sample more than `mads` noise scales (the MAD of the recent samples, as a
+/// standard deviation) away from both the device's recent median and its previous sample,
+/// after which the next sample returns to within `mads` scales of that previous one. When the
+/// next sample stays out instead, as after a step or in strong shaking, the sample was real.
+pub struct OutlierFilter {
+    max: Option<f64>,
+    mads: f64,
+    devices: HashMap<String, DeviceHistory>,
+}
+
+impl OutlierFilter {
+    /// `mads` of 0 turns the spike rule off, leaving only the cap.
+    pub fn new(max: Option<f64>, mads: f64) -> Self {
+        Self {
+            max,
+            mads,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Judges the next sample of `ua`, first settling the device's pending suspect with it.
+    pub fn screen(&mut self, ua: &str, accel: [f64; 3]) -> Screened {
+        if self.max.is_some_and(|max| magnitude(accel) > max) {
+            // Garbage says nothing about the pending suspect, which waits for the next sample
+            return Screened {
+                previous_spike: None,
+                verdict: Verdict::Outlier,
+            };
+        }
+        let device = match self.devices.get_mut(ua) {
+            Some(device) => device,
+            None => self.devices.entry(ua.to_string()).or_default(),
+        };
+        let previous_spike = device.suspect.take().map(|suspect| {
+            let returned = device
+                .last
+                .is_some_and(|last| (0..3).all(|axis| (accel[axis] - last[axis]).abs() <= self.mads * suspect.sigma[axis]));
+            if !returned {
+                device.accept(suspect.accel);
+            }
+            returned
+        });
+        let Some(last) = device.last else {
+            device.accept(accel);
+            return Screened {
+                previous_spike,
+                verdict: Verdict::Pass,
+            };
+        };
+        // Within `mads` floors of the previous sample cannot be a jump whatever the MAD is,
+        // which spares the median of most samples
+        let floor = MIN_SCALE_FRACTION * magnitude(last);
+        let jumped = |sigma: [f64; 3]| (0..3).any(|axis| (accel[axis] - last[axis]).abs() > self.mads * sigma[axis]);
+        if self.mads == 0.0 || device.recent.len() < MIN_HISTORY || !jumped([floor; 3]) {
+            device.accept(accel);
+            return Screened {
+                previous_spike,
+                verdict: Verdict::Pass,
+            };
+        }
+        let (median, sigma) = device.median_and_sigma(floor);
+        let far = (0..3).any(|axis| {
+            (accel[axis] - median[axis]).abs() > self.mads * sigma[axis]
+                && (accel[axis] - last[axis]).abs() > self.mads * sigma[axis]
+        });
+        if !far {
+            device.accept(accel);
+            return Screened {
+                previous_spike,
+                verdict: Verdict::Pass,
+            };
+        }
+        device.suspect = Some(Suspect { accel, sigma });
+        Screened {
+            previous_spike,
+            verdict: Verdict::Suspect,
+        }
+    }
+
+    /// Accepts the device's pending suspect without waiting any longer for its next sample.
+    pub fn release(&mut self, ua: &str) {
+        if let Some(device) = self.devices.get_mut(ua)
+            && let Some(suspect) = device.suspect.take()
+        {
+            device.accept(suspect.accel);
+        }
+    }
+}
+
+/// Checks against synthetic samples, in g at 100 Hz with gravity on z. A spike must be caught
+/// whether the device is at rest or shaking, while a step (the phone turned over) and strong
+/// shaking pass untouched.
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+
+    const UA: &str = "synthetic";
+    const MADS: f64 = 10.0;
+
+    /// Repeatable uniform noise in [-1, 1), from a xorshift.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0
+        }
+
+        /// At rest: gravity on z plus a little sensor noise.
+        fn rest(&mut self) -> [f64; 3] {
+            [self.next() * 0.002, self.next() * 0.002, 1.0 + self.next() * 0.002]
+        }
+
+        fn rest_for(&mut self, samples: usize) -> Vec<[f64; 3]> {
+            (0..samples).map(|_| self.rest()).collect()
+        }
+
+        /// 0.5 g at 5 Hz starting abruptly after 5 s of rest.
+        fn shaking(&mut self) -> Vec<[f64; 3]> {
+            (0..2000)
+                .map(|i| {
+                    let mut sample = self.rest();
+                    if i >= 500 {
+                        let phase = TAU * 5.0 * (i - 500) as f64 / 100.0;
+                        sample[0] += 0.5 * phase.sin();
+                        sample[1] += 0.5 * phase.cos();
+                    }
+                    sample
+                })
+                .collect()
+        }
+    }
+
+    /// What happened to a run of samples: which were caught as spikes (by index), which as
+    /// over the cap, and how many were held for a sample before passing.
+    #[derive(Default)]
+    struct Outcome {
+        spikes: Vec<usize>,
+        capped: Vec<usize>,
+        held_then_passed: usize,
+    }
+
+    fn screen_all(filter: &mut OutlierFilter, samples: &[[f64; 3]]) -> Outcome {
+        let mut outcome = Outcome::default();
+        let mut pending = None;
+        for (i, accel) in samples.iter().enumerate() {
+            let Screened { previous_spike, verdict } = filter.screen(UA, *accel);
+            match (previous_spike, pending.take()) {
+                (Some(true), Some(index)) => outcome.spikes.push(index),
+                (Some(false), Some(_)) => outcome.held_then_passed += 1,
+                (None, held) => pending = held,
+                (Some(_), None) => panic!("a suspect was settled that was never reported"),
+            }
+            match verdict {
+                Verdict::Pass => {}
+                Verdict::Outlier => outcome.capped.push(i),
+                Verdict::Suspect => pending = Some(i),
+            }
+        }
+        outcome
+    }
+
+    #[test]
+    fn catches_a_glitch_at_rest() {
+        let mut samples = Noise(0x2545_f491).rest_for(1000);
+        samples[500][0] = 50.0;
+        let outcome = screen_all(&mut OutlierFilter::new(None, MADS), &samples);
+        assert_eq!(outcome.spikes, [500]);
+    }
+
+    /// Turned over: z goes from +1 to -1 and stays there. Held for one sample, then passed.
+    #[test]
+    fn passes_a_step() {
+        let mut samples = Noise(0x2545_f491).rest_for(1000);
+        for sample in &mut samples[500..] {
+            sample[2] -= 2.0;
+        }
+        let outcome = screen_all(&mut OutlierFilter::new(None, MADS), &samples);
+        assert!(outcome.spikes.is_empty(), "spikes {:?}", outcome.spikes);
+        assert!(outcome.held_then_passed <= 1, "{} held then passed", outcome.held_then_passed);
+    }
+
+    #[test]
+    fn passes_strong_shaking() {
+        let samples = Noise(0x2545_f491).shaking();
+        let outcome = screen_all(&mut OutlierFilter::new(None, MADS), &samples);
+        assert!(outcome.spikes.is_empty(), "spikes {:?}", outcome.spikes);
+    }
+
+    #[test]
+    fn catches_a_glitch_while_shaking() {
+        let mut samples = Noise(0x1234_5678).shaking();
+        samples[1500][2] = -50.0;
+        let outcome = screen_all(&mut OutlierFilter::new(None, MADS), &samples);
+        assert_eq!(outcome.spikes, [1500]);
+    }
+
+    /// Two bad samples in a row are not isolated, so the spike rule lets them through, but the
+    /// cap catches them.
+    #[test]
+    fn caps_a_two_sample_glitch() {
+        let mut samples = Noise(0x2545_f491).rest_for(1000);
+        samples[500][0] = 50.0;
+        samples[501][0] = 50.0;
+        let outcome = screen_all(&mut OutlierFilter::new(None, MADS), &samples);
+        assert!(outcome.spikes.is_empty(), "spikes {:?}", outcome.spikes);
+        let outcome = screen_all(&mut OutlierFilter::new(Some(8.0), MADS), &samples);
+        assert_eq!(outcome.capped, [500, 501]);
+        assert!(outcome.spikes.is_empty(), "spikes {:?}", outcome.spikes);
+    }
+
+    /// A capped sample does not settle a pending suspect.
+    #[test]
+    fn cap_between_a_suspect_and_its_next_sample() {
+        let mut noise = Noise(0x2545_f491);
+        let mut filter = OutlierFilter::new(Some(8.0), MADS);
+        for _ in 0..100 {
+            filter.screen(UA, noise.rest());
+        }
+        assert_eq!(filter.screen(UA, [2.0, 0.0, 1.0]).verdict, Verdict::Suspect);
+        assert_eq!(filter.screen(UA, [50.0, 0.0, 1.0]).verdict, Verdict::Outlier);
+        assert_eq!(filter.screen(UA, noise.rest()).previous_spike, Some(true));
+    }
+
+    /// A suspect released without its next sample counts as real: the sample after it is
+    /// judged against it.
+    #[test]
+    fn released_suspect_counts_as_real() {
+        let mut noise = Noise(0x2545_f491);
+        let mut filter = OutlierFilter::new(None, MADS);
+        for _ in 0..100 {
+            filter.screen(UA, noise.rest());
+        }
+        assert_eq!(filter.screen(UA, [2.0, 0.0, 1.0]).verdict, Verdict::Suspect);
+        filter.release(UA);
+        let after = filter.screen(UA, [2.0, 0.0, 1.0]);
+        assert_eq!(after, Screened { previous_spike: None, verdict: Verdict::Pass });
+    }
+
+    /// With the spike rule off only the cap applies.
+    #[test]
+    fn zero_mads_disables_the_spike_rule() {
+        let mut samples = Noise(0x2545_f491).rest_for(1000);
+        samples[500][0] = 5.0;
+        let outcome = screen_all(&mut OutlierFilter::new(Some(8.0), 0.0), &samples);
+        assert!(outcome.spikes.is_empty(), "spikes {:?}", outcome.spikes);
+        assert!(outcome.capped.is_empty(), "capped {:?}", outcome.capped);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The rolling windows besides since-startup.
+const MINUTE_MS: u64 = 60 * 1000;
+const TEN_MINUTES_MS: u64 = 10 * 60 * 1000;
+/// Time constant of the per-axis offset (gravity, sensor bias) removed before taking the
+/// magnitude.
+const OFFSET_TAU_MS: f64 = 10_000.0;
+/// Gravity in each unit the offset is matched against, within ±20%.
+const GRAVITY: [(&str, f64); 3] = [("g", 1.0), ("m/s2", 9.80665), ("gal", 980.665)];
+
+/// The unit whose gravity `magnitude` is, if any.
+fn detect_unit(magnitude: f64) -> Option<&'static str> {
+    GRAVITY
+        .iter()
+        .find(|(_, gravity)| (magnitude - gravity).abs() <= gravity * 0.2)
+        .map(|(unit, _)| *unit)
+}
+
+fn to_gal(value: f64, unit: &str) -> Option<f64> {
+    let (_, gravity) = GRAVITY.iter().find(|(name, _)| *name == unit)?;
+    Some(value * 980.665 / gravity)
+}
+
+/// The largest value in a window, and when it was reached.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct PeakValue {
+    /// In the device's own unit
+    pub value: f64,
+    /// The same in gal, once `unit` is known
+    pub gal: Option<f64>,
+    /// When the sample was received (UNIX ms)
+    pub at_ms: u64,
+}
+
+/// A device's peak ground acceleration as reported by `/api/pga` and `/api/devices`.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct PgaReading {
+    /// Unit the device's `x`/`y`/`z` appear to be in (`g`, `m/s2` or `gal`), from the magnitude
+    /// of their offset at rest, which is gravity; null when that matches none of them (e.g. the
+    /// device removes gravity itself)
+    pub unit: Option<&'static str>,
+    pub last_minute: Option<PeakValue>,
+    pub last_10_minutes: Option<PeakValue>,
+    pub since_startup: Option<PeakValue>,
+}
+
+/// Rolling maximum: `(at_ms, value)` with values decreasing from the front, which is the
+/// maximum of what is still in the window.
+#[derive(Default)]
+struct RollingMax {
+    entries: VecDeque<(u64, f64)>,
+}
+
+impl RollingMax {
+    fn push(&mut self, at_ms: u64, value: f64, window_ms: u64) {
+        while self.entries.back().is_some_and(|(_, v)| *v <= value) {
+            self.entries.pop_back();
+        }
+        self.entries.push_back((at_ms, value));
+        while self.entries.front().is_some_and(|(at, _)| at_ms.saturating_sub(*at) > window_ms) {
+            self.entries.pop_front();
+        }
+    }
+
+    fn max(&self, now_ms: u64, window_ms: u64) -> Option<(u64, f64)> {
+        self.entries
+            .iter()
+            .find(|(at, _)| now_ms.saturating_sub(*at) <= window_ms)
+            .copied()
+    }
+}
+
+struct DevicePeaks {
+    last_t_ms: f64,
+    offset: [f64; 3],
+    minute: RollingMax,
+    ten_minutes: RollingMax,
+    since_startup: Option<(u64, f64)>,
+}
+
+/// Per-userAgent peak ground acceleration: the rolling maxima of the vector acceleration
+/// with its offset removed, updated at ingest.
+#[derive(Default)]
+pub struct PeakAccelerations {
+    devices: HashMap<String, DevicePeaks>,
+}
+
+impl PeakAccelerations {
+    /// Records a sample of `ua` taken at `t_ms` (device clock) and received at `now_ms`.
+    pub fn record(&mut self, ua: &str, t_ms: f64, accel: [f64; 3], now_ms: u64) {
+        let Some(device) = self.devices.get_mut(ua) else {
+            // The first sample is the best guess of the offset there is
+            let device = DevicePeaks {
+                last_t_ms: t_ms,
+                offset: accel,
+                minute: RollingMax::default(),
+                ten_minutes: RollingMax::default(),
+                since_startup: None,
+            };
+            self.devices.insert(ua.to_string(), device);
+            return;
+        };
+        let dt = (t_ms - device.last_t_ms).max(0.0);
+        device.last_t_ms = device.last_t_ms.max(t_ms);
+        let weight = 1.0 - (-dt / OFFSET_TAU_MS).exp();
+        let mut magnitude: f64 = 0.0;
+        for (axis, offset) in device.offset.iter_mut().enumerate() {
+            *offset += weight * (accel[axis] - *offset);
+            magnitude += (accel[axis] - *offset).powi(2);
+        }
+        let magnitude = magnitude.sqrt();
+        device.minute.push(now_ms, magnitude, MINUTE_MS);
+        device.ten_minutes.push(now_ms, magnitude, TEN_MINUTES_MS);
+        if device.since_startup.is_none_or(|(_, peak)| magnitude > peak) {
+            device.since_startup = Some((now_ms, magnitude));
+        }
+    }
+
+    pub fn reading(&self, ua: &str, now_ms: u64) -> Option<PgaReading> {
+        let device = self.devices.get(ua)?;
+        let gravity = device.offset.iter().map(|v| v * v).sum::<f64>().sqrt();
+        let unit = detect_unit(gravity);
+        let peak = |max: Option<(u64, f64)>| {
+            max.map(|(at_ms, value)| PeakValue {
+                value,
+                gal: unit.and_then(|unit| to_gal(value, unit)),
+                at_ms,
+            })
+        };
+        Some(PgaReading {
+            unit,
+            last_minute: peak(device.minute.max(now_ms, MINUTE_MS)),
+            last_10_minutes: peak(device.ten_minutes.max(now_ms, TEN_MINUTES_MS)),
+            since_startup: peak(device.since_startup),
+        })
+    }
+
+    pub fn snapshot(&self, now_ms: u64) -> BTreeMap<String, PgaReading> {
+        self.devices
+            .keys()
+            .filter_map(|ua| Some((ua.clone(), self.reading(ua, now_ms)?)))
+            .collect()
+    }
+}
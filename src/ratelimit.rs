@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use tokio::time::{Duration, Instant};
+
+// Prune idle buckets once the map grows beyond this many clients, and evict the least recently
+// seen one when none is idle
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token-bucket rate limiter keyed by client IP.
+///
+/// Each client may burst up to ten seconds' worth of requests and is then refilled at
+/// `per_minute / 60` requests per second.
+pub struct RateLimiter {
+    rate_per_sec: f64,
+    capacity: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Returns `None` when `per_minute` is 0 (limiting disabled).
+    pub fn new(per_minute: u32) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+        let rate_per_sec = per_minute as f64 / 60.0;
+        Some(Self {
+            rate_per_sec,
+            capacity: (rate_per_sec * 10.0).max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Like `new`, but with an explicit burst size instead of ten seconds' worth.
+    pub fn with_burst(per_minute: u32, burst: u32) -> Option<Self> {
+        let mut limiter = Self::new(per_minute)?;
+        limiter.capacity = burst.max(1) as f64;
+        Some(limiter)
+    }
+
+    /// Takes one token for `ip`, or returns how long to wait before retrying.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&ip) {
+            self.prune(&mut buckets, now);
+            // Every client is active; forgetting one costs it at most a fresh burst
+            if buckets.len() >= MAX_TRACKED_CLIENTS
+                && let Some(oldest) = buckets.iter().min_by_key(|(_, b)| b.updated).map(|(ip, _)| *ip)
+            {
+                buckets.remove(&oldest);
+            }
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate_per_sec).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate_per_sec))
+        }
+    }
+
+    /// Returns how long `ip` must wait for a token, without consuming one.
+    pub fn retry_after(&self, ip: IpAddr) -> Option<Duration> {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.get(&ip)?;
+        let elapsed = Instant::now().duration_since(bucket.updated).as_secs_f64();
+        let tokens = (bucket.tokens + elapsed * self.rate_per_sec).min(self.capacity);
+        (tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - tokens) / self.rate_per_sec))
+    }
+
+    /// Drops buckets that have refilled completely, i.e. clients that went idle.
+    fn prune(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        buckets.retain(|_, b| {
+            let elapsed = now.duration_since(b.updated).as_secs_f64();
+            b.tokens + elapsed * self.rate_per_sec < self.capacity
+        });
+    }
+}
+
+/// A single token bucket, for limiting one stream rather than many clients: holds up to
+/// `capacity` tokens and gains `fill_rate` per second.
+pub struct TokenBucket {
+    fill_rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full, so a burst of `capacity` goes through at once.
+    pub fn new(fill_rate: f64, capacity: f64) -> Self {
+        Self {
+            fill_rate,
+            capacity,
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes `n` tokens, or returns how long until there are enough.
+    pub fn try_consume(&mut self, n: u32) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.fill_rate).min(self.capacity);
+        self.updated = now;
+        let n = n as f64;
+        if self.tokens >= n {
+            self.tokens -= n;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((n - self.tokens) / self.fill_rate))
+        }
+    }
+}
+
+/// Per-class limiters applied by the `rate_limit` middleware.
+pub struct RateLimits {
+    pub cheap: Option<RateLimiter>,
+    pub expensive: Option<RateLimiter>,
+    pub ws: Option<RateLimiter>,
+    /// Budget for failed authentication attempts; exhausted clients get 429 before their
+    /// credentials are even checked.
+    pub auth_failures: Option<RateLimiter>,
+}
+
+impl RateLimits {
+    /// Picks the limiter for a request path; pages outside `/api` and `/ws` are not limited.
+    pub fn for_path(&self, path: &str) -> Option<&RateLimiter> {
+        if path == "/ws" || path.starts_with("/ws/") || path == "/api/graphql/ws" {
+            self.ws.as_ref()
+        } else if path.starts_with("/api/messages")
+            || path == "/api/aggregate"
+            || path == "/api/gaps"
+            || path == "/api/spectrum"
+            || path == "/api/graphql"
+        {
+            self.expensive.as_ref()
+        } else if path.starts_with("/api/") {
+            self.cheap.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn ip(n: u32) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(n))
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen_client_when_all_are_active() {
+        // One request per 10 s with a burst of one: every client below stays active
+        let limiter = RateLimiter::new(6).unwrap();
+        assert!(limiter.check(ip(0)).is_ok());
+        assert!(limiter.check(ip(1)).is_ok());
+        std::thread::sleep(Duration::from_millis(2));
+        for n in 2..MAX_TRACKED_CLIENTS as u32 {
+            assert!(limiter.check(ip(n)).is_ok());
+        }
+        std::thread::sleep(Duration::from_millis(2));
+        // Seen again, which leaves client 1 the least recently seen
+        assert!(limiter.check(ip(0)).is_err());
+
+        assert!(limiter.check(ip(u32::MAX)).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_CLIENTS);
+        assert!(buckets.contains_key(&ip(0)));
+        assert!(!buckets.contains_key(&ip(1)));
+        assert!(buckets.contains_key(&ip(u32::MAX)));
+    }
+
+    #[test]
+    fn prunes_idle_clients_before_evicting_active_ones() {
+        // 600 per second: a client is idle again a few milliseconds after its request
+        let limiter = RateLimiter::with_burst(36_000, 1).unwrap();
+        for n in 0..MAX_TRACKED_CLIENTS as u32 {
+            assert!(limiter.check(ip(n)).is_ok());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(limiter.check(ip(u32::MAX)).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
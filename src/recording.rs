@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::shake::ShakeEvent;
+
+/// Finished recordings remembered for `/api/events/{id}/recording`, oldest forgotten first
+/// (the files themselves stay).
+const MAX_RECORDINGS: usize = 1000;
+/// Cap on the pre-trigger ring, whatever --record-pre-trigger says, so a flood cannot exhaust
+/// memory between events.
+const MAX_PRE_TRIGGER_BYTES: usize = 256 * 1024 * 1024;
+
+/// Where `/api/events/{id}/recording` finds an event's messages.
+pub enum Lookup {
+    Finished(PathBuf),
+    /// The event or one overlapping it is still being recorded
+    Recording,
+    Unknown,
+}
+
+/// The file currently being written, covering one or more overlapping events.
+struct Recording {
+    file: BufWriter<File>,
+    /// Named after the first event until the peak is known, then renamed
+    part_path: PathBuf,
+    start_ms: u64,
+    event_ids: Vec<u64>,
+    /// Events of this recording that have not ended yet
+    ongoing: Vec<u64>,
+    peak_acceleration: f64,
+    peak_intensity: Option<f64>,
+    /// Once every event ended, when the post-roll is over
+    closes_at_ms: Option<u64>,
+}
+
+impl Recording {
+    fn absorb(&mut self, event: &ShakeEvent) {
+        self.peak_acceleration = self.peak_acceleration.max(event.peak_acceleration);
+        self.peak_intensity = match (self.peak_intensity, event.peak_intensity) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// `<start, UTC>-event<first id>-i<peak intensity>.ndjson`, or `-a<peak acceleration>`
+    /// without --intensity.
+    fn file_name(&self) -> String {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_millis(self.start_ms);
+        let start = humantime::format_rfc3339_seconds(start).to_string().replace(['-', ':'], "");
+        let peak = match self.peak_intensity {
+            Some(intensity) => format!("i{:.1}", intensity),
+            None => format!("a{:.3}", self.peak_acceleration),
+        };
+        format!("{}-event{}-{}.ndjson", start, self.event_ids[0], peak)
+    }
+}
+
+/// Event-triggered capture of the raw message stream under --record-dir.
+///
+/// Messages are kept in a ring for --record-pre-trigger. When a --sta-lta event starts, the
+/// ring and every following message go to a file until --record-post-roll after the last
+/// overlapping event has ended; an event starting meanwhile extends the same file.
+pub struct Recorder {
+    dir: PathBuf,
+    pre_trigger_ms: u64,
+    post_roll_ms: u64,
+    /// `(received_at_ms, line)`, oldest first
+    ring: VecDeque<(u64, String)>,
+    ring_bytes: usize,
+    active: Option<Recording>,
+    finished: BTreeMap<u64, PathBuf>,
+}
+
+impl Recorder {
+    /// Creates `dir` if needed.
+    pub fn new(dir: &Path, pre_trigger: Duration, post_roll: Duration) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pre_trigger_ms: pre_trigger.as_millis() as u64,
+            post_roll_ms: post_roll.as_millis() as u64,
+            ring: VecDeque::new(),
+            ring_bytes: 0,
+            active: None,
+            finished: BTreeMap::new(),
+        })
+    }
+
+    /// Takes one line (a message's envelope) received at `now_ms`: written to the open
+    /// recording, or kept in the pre-trigger ring. Returns the file a post-roll that ran out
+    /// closed, if any. A failed write abandons the open recording.
+    pub fn record(&mut self, line: String, now_ms: u64) -> std::io::Result<Option<PathBuf>> {
+        let closed = self.expire(now_ms)?;
+        match &mut self.active {
+            Some(recording) => {
+                // Given up rather than failing again for every following message
+                if let Err(err) = writeln!(recording.file, "{}", line) {
+                    self.active = None;
+                    return Err(err);
+                }
+            }
+            None => {
+                self.ring_bytes += line.len();
+                self.ring.push_back((now_ms, line));
+                while let Some((at_ms, front)) = self.ring.front() {
+                    if now_ms.saturating_sub(*at_ms) <= self.pre_trigger_ms && self.ring_bytes <= MAX_PRE_TRIGGER_BYTES {
+                        break;
+                    }
+                    self.ring_bytes -= front.len();
+                    self.ring.pop_front();
+                }
+            }
+        }
+        Ok(closed)
+    }
+
+    /// Opens a recording for an event that just started, beginning with the pre-trigger ring,
+    /// or adds the event to the one already open.
+    pub fn start(&mut self, event: &ShakeEvent) -> std::io::Result<()> {
+        if let Some(recording) = &mut self.active {
+            recording.event_ids.push(event.id);
+            recording.ongoing.push(event.id);
+            recording.closes_at_ms = None;
+            recording.absorb(event);
+            return Ok(());
+        }
+        let part_path = self.dir.join(format!("event{}.ndjson.part", event.id));
+        let mut file = BufWriter::new(File::create(&part_path)?);
+        let start_ms = self.ring.front().map_or(event.start_ms, |(at_ms, _)| *at_ms);
+        for (_, line) in self.ring.drain(..) {
+            writeln!(file, "{}", line)?;
+        }
+        self.ring_bytes = 0;
+        self.active = Some(Recording {
+            file,
+            part_path,
+            start_ms,
+            event_ids: vec![event.id],
+            ongoing: vec![event.id],
+            peak_acceleration: event.peak_acceleration,
+            peak_intensity: event.peak_intensity,
+            closes_at_ms: None,
+        });
+        Ok(())
+    }
+
+    /// Notes that an event ended at `now_ms`, starting the post-roll once no overlapping event
+    /// is left.
+    pub fn end(&mut self, event: &ShakeEvent, now_ms: u64) {
+        let Some(recording) = &mut self.active else {
+            return;
+        };
+        let Some(pos) = recording.ongoing.iter().position(|id| *id == event.id) else {
+            return;
+        };
+        recording.ongoing.remove(pos);
+        recording.absorb(event);
+        if recording.ongoing.is_empty() {
+            recording.closes_at_ms = Some(now_ms + self.post_roll_ms);
+        }
+    }
+
+    /// Closes the recording whose post-roll is over by `now_ms`.
+    pub fn expire(&mut self, now_ms: u64) -> std::io::Result<Option<PathBuf>> {
+        match self.active.as_ref().and_then(|recording| recording.closes_at_ms) {
+            Some(closes_at_ms) if closes_at_ms <= now_ms => self.close(),
+            _ => Ok(None),
+        }
+    }
+
+    /// Closes the open recording, if any, named by its start time and peak; at shutdown this
+    /// cuts the post-roll short.
+    pub fn close(&mut self) -> std::io::Result<Option<PathBuf>> {
+        let Some(mut recording) = self.active.take() else {
+            return Ok(None);
+        };
+        recording.file.flush()?;
+        let path = self.dir.join(recording.file_name());
+        std::fs::rename(&recording.part_path, &path)?;
+        for id in recording.event_ids {
+            self.finished.insert(id, path.clone());
+        }
+        while self.finished.len() > MAX_RECORDINGS {
+            self.finished.pop_first();
+        }
+        Ok(Some(path))
+    }
+
+    pub fn lookup(&self, event_id: u64) -> Lookup {
+        if let Some(path) = self.finished.get(&event_id) {
+            return Lookup::Finished(path.clone());
+        }
+        match &self.active {
+            Some(recording) if recording.event_ids.contains(&event_id) => Lookup::Recording,
+            _ => Lookup::Unknown,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Time constant of the exponentially-weighted rate estimate.
+const EWMA_TAU_MS: f64 = 10_000.0;
+const MINUTE_MS: u64 = 60_000;
+/// Completed one-minute averages kept per device.
+const HISTORY_MINUTES: usize = 10;
+
+/// Estimated delivery rate of one device.
+#[derive(Serialize, ToSchema)]
+pub struct SampleRate {
+    /// Exponentially-weighted samples per second (10 s time constant); decays toward zero while
+    /// the device is silent
+    pub samples_per_second: f64,
+    /// Average samples per second of each of the last 10 completed minutes, oldest first
+    pub history: Vec<f64>,
+}
+
+struct DeviceRate {
+    ewma: f64,
+    updated_ms: u64,
+    // Minute index (`ms / MINUTE_MS`) that `minute_count` belongs to
+    minute: u64,
+    minute_count: u64,
+    history: VecDeque<f64>,
+}
+
+impl DeviceRate {
+    fn new(now_ms: u64) -> Self {
+        Self {
+            ewma: 0.0,
+            updated_ms: now_ms,
+            minute: now_ms / MINUTE_MS,
+            minute_count: 0,
+            history: VecDeque::with_capacity(HISTORY_MINUTES),
+        }
+    }
+
+    /// Decays the estimate to `now_ms`.
+    fn decayed(&self, now_ms: u64) -> f64 {
+        let elapsed = now_ms.saturating_sub(self.updated_ms) as f64;
+        self.ewma * (-elapsed / EWMA_TAU_MS).exp()
+    }
+
+    /// Closes finished minutes, recording silent ones as zero.
+    fn roll_to(&mut self, now_ms: u64) {
+        let minute = now_ms / MINUTE_MS;
+        if minute <= self.minute {
+            return;
+        }
+        let skipped = (minute - self.minute - 1).min(HISTORY_MINUTES as u64);
+        self.push_history(self.minute_count as f64 / 60.0);
+        for _ in 0..skipped {
+            self.push_history(0.0);
+        }
+        self.minute = minute;
+        self.minute_count = 0;
+    }
+
+    fn push_history(&mut self, rate: f64) {
+        if self.history.len() == HISTORY_MINUTES {
+            self.history.pop_front();
+        }
+        self.history.push_back(rate);
+    }
+
+    fn add(&mut self, now_ms: u64, samples: u64) {
+        self.roll_to(now_ms);
+        self.ewma = self.decayed(now_ms) + samples as f64 * 1000.0 / EWMA_TAU_MS;
+        self.updated_ms = now_ms;
+        self.minute_count += samples;
+    }
+}
+
+/// Per-userAgent sample rate estimates, updated at ingest.
+#[derive(Default)]
+pub struct SampleRates {
+    devices: HashMap<String, DeviceRate>,
+}
+
+impl SampleRates {
+    /// Counts `samples` parsed samples from `ua` received at `now_ms`.
+    pub fn record(&mut self, ua: &str, samples: u64, now_ms: u64) {
+        let device = match self.devices.get_mut(ua) {
+            Some(device) => device,
+            None => self
+                .devices
+                .entry(ua.to_string())
+                .or_insert_with(|| DeviceRate::new(now_ms)),
+        };
+        device.add(now_ms, samples);
+    }
+
+    /// Current estimate for one device, decayed to `now_ms`.
+    pub fn samples_per_second(&self, ua: &str, now_ms: u64) -> Option<f64> {
+        self.devices.get(ua).map(|device| device.decayed(now_ms))
+    }
+
+    /// Snapshot of every device as of `now_ms`.
+    pub fn snapshot(&mut self, now_ms: u64) -> BTreeMap<String, SampleRate> {
+        self.devices
+            .iter_mut()
+            .map(|(ua, device)| {
+                device.roll_to(now_ms);
+                let rate = SampleRate {
+                    samples_per_second: device.decayed(now_ms),
+                    history: device.history.iter().copied().collect(),
+                };
+                (ua.clone(), rate)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Summary of the top-level keys observed across a set of JSON messages.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct InferredSchema {
+    /// Number of messages inspected
+    pub sampled: usize,
+    /// Number of JSON objects found (array messages contribute one per element)
+    pub records: usize,
+    /// Messages that were not valid JSON
+    pub invalid: usize,
+    /// Messages that were valid JSON but not an object or array of objects
+    pub non_object: usize,
+    /// Per key, how often each JSON type (`string`, `number`, `boolean`, `null`, `array`,
+    /// `object`) was seen
+    pub fields: BTreeMap<String, BTreeMap<&'static str, usize>>,
+}
+
+/// Accumulates an `InferredSchema` one message at a time.
+#[derive(Default)]
+pub struct SchemaInferrer {
+    schema: InferredSchema,
+}
+
+impl SchemaInferrer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, text: &str) {
+        self.schema.sampled += 1;
+        let Ok(value) = serde_json::from_str::<Value>(text) else {
+            self.schema.invalid += 1;
+            return;
+        };
+        match value {
+            Value::Object(obj) => self.observe_record(&obj),
+            Value::Array(items) if items.iter().all(Value::is_object) => {
+                for item in &items {
+                    if let Value::Object(obj) = item {
+                        self.observe_record(obj);
+                    }
+                }
+            }
+            _ => self.schema.non_object += 1,
+        }
+    }
+
+    fn observe_record(&mut self, obj: &serde_json::Map<String, Value>) {
+        self.schema.records += 1;
+        for (key, value) in obj {
+            *self
+                .schema
+                .fields
+                .entry(key.clone())
+                .or_default()
+                .entry(type_name(value))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn finish(self) -> InferredSchema {
+        self.schema
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
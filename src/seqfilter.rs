@@ -0,0 +1,150 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde_json::Value;
+
+/// How many of the latest sequence numbers of a source are remembered, to tell history replayed
+/// on reconnect from the numbering of an upstream that restarted. Numbers further than this
+/// below the highest seen also count as a restart.
+pub const REPLAY_WINDOW: usize = 10_000;
+
+/// Drops messages an upstream sends again, such as the recent history some upstreams replay
+/// on reconnect, by a sequence number field in them (--upstream-seq-field).
+///
+/// Messages without the field, or that are not JSON objects, always pass.
+pub struct SeqFilter {
+    field: String,
+    sources: HashMap<String, Source>,
+}
+
+#[derive(Default)]
+struct Source {
+    /// The latest admitted sequence numbers, ascending; the last is the highest seen
+    recent: VecDeque<u64>,
+    /// Set by `connected` until the first numbered message of the connection
+    fresh: bool,
+}
+
+/// What `SeqFilter::admit` made of a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    /// No sequence number, or one above the highest seen
+    New,
+    /// Numbered at or below the highest seen
+    Duplicate,
+    /// The upstream started numbering again: the first number of a connection was below the
+    /// highest seen, or a number dropped further back than `REPLAY_WINDOW`, without being one
+    /// seen recently. The message is admitted and counting starts over from it.
+    Reset { max_seen: u64 },
+}
+
+impl SeqFilter {
+    pub fn new(field: String) -> Self {
+        Self {
+            field,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Marks a new connection to `source`: its first numbered message decides whether the
+    /// upstream is replaying history or numbering from scratch.
+    pub fn connected(&mut self, source: &str) {
+        if let Some(source) = self.sources.get_mut(source) {
+            source.fresh = true;
+        }
+    }
+
+    /// Whether `text` from `source` is new, i.e. has no sequence number or one above any seen
+    /// from `source` before, which then becomes the highest seen; or starts a new numbering.
+    pub fn admit(&mut self, source: &str, text: &str) -> Admission {
+        let Some(seq) = self.seq(text) else {
+            return Admission::New;
+        };
+        let source = self.sources.entry(source.to_string()).or_default();
+        let fresh = std::mem::take(&mut source.fresh);
+        let admission = match source.recent.back().copied() {
+            None => Admission::New,
+            Some(max_seen) if seq > max_seen => Admission::New,
+            Some(_) if source.recent.binary_search(&seq).is_ok() => return Admission::Duplicate,
+            Some(max_seen) if fresh || max_seen - seq > REPLAY_WINDOW as u64 => {
+                source.recent.clear();
+                Admission::Reset { max_seen }
+            }
+            Some(_) => return Admission::Duplicate,
+        };
+        if source.recent.len() == REPLAY_WINDOW {
+            source.recent.pop_front();
+        }
+        source.recent.push_back(seq);
+        admission
+    }
+
+    pub fn max_seen(&self, source: &str) -> Option<u64> {
+        self.sources.get(source)?.recent.back().copied()
+    }
+
+    /// The message's sequence number: a non-negative integer, or a string of one.
+    fn seq(&self, text: &str) -> Option<u64> {
+        let value = serde_json::from_str::<Value>(text).ok()?;
+        match value.get(&self.field)? {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(seq: u64) -> String {
+        format!(r#"{{"seq":{},"x":0.01}}"#, seq)
+    }
+
+    fn admit_all(filter: &mut SeqFilter, seqs: impl IntoIterator<Item = u64>) -> Vec<Admission> {
+        seqs.into_iter().map(|seq| filter.admit("up", &numbered(seq))).collect()
+    }
+
+    #[test]
+    fn drops_history_replayed_on_reconnect() {
+        let mut filter = SeqFilter::new("seq".to_string());
+        assert!(admit_all(&mut filter, 1..=100).iter().all(|a| *a == Admission::New));
+        filter.connected("up");
+        assert!(admit_all(&mut filter, 91..=100).iter().all(|a| *a == Admission::Duplicate));
+        assert_eq!(filter.admit("up", &numbered(101)), Admission::New);
+        assert_eq!(filter.max_seen("up"), Some(101));
+    }
+
+    #[test]
+    fn rebases_when_a_new_connection_starts_numbering_again() {
+        let mut filter = SeqFilter::new("seq".to_string());
+        admit_all(&mut filter, 1..=100);
+        filter.connected("up");
+        assert_eq!(filter.admit("up", &numbered(0)), Admission::Reset { max_seen: 100 });
+        assert!(admit_all(&mut filter, 1..=150).iter().all(|a| *a == Admission::New));
+        assert_eq!(filter.max_seen("up"), Some(150));
+    }
+
+    #[test]
+    fn rebases_on_a_large_jump_back_within_a_connection() {
+        let mut filter = SeqFilter::new("seq".to_string());
+        let last = REPLAY_WINDOW as u64 + 500;
+        admit_all(&mut filter, 1..=last);
+        // A small step back is a resend
+        assert_eq!(filter.admit("up", &numbered(last - 10)), Admission::Duplicate);
+        assert_eq!(filter.admit("up", &numbered(3)), Admission::Reset { max_seen: last });
+        assert_eq!(filter.admit("up", &numbered(4)), Admission::New);
+        assert_eq!(filter.admit("up", &numbered(4)), Admission::Duplicate);
+    }
+
+    #[test]
+    fn sources_and_unnumbered_messages_are_independent() {
+        let mut filter = SeqFilter::new("seq".to_string());
+        assert_eq!(filter.admit("a", &numbered(5)), Admission::New);
+        assert_eq!(filter.admit("b", &numbered(5)), Admission::New);
+        assert_eq!(filter.admit("a", r#"{"x":1}"#), Admission::New);
+        assert_eq!(filter.admit("a", "not json"), Admission::New);
+        assert_eq!(filter.admit("a", r#"{"seq":"5"}"#), Admission::Duplicate);
+        assert_eq!(filter.max_seen("c"), None);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Events kept for `/api/shake-events`, oldest dropped first.
+const MAX_EVENTS: usize = 1000;
+/// Silence after which a device's averages start over (and an ongoing event ends).
+const MAX_GAP_MS: f64 = 5_000.0;
+
+/// Settings of the --sta-lta detector.
+pub struct StaLtaConfig {
+    pub sta_window: Duration,
+    pub lta_window: Duration,
+    pub trigger_ratio: f64,
+    pub detrigger_ratio: f64,
+    pub min_event_duration: Duration,
+}
+
+/// Shaking detected on one device, from the trigger to the detrigger.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ShakeEvent {
+    pub id: u64,
+    pub user_agent: String,
+    /// When the STA/LTA ratio reached --trigger-ratio (UNIX ms, collector clock)
+    pub start_ms: u64,
+    /// When it fell below --detrigger-ratio; null while the event is ongoing
+    pub end_ms: Option<u64>,
+    /// Largest vector acceleration with the device's offset (gravity) removed, in the unit of
+    /// its `x`/`y`/`z`
+    pub peak_acceleration: f64,
+    /// Highest intensity computed for the device during the event; null without --intensity
+    pub peak_intensity: Option<f64>,
+    /// The --correlate-devices event confirming this one; null while it is unconfirmed
+    pub confirmed_id: Option<u64>,
+}
+
+/// A change to be announced on /ws.
+#[derive(Clone)]
+pub enum Transition {
+    Start(ShakeEvent),
+    End(ShakeEvent),
+}
+
+impl Transition {
+    pub fn event(&self) -> &ShakeEvent {
+        match self {
+            Transition::Start(event) | Transition::End(event) => event,
+        }
+    }
+
+    /// The `{"type":"event_start"|"event_end",...event}` message.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Notice<'a> {
+            #[serde(rename = "type")]
+            kind: &'static str,
+            #[serde(flatten)]
+            event: &'a ShakeEvent,
+        }
+        let kind = match self {
+            Transition::Start(_) => "event_start",
+            Transition::End(_) => "event_end",
+        };
+        serde_json::to_string(&Notice {
+            kind,
+            event: self.event(),
+        }).expect("notice serializes")
+    }
+}
+
+enum Phase {
+    Quiet,
+    // Triggered, but not yet for --min-event-duration
+    Pending {
+        start_ms: u64,
+        peak_acceleration: f64,
+        peak_intensity: Option<f64>,
+    },
+    // Reported with `event_start`; the event is at `id` in the list
+    Active { id: u64 },
+}
+
+struct DeviceTrigger {
+    last_t_ms: f64,
+    // Since the averages (re)started, to hold off triggering until the LTA has settled
+    warm_ms: f64,
+    // Slowly following offset of each axis (gravity, sensor bias)
+    baseline: [f64; 3],
+    sta: f64,
+    lta: f64,
+    phase: Phase,
+}
+
+impl DeviceTrigger {
+    fn new(t_ms: f64, accel: [f64; 3]) -> Self {
+        Self {
+            last_t_ms: t_ms,
+            warm_ms: 0.0,
+            baseline: accel,
+            sta: 0.0,
+            lta: 0.0,
+            phase: Phase::Quiet,
+        }
+    }
+}
+
+/// Per-userAgent STA/LTA triggers over the vector acceleration, and the events they produced.
+///
+/// Samples arrive unevenly, so the short- and long-term averages are exponential with the
+/// windows as time constants, weighted by the time since the previous sample.
+pub struct ShakeDetector {
+    config: StaLtaConfig,
+    devices: HashMap<String, DeviceTrigger>,
+    events: VecDeque<ShakeEvent>,
+    next_id: u64,
+}
+
+impl ShakeDetector {
+    pub fn new(config: StaLtaConfig) -> Self {
+        Self {
+            config,
+            devices: HashMap::new(),
+            events: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Feeds a sample of `ua` taken at `t_ms` (device clock) and received at `now_ms`, along
+    /// with the device's current intensity, if computed.
+    pub fn record(
+        &mut self,
+        ua: &str,
+        t_ms: f64,
+        accel: [f64; 3],
+        intensity: Option<f64>,
+        now_ms: u64,
+    ) -> Option<Transition> {
+        let Some(device) = self.devices.get_mut(ua) else {
+            self.devices.insert(ua.to_string(), DeviceTrigger::new(t_ms, accel));
+            return None;
+        };
+        let dt = t_ms - device.last_t_ms;
+        // Repeated or out-of-order `t`
+        if dt <= 0.0 {
+            return None;
+        }
+        if dt > MAX_GAP_MS {
+            let ended = match device.phase {
+                Phase::Active { id } => Some(id),
+                _ => None,
+            };
+            *device = DeviceTrigger::new(t_ms, accel);
+            return ended.and_then(|id| self.end(id, now_ms));
+        }
+        device.last_t_ms = t_ms;
+        device.warm_ms += dt;
+
+        let sta_secs = self.config.sta_window.as_secs_f64();
+        let lta_secs = self.config.lta_window.as_secs_f64();
+        let lta_weight = 1.0 - (-dt / 1000.0 / lta_secs).exp();
+        let sta_weight = 1.0 - (-dt / 1000.0 / sta_secs).exp();
+        let mut magnitude: f64 = 0.0;
+        for (axis, base) in device.baseline.iter_mut().enumerate() {
+            *base += lta_weight * (accel[axis] - *base);
+            magnitude += (accel[axis] - *base).powi(2);
+        }
+        let magnitude = magnitude.sqrt();
+        device.sta += sta_weight * (magnitude - device.sta);
+        // Held during an event, or the shaking itself would raise the LTA and end it early
+        if matches!(device.phase, Phase::Quiet) {
+            device.lta += lta_weight * (magnitude - device.lta);
+        }
+        let ratio = match device.lta {
+            lta if lta > 0.0 => device.sta / lta,
+            _ if device.sta > 0.0 => f64::INFINITY,
+            _ => 0.0,
+        };
+        let settled = device.warm_ms >= lta_secs * 1000.0;
+
+        match &mut device.phase {
+            Phase::Quiet => {
+                if settled && ratio >= self.config.trigger_ratio {
+                    device.phase = Phase::Pending {
+                        start_ms: now_ms,
+                        peak_acceleration: magnitude,
+                        peak_intensity: intensity,
+                    };
+                }
+                None
+            }
+            Phase::Pending {
+                start_ms,
+                peak_acceleration,
+                peak_intensity,
+            } => {
+                if ratio < self.config.detrigger_ratio {
+                    // Too short to be more than a knock or a noise burst
+                    device.phase = Phase::Quiet;
+                    return None;
+                }
+                *peak_acceleration = peak_acceleration.max(magnitude);
+                *peak_intensity = max_intensity(*peak_intensity, intensity);
+                if now_ms.saturating_sub(*start_ms) < self.config.min_event_duration.as_millis() as u64 {
+                    return None;
+                }
+                let event = ShakeEvent {
+                    id: self.next_id,
+                    user_agent: ua.to_string(),
+                    start_ms: *start_ms,
+                    end_ms: None,
+                    peak_acceleration: *peak_acceleration,
+                    peak_intensity: *peak_intensity,
+                    confirmed_id: None,
+                };
+                self.next_id += 1;
+                device.phase = Phase::Active { id: event.id };
+                if self.events.len() == MAX_EVENTS {
+                    self.events.pop_front();
+                }
+                self.events.push_back(event.clone());
+                Some(Transition::Start(event))
+            }
+            Phase::Active { id } => {
+                let id = *id;
+                if ratio < self.config.detrigger_ratio {
+                    device.phase = Phase::Quiet;
+                    return self.end(id, now_ms);
+                }
+                if let Some(event) = self.event_mut(id) {
+                    event.peak_acceleration = event.peak_acceleration.max(magnitude);
+                    event.peak_intensity = max_intensity(event.peak_intensity, intensity);
+                }
+                None
+            }
+        }
+    }
+
+    /// Events oldest first, ongoing ones included; only those of `ua` when given.
+    pub fn events(&self, ua: Option<&str>) -> Vec<ShakeEvent> {
+        self.events
+            .iter()
+            .filter(|event| ua.is_none_or(|ua| event.user_agent == ua))
+            .cloned()
+            .collect()
+    }
+
+    fn end(&mut self, id: u64, now_ms: u64) -> Option<Transition> {
+        let event = self.event_mut(id)?;
+        event.end_ms = Some(now_ms);
+        Some(Transition::End(event.clone()))
+    }
+
+    fn event_mut(&mut self, id: u64) -> Option<&mut ShakeEvent> {
+        // Ongoing events are among the newest
+        self.events.iter_mut().rev().find(|event| event.id == id)
+    }
+}
+
+fn max_intensity(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
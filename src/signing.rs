@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Signs stored messages under --sign-key with HMAC-SHA256.
+///
+/// A JSON object gets the hex signature of its serialization as a last `"_sig"` member, so the
+/// signed bytes are the message with `,"_sig":"<hex>"` (or `"_sig":"<hex>"` for an empty
+/// object) removed. Any other text gets ` _sig=<hex>` appended to it. JSON arrays and scalars
+/// are left unsigned, since neither form would keep them valid JSON.
+pub struct MessageSigner {
+    mac: Hmac<Sha256>,
+    key_id: String,
+    warned_unsigned: AtomicBool,
+}
+
+impl MessageSigner {
+    /// A signer for the hex-encoded `key`.
+    pub fn new(key: &str) -> Result<Self, String> {
+        let key = hex::decode(key.trim()).map_err(|err| format!("not hex: {}", err))?;
+        if key.is_empty() {
+            return Err("empty key".to_string());
+        }
+        Ok(Self {
+            mac: Hmac::new_from_slice(&key).expect("HMAC takes keys of any length"),
+            key_id: hex::encode(Sha256::digest(&key)),
+            warned_unsigned: AtomicBool::new(false),
+        })
+    }
+
+    /// SHA-256 of the key, which tells clients which key the messages are signed with without
+    /// revealing it.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The hex HMAC of `bytes`.
+    pub fn sign(&self, bytes: &[u8]) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(bytes);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// `obj` serialized with its signature added.
+    pub fn sign_object(&self, mut obj: Map<String, Value>) -> String {
+        let sig = self.sign(Value::Object(obj.clone()).to_string().as_bytes());
+        obj.insert("_sig".to_string(), Value::from(sig));
+        Value::Object(obj).to_string()
+    }
+
+    /// `text` with ` _sig=<hex>` appended.
+    pub fn sign_text(&self, text: &str) -> String {
+        format!("{} _sig={}", text, self.sign(text.as_bytes()))
+    }
+
+    /// Warns, once, that a JSON array or scalar went out unsigned.
+    pub fn warn_unsigned(&self) {
+        if !self.warned_unsigned.swap(true, Ordering::Relaxed) {
+            tracing::warn!("--sign-key only signs JSON objects and non-JSON text; JSON arrays and scalars are stored unsigned");
+        }
+    }
+}
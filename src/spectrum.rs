@@ -0,0 +1,262 @@
+use realfft::RealFftPlanner;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Fewer samples than this give no meaningful spectrum.
+pub const MIN_SAMPLES: usize = 16;
+
+/// Highest `/api/spectrum?rate_hz=` accepted, well above what phone accelerometers sample at.
+pub const MAX_RATE_HZ: f64 = 10_000.0;
+
+/// Which acceleration `/api/spectrum?axis=` analyses.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SpectrumAxis {
+    X,
+    Y,
+    Z,
+    /// The vector's length
+    #[default]
+    Mag,
+}
+
+impl SpectrumAxis {
+    pub fn value(self, [x, y, z]: [f64; 3]) -> f64 {
+        match self {
+            SpectrumAxis::X => x,
+            SpectrumAxis::Y => y,
+            SpectrumAxis::Z => z,
+            SpectrumAxis::Mag => (x * x + y * y + z * z).sqrt(),
+        }
+    }
+}
+
+/// Magnitude spectrum of one axis, as returned by `/api/spectrum`.
+#[derive(Serialize, ToSchema)]
+pub struct Spectrum {
+    /// Rate the samples were resampled to
+    pub sample_rate_hz: f64,
+    /// Samples in the range, and the points they were resampled to
+    pub samples: usize,
+    pub points: usize,
+    /// Frequency of each bin, from 0 up to half `sample_rate_hz`
+    pub freq_hz: Vec<f64>,
+    /// Amplitude of each bin, in the unit of the axis: a sine of amplitude `A` on a bin reads
+    /// `A` there
+    pub magnitude: Vec<f64>,
+}
+
+/// The device's rate from the median spacing of `times` (sorted, ms); `None` when every sample
+/// shares one time.
+pub fn median_rate_hz(times: &[f64]) -> Option<f64> {
+    let mut spacings: Vec<f64> = times.windows(2).map(|pair| pair[1] - pair[0]).filter(|dt| *dt > 0.0).collect();
+    if spacings.is_empty() {
+        return None;
+    }
+    let middle = spacings.len() / 2;
+    let (_, median, _) = spacings.select_nth_unstable_by(middle, f64::total_cmp);
+    Some(1000.0 / *median)
+}
+
+/// Points `resample` makes of samples spanning `first_ms..=last_ms` at `rate_hz`, saturating
+/// at `usize::MAX` so an absurd rate is caught by a size limit instead of overflowing.
+pub fn resampled_len(first_ms: f64, last_ms: f64, rate_hz: f64) -> usize {
+    (((last_ms - first_ms) * rate_hz / 1000.0).floor().max(0.0) as usize).saturating_add(1)
+}
+
+/// Linearly interpolates `samples` (`(t_ms, value)`, sorted by `t`, without repeats) onto a
+/// grid of `rate_hz` from the first sample's time, so irregular spacing (jitter, batching,
+/// dropped samples) does not smear the spectrum. Interpolating attenuates a sine at `f` by
+/// `sinc²(f / rate_hz)`: 5% at a tenth of the rate, 19% at a quarter.
+pub fn resample(samples: &[(f64, f64)], rate_hz: f64) -> Vec<f64> {
+    let (Some(&(first, first_value)), Some(&(last, _))) = (samples.first(), samples.last()) else {
+        return Vec::new();
+    };
+    if samples.len() == 1 {
+        return vec![first_value];
+    }
+    let step_ms = 1000.0 / rate_hz;
+    let len = resampled_len(first, last, rate_hz);
+    let mut points = Vec::with_capacity(len);
+    // Index of the sample ending the segment the grid is in
+    let mut next = 1;
+    for i in 0..len {
+        let t = first + i as f64 * step_ms;
+        while next < samples.len() - 1 && samples[next].0 < t {
+            next += 1;
+        }
+        let (t1, v1) = samples[next - 1];
+        let (t2, v2) = samples[next];
+        points.push(v1 + (v2 - v1) * ((t - t1) / (t2 - t1)).clamp(0.0, 1.0));
+    }
+    points
+}
+
+/// Multiplies `values` by a Hann window, returning the window's sum (its coherent gain times
+/// the length), which the magnitudes are scaled back by.
+pub fn hann(values: &mut [f64]) -> f64 {
+    let n = values.len();
+    if n < 2 {
+        return n as f64;
+    }
+    let mut sum = 0.0;
+    for (i, value) in values.iter_mut().enumerate() {
+        let w = 0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / (n - 1) as f64).cos();
+        *value *= w;
+        sum += w;
+    }
+    sum
+}
+
+/// One-sided amplitude spectrum of `points` sampled at `rate_hz`: mean removed, Hann window,
+/// FFT, and each bin scaled so a sine's amplitude reads as itself.
+pub fn magnitude_spectrum(mut points: Vec<f64>, rate_hz: f64, samples: usize) -> Spectrum {
+    let n = points.len();
+    // The offset (gravity, sensor bias) would otherwise bury the low bins in the window's leakage
+    let mean = points.iter().sum::<f64>() / n as f64;
+    for point in &mut points {
+        *point -= mean;
+    }
+    let window_sum = hann(&mut points);
+    let fft = RealFftPlanner::<f64>::new().plan_fft_forward(n);
+    let mut spectrum = fft.make_output_vec();
+    fft.process(&mut points, &mut spectrum).expect("buffers sized by the plan");
+    let magnitude = spectrum
+        .iter()
+        .enumerate()
+        .map(|(bin, c)| {
+            // DC and Nyquist have no mirror image to fold in
+            let scale = if bin == 0 || 2 * bin == n { 1.0 } else { 2.0 };
+            c.norm() * scale / window_sum
+        })
+        .collect();
+    Spectrum {
+        sample_rate_hz: rate_hz,
+        samples,
+        points: n,
+        freq_hz: (0..spectrum.len()).map(|bin| bin as f64 * rate_hz / n as f64).collect(),
+        magnitude,
+    }
+}
+
+/// Checks against synthetic signals with a known answer. Resampling by linear interpolation
+/// passes a sine at `f` with gain `sinc²(f / rate)`, so that is the amplitude expected back.
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+
+    use super::*;
+
+    const T0: f64 = 1_768_000_000_000.0;
+
+    /// Repeatable uniform noise in [-1, 1), from a xorshift.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0
+        }
+    }
+
+    /// `secs` of nominally 100 Hz sample times, each off by up to ±3 ms.
+    fn jittered_times(secs: f64) -> Vec<f64> {
+        let mut noise = Noise(0x2545_f491);
+        (0..(secs * 100.0) as usize).map(|i| T0 + i as f64 * 10.0 + noise.next() * 3.0).collect()
+    }
+
+    fn interpolation_gain(f: f64, rate: f64) -> f64 {
+        let x = PI * f / rate;
+        (x.sin() / x).powi(2)
+    }
+
+    /// The magnitude of the bin nearest `f`.
+    fn at(spectrum: &Spectrum, f: f64) -> f64 {
+        let bin = (f * spectrum.points as f64 / spectrum.sample_rate_hz).round() as usize;
+        spectrum.magnitude[bin]
+    }
+
+    /// A ramp is linear, so interpolating it is exact however irregular the samples.
+    #[test]
+    fn resamples_a_ramp_exactly() {
+        let times = jittered_times(10.0);
+        let ramp: Vec<(f64, f64)> = times.iter().map(|t| (*t, (t - T0) * 0.5)).collect();
+        let points = resample(&ramp, 100.0);
+        let worst = points
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v - (times[0] - T0 + i as f64 * 10.0) * 0.5).abs())
+            .fold(0.0_f64, f64::max);
+        assert!(worst < 1e-9, "off by at most {:e}", worst);
+        let span = times[times.len() - 1] - times[0];
+        assert_eq!(points.len(), (span / 10.0).floor() as usize + 1, "points for {:.1} ms", span);
+    }
+
+    /// A gap is bridged in a straight line.
+    #[test]
+    fn bridges_a_gap() {
+        let points = resample(&[(0.0, 0.0), (10.0, 1.0), (1010.0, 3.0)], 100.0);
+        assert_eq!(points[1], 1.0);
+        assert!((points[51] - 2.0).abs() < 1e-12, "{} at 510 ms", points[51]);
+        assert_eq!(points[101], 3.0);
+    }
+
+    /// A rate no grid could be built at saturates instead of overflowing.
+    #[test]
+    fn resampled_len_saturates_at_a_huge_rate() {
+        assert_eq!(resampled_len(T0, T0 + 1000.0, 100.0), 101);
+        assert_eq!(resampled_len(T0, T0 + 1000.0, 1e300), usize::MAX);
+        assert_eq!(resampled_len(T0, T0 + 1000.0, f64::MAX), usize::MAX);
+    }
+
+    #[test]
+    fn median_rate_of_jittered_samples() {
+        let rate = median_rate_hz(&jittered_times(10.0)).expect("enough samples");
+        assert!((rate - 100.0).abs() < 5.0, "{:.2} Hz", rate);
+    }
+
+    /// The window is 0 at the ends and 1 in the middle, and sums to (n - 1) / 2.
+    #[test]
+    fn hann_window() {
+        let mut ones = vec![1.0; 101];
+        let sum = hann(&mut ones);
+        assert!(ones[0].abs() < 1e-12 && ones[100].abs() < 1e-12, "ends {}, {}", ones[0], ones[100]);
+        assert!((ones[50] - 1.0).abs() < 1e-12, "middle {}", ones[50]);
+        assert!((sum - 50.0).abs() < 1e-9, "sum {}", sum);
+    }
+
+    /// Two tones on top of gravity, irregularly sampled: both read at their amplitude, nothing
+    /// else comes near.
+    #[test]
+    fn finds_two_tones() {
+        let (f1, a1, f2, a2) = (2.0, 0.3, 12.0, 0.05);
+        let times = jittered_times(60.0);
+        let samples: Vec<(f64, f64)> = times
+            .iter()
+            .map(|t| {
+                let s = (t - T0) / 1000.0;
+                (*t, 9.80665 + a1 * (TAU * f1 * s).sin() + a2 * (TAU * f2 * s).sin())
+            })
+            .collect();
+        let rate = median_rate_hz(&times).expect("enough samples");
+        let result = magnitude_spectrum(resample(&samples, rate), rate, samples.len());
+        for (f, amplitude) in [(f1, a1), (f2, a2)] {
+            let expected = amplitude * interpolation_gain(f, rate);
+            let actual = at(&result, f);
+            assert!((actual - expected).abs() < expected * 0.03, "{} Hz: {:.4}, expected {:.4}", f, actual, expected);
+        }
+        assert!(result.magnitude[0] < 1e-3, "gravity left: {:e} at 0 Hz", result.magnitude[0]);
+        let elsewhere = result
+            .freq_hz
+            .iter()
+            .zip(&result.magnitude)
+            .filter(|(f, _)| (*f - f1).abs() > 0.5 && (*f - f2).abs() > 0.5)
+            .map(|(_, m)| *m)
+            .fold(0.0_f64, f64::max);
+        assert!(elsewhere < a2 * 0.1, "away from the tones: {:.5}", elsewhere);
+        let top = result.freq_hz[result.freq_hz.len() - 1];
+        assert!((top - rate / 2.0).abs() < rate / result.points as f64, "bins up to {} Hz", top);
+    }
+}
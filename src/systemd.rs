@@ -0,0 +1,53 @@
+use std::net::TcpListener;
+
+/// First descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Takes the listening sockets passed by systemd socket activation, if any.
+///
+/// Follows `sd_listen_fds(3)`: `LISTEN_FDS` descriptors starting at 3, only when `LISTEN_PID`
+/// names this process (so a forked `--daemonize` child or an unrelated child ignores them).
+/// The variables are removed so they are not inherited further.
+#[cfg(unix)]
+pub fn listeners() -> Result<Vec<TcpListener>, String> {
+    use std::os::fd::FromRawFd;
+
+    let Ok(count) = std::env::var("LISTEN_FDS") else {
+        return Ok(Vec::new());
+    };
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    // SAFETY: nothing else reads or writes the environment concurrently this early in startup
+    unsafe {
+        std::env::remove_var("LISTEN_FDS");
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDNAMES");
+    }
+    if !for_us {
+        return Ok(Vec::new());
+    }
+    let count: i32 = count
+        .parse()
+        .map_err(|_| format!("LISTEN_FDS is not a number: {}", count))?;
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // SAFETY: systemd hands these descriptors to this process alone, and each is taken once
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            let addr = listener
+                .local_addr()
+                .map_err(|e| format!("fd {} from systemd is not a TCP socket: {}", fd, e))?;
+            listener
+                .set_nonblocking(true)
+                .map_err(|e| format!("fd {} ({}): {}", fd, addr, e))?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listeners() -> Result<Vec<TcpListener>, String> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,411 @@
+//! Tests that drive the HTTP routes, with a state built as `run` builds it but without an
+//! upstream connection: messages are ingested by calling the ingest path directly.
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderMap, Request};
+use tower::ServiceExt;
+
+use super::*;
+
+/// A state for the options `args` (after the upstream URL, which is never connected to).
+fn state(args: &[&str]) -> AppState {
+    let config = Args::parse_from(["yurecollect", "ws://127.0.0.1:9/"].into_iter().chain(args.iter().copied()));
+    let shared = SharedState {
+        shutdown: CancellationToken::new(),
+        ws_sessions: TaskTracker::new(),
+        signer: None,
+        webhook: None,
+        alerts: None,
+        rate_limits: Arc::new(RateLimits {
+            cheap: None,
+            expensive: None,
+            ws: None,
+            auth_failures: None,
+        }),
+        request_limits: Arc::new(RequestLimits::new(
+            config.max_concurrent_requests,
+            config.request_timeout_secs,
+            config.max_body_bytes,
+            config.max_ws_clients,
+        )),
+        api_tokens: Arc::new(ApiTokens::default()),
+        ip_filter: Arc::new(IpFilter::load(Vec::new(), Vec::new(), None, None).expect("empty lists load")),
+        basic_auth: None,
+        jwt: None,
+        audit: Arc::new(AuditLog::default()),
+        log_level: Arc::new(LogLevel::detached()),
+        uplot_cdn: true,
+    };
+    AppState::new(config, None, &shared)
+}
+
+/// `n` accelerometer messages of three devices at 100 Hz, shaped like those of yuredroid.
+fn accelerometer_messages(n: usize) -> Vec<String> {
+    // Repeatable sensor noise in [-0.01, 0.01), from a xorshift
+    let mut bits: u32 = 0x2545_f491;
+    let mut noise = move || {
+        bits ^= bits << 13;
+        bits ^= bits >> 17;
+        bits ^= bits << 5;
+        (bits as f64 / u32::MAX as f64 * 2.0 - 1.0) * 0.01
+    };
+    let devices = [
+        ("yuredroid 1.4.2 on Xiaomi 2201117TG", "EReERYeurRE"),
+        ("yuredroid 1.4.2 on Google Pixel 7", "Kq3VbT0xWmA"),
+        ("yureios 2.0.1 on iPhone15,3", "pZ8sLh2NcYe"),
+    ];
+    (0..n)
+        .map(|i| {
+            let (ua, id) = devices[i % devices.len()];
+            json!({
+                "t": 1_768_117_058_365_u64 + (i / devices.len()) as u64 * 10,
+                "userAgent": ua,
+                "x": noise(),
+                "y": noise(),
+                "z": 1.0 + noise(),
+                "yureId": id,
+            })
+            .to_string()
+        })
+        .collect()
+}
+
+async fn ingest(state: &AppState, messages: &[String]) {
+    for text in messages {
+        let value: Value = serde_json::from_str(text).expect("test messages are JSON");
+        ingest_text(state, text.clone(), Some(value), MessageFormat::Json).await;
+    }
+}
+
+/// GETs `uri` from the routes of `state`, asking for `encoding` if given.
+async fn send(state: &AppState, uri: &str, encoding: Option<&str>) -> Response {
+    let mut request = Request::get(uri);
+    if let Some(encoding) = encoding {
+        request = request.header(header::ACCEPT_ENCODING, encoding);
+    }
+    app_router(state.clone())
+        .oneshot(request.body(Body::empty()).expect("request builds"))
+        .await
+        .expect("the router is infallible")
+}
+
+/// Like `send`, for a request that has to succeed.
+async fn get(state: &AppState, uri: &str, encoding: Option<&str>) -> (HeaderMap, Vec<u8>) {
+    let response = send(state, uri, encoding).await;
+    assert!(response.status().is_success(), "GET {}: {}", uri, response.status());
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.expect("body reads");
+    (headers, body.to_vec())
+}
+
+fn gunzip(body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(body), &mut decoded).expect("valid gzip");
+    decoded
+}
+
+fn content_encoding(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok())
+}
+
+#[tokio::test]
+async fn gzip_response_decodes_to_the_plain_one() {
+    let state = state(&[]);
+    ingest(&state, &accelerometer_messages(300)).await;
+    let (plain_headers, plain) = get(&state, "/api/messages", None).await;
+    assert_eq!(content_encoding(&plain_headers), None);
+    let (headers, gzipped) = get(&state, "/api/messages", Some("gzip")).await;
+    assert_eq!(content_encoding(&headers), Some("gzip"));
+    assert_eq!(gunzip(&gzipped), plain);
+}
+
+#[tokio::test]
+async fn zstd_response_decodes_to_the_plain_one() {
+    let state = state(&[]);
+    ingest(&state, &accelerometer_messages(300)).await;
+    let (_, plain) = get(&state, "/api/messages", None).await;
+    let (headers, compressed) = get(&state, "/api/messages", Some("zstd")).await;
+    assert_eq!(content_encoding(&headers), Some("zstd"));
+    assert_eq!(zstd::decode_all(compressed.as_slice()).expect("valid zstd"), plain);
+}
+
+#[tokio::test]
+async fn ndjson_stream_is_not_compressed() {
+    let state = state(&[]);
+    let messages = accelerometer_messages(300);
+    ingest(&state, &messages).await;
+    let (headers, body) = get(&state, "/api/messages/stream", Some("gzip, zstd")).await;
+    assert_eq!(
+        headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+        Some("application/x-ndjson")
+    );
+    assert_eq!(content_encoding(&headers), None);
+    assert_eq!(String::from_utf8(body).expect("UTF-8").lines().count(), messages.len());
+}
+
+/// The size benchmark: a full page of `/api/messages` (500 messages) shrinks by at least 70%.
+#[tokio::test]
+async fn compression_shrinks_accelerometer_json_by_70_percent() {
+    let state = state(&[]);
+    ingest(&state, &accelerometer_messages(500)).await;
+    let (_, plain) = get(&state, "/api/messages?limit=500", None).await;
+    for encoding in ["gzip", "zstd"] {
+        let (_, compressed) = get(&state, "/api/messages?limit=500", Some(encoding)).await;
+        let reduction = 1.0 - compressed.len() as f64 / plain.len() as f64;
+        println!(
+            "{}: {} -> {} bytes, {:.1}% smaller",
+            encoding,
+            plain.len(),
+            compressed.len(),
+            reduction * 100.0
+        );
+        assert!(reduction >= 0.7, "{} only {:.1}% smaller", encoding, reduction * 100.0);
+    }
+}
+
+/// The `_seq` of each line of an NDJSON export.
+async fn exported_seqs(state: &AppState, uri: &str) -> Vec<u64> {
+    let (_, body) = get(state, uri, None).await;
+    String::from_utf8(body)
+        .expect("UTF-8")
+        .lines()
+        .map(|line| {
+            let value: Value = serde_json::from_str(line).expect("exported lines are JSON");
+            value["_seq"].as_u64().expect("--inject-seq numbers every message")
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn stream_range_is_repeatable_and_resumable() {
+    let state = state(&["--inject-seq"]);
+    ingest(&state, &accelerometer_messages(50)).await;
+    let range = "/api/messages/stream?after_seq=10&until_seq=30";
+    let first = get(&state, range, None).await.1;
+    assert_eq!(get(&state, range, None).await.1, first);
+    assert_eq!(exported_seqs(&state, range).await, (11..=30).collect::<Vec<_>>());
+
+    // A download cut off after seq 17 picks up from there
+    let resumed = exported_seqs(&state, "/api/messages/stream?after_seq=17&until_seq=30").await;
+    assert_eq!(resumed, (18..=30).collect::<Vec<_>>());
+    assert_eq!(exported_seqs(&state, "/api/messages/stream?after_seq=45").await, (46..=50).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn stream_range_out_of_bounds_is_clamped() {
+    let state = state(&["--inject-seq"]);
+    ingest(&state, &accelerometer_messages(20)).await;
+    assert!(exported_seqs(&state, "/api/messages/stream?after_seq=20").await.is_empty());
+    assert!(exported_seqs(&state, "/api/messages/stream?after_seq=1000&until_seq=2000").await.is_empty());
+    let clamped = exported_seqs(&state, "/api/messages/stream?after_seq=15&until_seq=1000").await;
+    assert_eq!(clamped, (16..=20).collect::<Vec<_>>());
+    assert_eq!(exported_seqs(&state, "/api/messages/stream?until_seq=3").await, [1, 2, 3]);
+    assert!(exported_seqs(&state, "/api/messages/stream?until_seq=0").await.is_empty());
+}
+
+#[tokio::test]
+async fn malformed_stream_ranges_are_rejected() {
+    let state = state(&["--inject-seq"]);
+    ingest(&state, &accelerometer_messages(5)).await;
+    for uri in [
+        "/api/messages/stream?after_seq=abc",
+        "/api/messages/stream?after_seq=-1",
+        "/api/messages/stream?until_seq=1.5",
+        "/api/messages/stream?after_seq=4&until_seq=2",
+        "/api/messages/stream?follow=1&until_seq=3",
+    ] {
+        assert_eq!(send(&state, uri, None).await.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+    let unnumbered = self::state(&[]);
+    let response = send(&unnumbered, "/api/messages/stream?after_seq=1", None).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Serves the routes of `state` on a local port and opens `/ws?{query}` on it.
+async fn connect_ws(
+    state: &AppState,
+    query: &str,
+) -> tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("binds");
+    let addr = listener.local_addr().expect("bound");
+    tokio::spawn(axum::serve(listener, app_router(state.clone())).into_future());
+    let subscribed = state.tx.receiver_count();
+    let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws?{}", addr, query))
+        .await
+        .expect("/ws upgrades");
+    // The session subscribes once the upgrade is done, a little after the client sees the 101
+    while state.tx.receiver_count() == subscribed {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    ws
+}
+
+/// Broadcasts a live message without yielding, so a /ws session cannot read between them.
+fn broadcast(state: &AppState, id: u64) {
+    let _ = state.tx.send(LiveMessage {
+        id,
+        received_at_ms: now_ms(),
+        seq: None,
+        format: MessageFormat::Json,
+        t_corrected: None,
+        intensity: None,
+        text: json!({ "id": id }).to_string(),
+        user_agents: Arc::default(),
+        encoded: Arc::default(),
+    });
+}
+
+async fn next_text<S>(ws: &mut S) -> Option<String>
+where
+    S: futures_util::Stream<Item = Result<tokio_tungstenite::tungstenite::Message, tokio_tungstenite::tungstenite::Error>>
+        + Unpin,
+{
+    use tokio_tungstenite::tungstenite::Message;
+    loop {
+        let frame = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("a frame within 5s");
+        match frame {
+            Some(Ok(Message::Text(text))) => return Some(text),
+            Some(Ok(Message::Close(frame))) => return frame.map(|frame| format!("close: {}", frame.reason)),
+            Some(Ok(_)) => continue,
+            Some(Err(_)) | None => return None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn lagged_ws_client_is_told_and_resynced() {
+    let mut state = state(&[]);
+    state.tx = broadcast::channel(2).0;
+    let mut ws = connect_ws(&state, "heartbeat=0").await;
+    // Ten messages into a channel of two, while the session is not running
+    (1..=10).for_each(|id| broadcast(&state, id));
+
+    let notice: Value = serde_json::from_str(&next_text(&mut ws).await.expect("a notice")).expect("JSON");
+    assert_eq!(notice, json!({ "type": "lagged", "missed": 8 }));
+    // Carries on with the newest messages, and with the live ones after them
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some(r#"{"id":9}"#));
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some(r#"{"id":10}"#));
+    broadcast(&state, 11);
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some(r#"{"id":11}"#));
+    assert_eq!(state.lag_dropped.load(Ordering::Relaxed), 8);
+}
+
+#[tokio::test]
+async fn lagged_ws_client_is_dropped_with_ws_disconnect_on_lag() {
+    let mut state = state(&["--ws-disconnect-on-lag"]);
+    state.tx = broadcast::channel(2).0;
+    let mut ws = connect_ws(&state, "heartbeat=0").await;
+    (1..=10).for_each(|id| broadcast(&state, id));
+
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some("close: lagged"));
+    assert_eq!(next_text(&mut ws).await, None);
+}
+
+#[tokio::test]
+async fn ws_batch_goes_out_in_order_when_due() {
+    let state = state(&[]);
+    let mut ws = connect_ws(&state, "heartbeat=0&batch_ms=50").await;
+    (1..=5).for_each(|id| broadcast(&state, id));
+    let started = Instant::now();
+    let frame = next_text(&mut ws).await.expect("a batch");
+    assert!(started.elapsed() >= Duration::from_millis(40), "sent before its window");
+    assert_eq!(frame, r#"[{"id":1},{"id":2},{"id":3},{"id":4},{"id":5}]"#);
+
+    broadcast(&state, 6);
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some(r#"[{"id":6}]"#));
+}
+
+#[tokio::test]
+async fn lagged_notice_follows_the_pending_batch() {
+    let mut state = state(&[]);
+    state.tx = broadcast::channel(2).0;
+    let mut ws = connect_ws(&state, "heartbeat=0&batch_ms=1000").await;
+    broadcast(&state, 1);
+    // Let the session put the first message in its batch before the channel overflows
+    while !state.tx.is_empty() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    (2..=11).for_each(|id| broadcast(&state, id));
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some(r#"[{"id":1}]"#));
+    let notice: Value = serde_json::from_str(&next_text(&mut ws).await.expect("a notice")).expect("JSON");
+    assert_eq!(notice, json!({ "type": "lagged", "missed": 8 }));
+}
+
+#[tokio::test]
+async fn throttled_ingest_records_its_wait() {
+    let state = state(&["--upstream-rate-limit-rps", "20"]);
+    // A second's burst goes through at once, the next two wait a token each
+    ingest(&state, &accelerometer_messages(22)).await;
+    assert_eq!(state.ingest_throttled.load(Ordering::Relaxed), 2);
+    let waited = state.ingest_throttled_us.load(Ordering::Relaxed);
+    assert!(waited >= 80_000, "waited {}us for two tokens at 20/s", waited);
+}
+
+#[tokio::test]
+async fn throttled_ingest_gives_way_to_shutdown() {
+    let state = state(&["--upstream-rate-limit-rps", "1"]);
+    ingest(&state, &accelerometer_messages(1)).await;
+    state.shutdown.cancel();
+    let started = Instant::now();
+    ingest(&state, &accelerometer_messages(1)).await;
+    assert!(started.elapsed() < Duration::from_millis(500), "waited {:?}", started.elapsed());
+    assert_eq!(state.buffer.read().await.len(), 2);
+}
+
+/// A backlog held back by --upstream-rate-limit-rps is also a backlog in front of the pongs, so
+/// it must not look like an upstream that stopped answering.
+#[tokio::test]
+async fn throttled_upstream_is_not_taken_for_dead() {
+    use tokio_tungstenite::tungstenite::Message;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("binds");
+    let url = format!("ws://{}/", listener.local_addr().expect("bound"));
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.expect("accepts");
+        let mut ws = tokio_tungstenite::accept_async(socket).await.expect("upgrades");
+        for text in accelerometer_messages(300) {
+            ws.send(Message::Text(text)).await.expect("sends");
+        }
+        // Reading answers the collector's pings
+        while ws.next().await.is_some() {}
+    });
+    let state = state(&[
+        "--upstream-rate-limit-rps",
+        "50",
+        "--upstream-ping-interval-secs",
+        "1",
+        "--upstream-pong-timeout-secs",
+        "1",
+    ]);
+    let upstream = tokio::spawn(run_upstream_ws(url, state.clone(), None));
+    // Six seconds of backlog: pings go out after one and two seconds, behind it
+    tokio::time::sleep(Duration::from_millis(3500)).await;
+    state.shutdown.cancel();
+    upstream.await.expect("the upstream task ends");
+
+    let upstream = state.upstream.read().await;
+    let dropped: Vec<_> = upstream
+        .history
+        .iter()
+        .filter(|event| event.kind != ConnectionEventKind::Connected)
+        .map(|event| event.detail.clone())
+        .collect();
+    assert!(dropped.is_empty(), "disconnected: {:?}", dropped);
+    assert!(state.ingest_throttled.load(Ordering::Relaxed) > 100);
+}
+
+#[tokio::test]
+async fn spectrum_rejects_an_absurd_rate() {
+    let state = state(&[]);
+    ingest(&state, &accelerometer_messages(300)).await;
+    let ua = "yuredroid%201.4.2%20on%20Google%20Pixel%207";
+    for rate in ["1e300", "inf", "NaN", "0", "10001"] {
+        let uri = format!("/api/spectrum?ua={}&rate_hz={}", ua, rate);
+        assert_eq!(send(&state, &uri, None).await.status(), StatusCode::BAD_REQUEST, "{}", rate);
+    }
+    let (_, body) = get(&state, &format!("/api/spectrum?ua={}&rate_hz=100", ua), None).await;
+    let spectrum: Value = serde_json::from_slice(&body).expect("JSON");
+    assert_eq!(spectrum["sample_rate_hz"], json!(100.0));
+}
@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use axum_server::tls_rustls::RustlsConfig;
+
+/// Loads the PEM certificate chain and private key for `--tls-cert`/`--tls-key`.
+///
+/// The resulting config advertises `h2` and `http/1.1` via ALPN.
+pub async fn load(cert: &Path, key: &Path) -> Result<RustlsConfig, String> {
+    // Only the ring provider is compiled in; installing it fails harmlessly if already set
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key)
+        .await
+        .map_err(|e| format!("{} / {}: {}", cert.display(), key.display(), e))
+}
+
+/// Re-reads the certificate and key on every SIGHUP, e.g. after a Let's Encrypt renewal.
+///
+/// New handshakes use the reloaded pair; a failed reload keeps serving the previous one.
+#[cfg(unix)]
+pub fn reload_on_sighup(config: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                tracing::error!("failed to listen for SIGHUP, TLS reload disabled: {}", err);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match config.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => tracing::info!(cert = %cert.display(), "reloaded TLS certificate"),
+                Err(err) => tracing::error!(
+                    "failed to reload TLS certificate, keeping the previous one: {} / {}: {}",
+                    cert.display(),
+                    key.display(),
+                    err
+                ),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_config: RustlsConfig, _cert: PathBuf, _key: PathBuf) {}
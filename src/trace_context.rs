@@ -0,0 +1,63 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::context::FutureExt;
+use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+use opentelemetry::{global, Context};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+
+const TRACER: &str = "yurecollect";
+
+/// Installs the W3C Trace Context propagator and a tracer provider that gives spans their IDs.
+/// Spans are not exported; only their context is passed on, in `traceparent` headers.
+pub fn init() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(SdkTracerProvider::builder().build());
+}
+
+/// Handles the request in a server span continuing the trace of its `traceparent` header, or
+/// in a new root span without one (or with an invalid one).
+///
+/// The span's context is attached (`Context::attach`) whenever the request's future is polled,
+/// so anything run while handling it sees it as `Context::current()`.
+pub async fn propagate(req: Request, next: Next) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+    let route = req.extensions().get::<MatchedPath>().map(|m| m.as_str().to_string());
+    let name = match route {
+        Some(route) => format!("{} {}", req.method(), route),
+        None => req.method().to_string(),
+    };
+    let tracer = global::tracer(TRACER);
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Server)
+        .start_with_context(&tracer, &parent);
+    next.run(req).with_context(parent.with_span(span)).await
+}
+
+/// The trace ID of the current context, as in `traceparent`; `None` outside a trace.
+pub fn current_trace_id() -> Option<String> {
+    let cx = Context::current();
+    let span_context = cx.span().span_context().clone();
+    span_context.is_valid().then(|| span_context.trace_id().to_string())
+}
+
+/// Headers for an outgoing request made on behalf of `parent`: `traceparent` (and
+/// `tracestate`) of a new client span, a child of the trace in `parent` or the root of a new
+/// one.
+pub fn outgoing(parent: &Context, name: &'static str) -> HeaderMap {
+    let tracer = global::tracer(TRACER);
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Client)
+        .start_with_context(&tracer, parent);
+    let cx = parent.with_span(span);
+    let mut headers = HeaderMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut HeaderInjector(&mut headers)));
+    headers
+}
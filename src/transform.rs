@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use mlua::{Function, Lua, Value};
+
+use crate::wasm_plugin::WasmPlugin;
+
+/// What rewrites or drops every upstream text message before it is buffered: a
+/// `--transform-script` or a `--wasm-plugin`. Loaded once and kept by the upstream task.
+pub enum Transformer {
+    Lua(LuaScript),
+    Wasm(Box<WasmPlugin>),
+}
+
+/// What a script or plugin made of a message.
+pub enum Transformed {
+    Message(String),
+    /// Dropped on purpose
+    Dropped,
+    /// The script or plugin failed; the message is passed on unchanged
+    Failed { text: String, error: String },
+}
+
+impl Transformer {
+    pub fn apply(&mut self, text: String) -> Transformed {
+        let result = match self {
+            Transformer::Lua(script) => script.apply(&text),
+            Transformer::Wasm(plugin) => plugin.apply(&text),
+        };
+        match result {
+            Ok(Some(transformed)) => Transformed::Message(transformed),
+            Ok(None) => Transformed::Dropped,
+            Err(error) => Transformed::Failed { text, error },
+        }
+    }
+}
+
+/// The `transform(msg)` function of a `--transform-script`.
+pub struct LuaScript {
+    // Keeps the state `transform` lives in
+    _lua: Lua,
+    transform: Function,
+}
+
+impl LuaScript {
+    /// Runs the script, which must define a global `transform` function.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let lua = Lua::new();
+        lua.load(source)
+            .set_name(format!("@{}", path.display()))
+            .exec()
+            .map_err(|e| e.to_string())?;
+        let transform = lua
+            .globals()
+            .get::<Option<Function>>("transform")
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("{} does not define a `transform` function", path.display()))?;
+        Ok(Self { _lua: lua, transform })
+    }
+
+    /// The message as the script rewrote it, or `None` when it returned `nil` to drop it. A
+    /// script error or a result that is not a UTF-8 string is logged and returned, for the
+    /// message to be passed on.
+    pub fn apply(&self, text: &str) -> Result<Option<String>, String> {
+        let result = self.transform.call::<Value>(text).and_then(|value| match value {
+            Value::Nil => Ok(None),
+            Value::String(s) => Ok(Some(s.to_str()?.to_owned())),
+            other => Err(mlua::Error::runtime(format!(
+                "`transform` must return a string or nil, not {}",
+                other.type_name()
+            ))),
+        });
+        result.map_err(|err| {
+            tracing::warn!("transform script failed, passing the message on unchanged: {}", err);
+            err.to_string()
+        })
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+
+use crate::watch::{self, Update};
+
+/// Messages kept in the log pane.
+const MAX_LOG_LINES: usize = 1000;
+/// One-second rate samples kept for the chart.
+const MAX_RATE_SAMPLES: usize = 300;
+
+/// The subset of `/api/stats` shown in the chart title.
+#[derive(Deserialize)]
+struct Stats {
+    messages: usize,
+    messages_per_second: f64,
+    unique_user_agents: usize,
+}
+
+enum Input {
+    /// Typing a filter regex after `/`
+    Filter(String),
+    Normal,
+}
+
+struct App {
+    log: VecDeque<String>,
+    // Lines scrolled up from the newest; 0 follows new messages
+    scroll: usize,
+    filter: Option<Regex>,
+    input: Input,
+    // Messages received over /api/events in each of the last seconds, oldest first
+    rates: VecDeque<u64>,
+    this_second: u64,
+    stats: Option<Stats>,
+    status: String,
+}
+
+/// Runs the dashboard against the instance at `server` until `q` or Ctrl+C.
+pub async fn run(server: &str, token: Option<&str>) -> Result<(), String> {
+    let mut terminal = ratatui::try_init().map_err(|e| format!("cannot start the terminal UI: {}", e))?;
+    let (updates_tx, mut updates) = mpsc::unbounded_channel();
+    let mut events = {
+        let server = server.to_string();
+        let token = token.map(str::to_string);
+        tokio::spawn(async move {
+            watch::follow(&server, token.as_deref(), |update| {
+                let _ = updates_tx.send(update);
+            })
+            .await
+        })
+    };
+
+    // crossterm's reads block, so keys are forwarded from a thread of their own
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if keys_tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let stats_url = format!("{}/api/stats", server.trim_end_matches('/'));
+    let mut app = App {
+        log: VecDeque::new(),
+        scroll: 0,
+        filter: None,
+        input: Input::Normal,
+        rates: VecDeque::new(),
+        this_second: 0,
+        stats: None,
+        status: format!("connecting to {}", server),
+    };
+    let mut tick = interval(Duration::from_secs(1));
+    let mut redraw = interval(Duration::from_millis(100));
+    let result = loop {
+        tokio::select! {
+            Some(update) = updates.recv() => app.apply(update),
+            Some(event) = keys.recv() => {
+                if !app.handle(event) {
+                    break Ok(());
+                }
+            }
+            _ = tick.tick() => {
+                app.next_second();
+                app.stats = fetch_stats(&client, &stats_url, token).await;
+            }
+            _ = redraw.tick() => draw(&mut terminal, &app),
+            // follow() only returns on an error that retrying cannot fix, e.g. a 401
+            result = &mut events => break result.map_err(|e| e.to_string()).and_then(|r| r),
+        }
+    };
+    ratatui::restore();
+    events.abort();
+    result
+}
+
+async fn fetch_stats(client: &reqwest::Client, url: &str, token: Option<&str>) -> Option<Stats> {
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let body = request.send().await.ok()?.error_for_status().ok()?.bytes().await.ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+impl App {
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Message(text) => {
+                self.this_second += 1;
+                if self.log.len() == MAX_LOG_LINES {
+                    self.log.pop_front();
+                }
+                self.log.push_back(text);
+                // Keep the same lines in view while scrolled back
+                if self.scroll > 0 {
+                    self.scroll += 1;
+                }
+                self.status = "connected".to_string();
+            }
+            Update::Lagged(data) => self.status = format!("fell behind the server: {}", data),
+            Update::Status(status) => self.status = status,
+        }
+    }
+
+    fn next_second(&mut self) {
+        if self.rates.len() == MAX_RATE_SAMPLES {
+            self.rates.pop_front();
+        }
+        self.rates.push_back(self.this_second);
+        self.this_second = 0;
+    }
+
+    /// Handles a terminal event; returns false to quit.
+    fn handle(&mut self, event: Event) -> bool {
+        let Event::Key(key) = event else { return true };
+        if key.kind != KeyEventKind::Press {
+            return true;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        if let Input::Filter(pattern) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let pattern = std::mem::take(pattern);
+                    self.input = Input::Normal;
+                    if pattern.is_empty() {
+                        self.filter = None;
+                        self.status = "filter cleared".to_string();
+                    } else {
+                        match Regex::new(&pattern) {
+                            Ok(re) => {
+                                self.filter = Some(re);
+                                self.scroll = 0;
+                            }
+                            Err(err) => self.status = format!("invalid filter: {}", err),
+                        }
+                    }
+                }
+                KeyCode::Esc => self.input = Input::Normal,
+                KeyCode::Backspace => {
+                    pattern.pop();
+                }
+                KeyCode::Char(c) => pattern.push(c),
+                _ => {}
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') => {
+                self.log.clear();
+                self.scroll = 0;
+            }
+            KeyCode::Char('/') => {
+                let current = self.filter.as_ref().map(|re| re.as_str().to_string());
+                self.input = Input::Filter(current.unwrap_or_default());
+            }
+            KeyCode::Up => self.scroll += 1,
+            KeyCode::Down => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageUp => self.scroll += 10,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::End => self.scroll = 0,
+            _ => {}
+        }
+        true
+    }
+}
+
+fn draw(terminal: &mut DefaultTerminal, app: &App) {
+    let _ = terminal.draw(|frame| render(frame, app));
+}
+
+fn render(frame: &mut Frame, app: &App) {
+    let [log_area, chart_area, status_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    // Newest at the bottom; `scroll` moves the window back in time
+    let lines: Vec<&String> = app
+        .log
+        .iter()
+        .filter(|line| app.filter.as_ref().is_none_or(|re| re.is_match(line)))
+        .collect();
+    let height = log_area.height.saturating_sub(2) as usize;
+    let scroll = app.scroll.min(lines.len().saturating_sub(height));
+    let end = lines.len() - scroll;
+    let start = end.saturating_sub(height);
+    let mut title = format!(" messages ({}) ", lines.len());
+    if let Some(re) = &app.filter {
+        title.push_str(&format!("filter /{}/ ", re.as_str()));
+    }
+    if scroll > 0 {
+        title.push_str(&format!("scrolled back {} ", scroll));
+    }
+    let items: Vec<&str> = lines[start..end].iter().map(|line| line.as_str()).collect();
+    frame.render_widget(List::new(items).block(Block::bordered().title(title)), log_area);
+
+    // The newest samples that fit, right-aligned
+    let width = chart_area.width.saturating_sub(2) as usize;
+    let rates: Vec<u64> = app.rates.iter().skip(app.rates.len().saturating_sub(width)).copied().collect();
+    let mut chart_title = format!(" messages/s: {} ", rates.last().copied().unwrap_or(0));
+    if let Some(stats) = &app.stats {
+        chart_title.push_str(&format!(
+            "| server: {:.1}/s, {} buffered, {} devices ",
+            stats.messages_per_second, stats.messages, stats.unique_user_agents
+        ));
+    }
+    let chart = Sparkline::default()
+        .block(Block::bordered().title(chart_title))
+        .data(&rates);
+    frame.render_widget(chart, chart_area);
+
+    let status = match &app.input {
+        Input::Filter(pattern) => Line::from(format!("/{}", pattern)),
+        Input::Normal => Line::from(format!(
+            "{}  |  q quit  c clear  / filter  ↑↓ PgUp PgDn End scroll",
+            app.status
+        ))
+        .style(Style::new().dim()),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+}
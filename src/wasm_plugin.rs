@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use wasmtime::{Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+/// Most linear memory a plugin may grow to.
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+/// Fuel (roughly, WebAssembly instructions) a plugin may spend on one message, so a plugin
+/// stuck in a loop cannot stall the upstream.
+const FUEL_PER_MESSAGE: u64 = 100_000_000;
+/// `transform`'s result for "drop the message".
+const DROP: i64 = -1;
+
+/// A `--wasm-plugin` module whose `transform` export rewrites every upstream text message,
+/// following the ABI in docs/wasm-plugin.md. It gets no imports, so it can compute but not
+/// reach the file system, network or clock.
+pub struct WasmPlugin {
+    module: Module,
+    instance: PluginInstance,
+}
+
+struct PluginInstance {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+    transform: TypedFunc<(i32, i32), i64>,
+}
+
+impl WasmPlugin {
+    /// Compiles the module (binary `.wasm` or text `.wat`) and instantiates it once.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+        let module = Module::from_file(&engine, path).map_err(|e| format!("{}: {:#}", path.display(), e))?;
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "{} imports `{}::{}`, but plugins get no host functions",
+                path.display(),
+                import.module(),
+                import.name()
+            ));
+        }
+        let instance = PluginInstance::new(&module).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self { module, instance })
+    }
+
+    /// The message as the plugin rewrote it, or `None` when it asked to drop it. A trap, running
+    /// out of fuel or output that is not UTF-8 is logged and returned, for the message to be
+    /// passed on, and the plugin starts over from a fresh instance, since its memory may be
+    /// inconsistent.
+    pub fn apply(&mut self, text: &str) -> Result<Option<String>, String> {
+        self.instance.call(text).inspect_err(|err| {
+            tracing::warn!("wasm plugin failed, passing the message on unchanged: {}", err);
+            match PluginInstance::new(&self.module) {
+                Ok(fresh) => self.instance = fresh,
+                Err(err) => tracing::warn!("failed to restart the wasm plugin: {}", err),
+            }
+        })
+    }
+}
+
+impl PluginInstance {
+    fn new(module: &Module) -> Result<Self, String> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build();
+        let mut store = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_MESSAGE).map_err(|e| e.to_string())?;
+        let instance = Instance::new(&mut store, module, &[]).map_err(|e| format!("{:#}", e))?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("does not export its `memory`")?;
+        let alloc = instance
+            .get_typed_func(&mut store, "alloc")
+            .map_err(|e| format!("`alloc(len: i32) -> i32`: {:#}", e))?;
+        let transform = instance
+            .get_typed_func(&mut store, "transform")
+            .map_err(|e| format!("`transform(ptr: i32, len: i32) -> i64`: {:#}", e))?;
+        // Optional: plugins with a bump or single-buffer allocator have nothing to free
+        let dealloc = match instance.get_export(&mut store, "dealloc") {
+            None => None,
+            Some(_) => Some(
+                instance
+                    .get_typed_func(&mut store, "dealloc")
+                    .map_err(|e| format!("`dealloc(ptr: i32, len: i32)`: {:#}", e))?,
+            ),
+        };
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            dealloc,
+            transform,
+        })
+    }
+
+    fn call(&mut self, text: &str) -> Result<Option<String>, String> {
+        self.store.set_fuel(FUEL_PER_MESSAGE).map_err(|e| e.to_string())?;
+        let len = i32::try_from(text.len()).map_err(|_| "message longer than 2 GiB".to_string())?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(|e| format!("alloc: {:#}", e))?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, text.as_bytes())
+            .map_err(|_| format!("alloc returned {} bytes at {}, outside its memory", len, ptr))?;
+        let packed = self
+            .transform
+            .call(&mut self.store, (ptr, len))
+            .map_err(|e| format!("transform: {:#}", e))?;
+        if packed == DROP {
+            self.free(ptr, len)?;
+            return Ok(None);
+        }
+        let (out_ptr, out_len) = ((packed as u64 >> 32) as u32, packed as u32);
+        let mut out = vec![0; out_len as usize];
+        self.memory
+            .read(&self.store, out_ptr as usize, &mut out)
+            .map_err(|_| format!("transform returned {} bytes at {}, outside its memory", out_len, out_ptr))?;
+        // A plugin may answer with its input buffer, rewritten in place, which is then freed once
+        if out_ptr as i32 != ptr {
+            self.free(ptr, len)?;
+        }
+        self.free(out_ptr as i32, out_len as i32)?;
+        String::from_utf8(out)
+            .map(Some)
+            .map_err(|_| "transform returned bytes that are not UTF-8".to_string())
+    }
+
+    fn free(&mut self, ptr: i32, len: i32) -> Result<(), String> {
+        match &self.dealloc {
+            Some(dealloc) => dealloc
+                .call(&mut self.store, (ptr, len))
+                .map_err(|e| format!("dealloc: {:#}", e)),
+            None => Ok(()),
+        }
+    }
+}
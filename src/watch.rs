@@ -0,0 +1,163 @@
+use futures_util::StreamExt;
+use regex::Regex;
+use serde_json::Value;
+use tokio::time::{sleep, Duration};
+
+use crate::cli::WatchFormat;
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// What following `/api/events` produced.
+pub enum Update {
+    /// One received message
+    Message(String),
+    /// The server skipped messages for us; carries the `lagged` event's JSON
+    Lagged(String),
+    /// Connection trouble that is being retried
+    Status(String),
+}
+
+/// Follows a running instance's `/api/events` and prints each message to stdout, reconnecting
+/// after errors until Ctrl+C.
+pub async fn run(
+    server: &str,
+    token: Option<&str>,
+    format: WatchFormat,
+    filter: Option<&str>,
+) -> Result<(), String> {
+    let filter = filter
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| format!("invalid --filter: {}", e))?;
+    let print = |update| match update {
+        Update::Message(data) => {
+            if filter.as_ref().is_none_or(|re| re.is_match(&data)) {
+                println!("{}", render(&data, format));
+            }
+        }
+        Update::Lagged(data) => tracing::warn!("fell behind the server, skipped messages: {}", data),
+        Update::Status(status) => tracing::warn!("{}", status),
+    };
+    tokio::select! {
+        result = follow(server, token, print) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
+/// Streams `/api/events` of the instance at `server` into `on_update`, reconnecting after
+/// connection errors. Only returns on an error that retrying will not fix, such as a 401.
+pub async fn follow(
+    server: &str,
+    token: Option<&str>,
+    mut on_update: impl FnMut(Update),
+) -> Result<(), String> {
+    let url = format!("{}/api/events", server.trim_end_matches('/'));
+    let client = reqwest::Client::new();
+    loop {
+        match follow_once(&client, &url, token, &mut on_update).await {
+            Ok(()) => on_update(Update::Status(format!("{} closed the stream, reconnecting...", url))),
+            // Authentication and routing errors will not fix themselves
+            Err(Failure::Fatal(err)) => return Err(err),
+            Err(Failure::Retry(err)) => on_update(Update::Status(format!(
+                "{}: {}, retrying in {:?}",
+                url, err, RETRY_DELAY
+            ))),
+        }
+        sleep(RETRY_DELAY).await;
+    }
+}
+
+enum Failure {
+    Fatal(String),
+    Retry(String),
+}
+
+async fn follow_once(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+    on_update: &mut impl FnMut(Update),
+) -> Result<(), Failure> {
+    let mut request = client.get(url).header("accept", "text/event-stream");
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.map_err(|e| Failure::Retry(e.to_string()))?;
+    let status = response.status();
+    if status.is_client_error() {
+        return Err(Failure::Fatal(format!("{} answered {}", url, status)));
+    }
+    if !status.is_success() {
+        return Err(Failure::Retry(format!("status {}", status)));
+    }
+
+    let mut body = response.bytes_stream();
+    let mut pending = Vec::new();
+    let mut event = SseEvent::default();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| Failure::Retry(e.to_string()))?;
+        pending.extend_from_slice(&chunk);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if let Some(update) = event.dispatch() {
+                    on_update(update);
+                }
+            } else {
+                event.feed(line);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One Server-Sent Event being assembled line by line.
+#[derive(Default)]
+struct SseEvent {
+    name: Option<String>,
+    data: Option<String>,
+}
+
+impl SseEvent {
+    fn feed(&mut self, line: &str) {
+        // Lines starting with ':' are comments (keep-alives)
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => self.name = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            _ => {}
+        }
+    }
+
+    /// Completes the event at a blank line; events without data or of unknown types are dropped.
+    fn dispatch(&mut self) -> Option<Update> {
+        let event = std::mem::take(self);
+        let data = event.data?;
+        match event.name.as_deref() {
+            None | Some("message") => Some(Update::Message(data)),
+            Some("lagged") => Some(Update::Lagged(data)),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Formats one message; text that is not JSON is printed as-is.
+fn render(data: &str, format: WatchFormat) -> String {
+    let parsed = || serde_json::from_str::<Value>(data).ok();
+    match format {
+        WatchFormat::Raw => data.to_string(),
+        WatchFormat::Json => parsed().map_or_else(|| data.to_string(), |v| v.to_string()),
+        WatchFormat::Pretty => parsed()
+            .and_then(|v| serde_json::to_string_pretty(&v).ok())
+            .unwrap_or_else(|| data.to_string()),
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_graphql::SimpleObject;
+use opentelemetry::Context;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use crate::shake::Transition;
+use crate::signing::MessageSigner;
+use crate::trace_context;
+
+/// Notifications waiting for delivery before further ones are dropped, and counted, so a dead
+/// endpoint never holds up ingest.
+const QUEUE_SIZE: usize = 256;
+/// Attempts per notification, with the wait doubling from `FIRST_RETRY` in between.
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How far below --webhook-threshold the intensity has to fall for an exceedance to wind down.
+const HYSTERESIS: f64 = 0.5;
+
+/// The JSON body POSTed to --webhook-url.
+#[derive(Serialize)]
+pub struct Notification {
+    /// `threshold_exceeded`, `threshold_cleared`, `event_start` or `event_end`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// --instance-name, or null
+    pub instance: Option<String>,
+    /// The --namespace the device streams to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// The device's userAgent
+    pub device: String,
+    /// UNIX ms on the collector's clock
+    pub start_ms: u64,
+    /// Null until the exceedance or event is over
+    pub end_ms: Option<u64>,
+    pub peak_intensity: Option<f64>,
+    /// Events only, in the unit of the device's `x`/`y`/`z`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_acceleration: Option<f64>,
+    /// Threshold notifications only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// Events only, as in `/api/shake-events`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<u64>,
+    /// Events under --correlate-devices only: the confirmed event this one is part of, so
+    /// receivers can act on shaking seen by several devices first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmed_id: Option<u64>,
+}
+
+/// --webhook-url deliveries since startup.
+#[derive(Serialize, ToSchema, SimpleObject)]
+pub struct WebhookStats {
+    /// POSTs answered with a 2xx status
+    pub delivered: u64,
+    /// POSTs made, retries included
+    pub attempts: u64,
+    /// POSTs that failed or were answered with another status
+    pub failed_attempts: u64,
+    /// Notifications given up on after every attempt failed, or dropped because the queue was
+    /// full
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    delivered: AtomicU64,
+    attempts: AtomicU64,
+    failed_attempts: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A device's intensity at or above --webhook-threshold.
+struct Exceedance {
+    start_ms: u64,
+    peak: f64,
+    /// Since when it has been below the threshold by `HYSTERESIS`
+    below_since_ms: Option<u64>,
+}
+
+/// Webhook settings, validated at startup.
+pub struct WebhookConfig {
+    pub url: reqwest::Url,
+    pub signer: Option<MessageSigner>,
+    pub instance: Option<String>,
+    pub threshold: Option<f64>,
+    pub cooldown: Duration,
+}
+
+/// Sends --webhook-url a notification when a --sta-lta event starts and ends, and when a
+/// device's intensity exceeds --webhook-threshold and clears again.
+///
+/// An exceedance only clears once the intensity has stayed `HYSTERESIS` below the threshold
+/// for --webhook-cooldown, so shaking that hovers around the threshold is one start and one end
+/// notification rather than one per crossing.
+pub struct Notifier {
+    /// With the trace context the notification was made in
+    tx: mpsc::Sender<(Notification, Context)>,
+    instance: Option<String>,
+    threshold: Option<f64>,
+    cooldown_ms: u64,
+    /// Keyed by namespace and device
+    exceedances: Mutex<HashMap<(Option<String>, String), Exceedance>>,
+    counters: Arc<Counters>,
+}
+
+impl Notifier {
+    /// Starts the delivery task.
+    pub fn spawn(config: WebhookConfig) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let counters = Arc::new(Counters::default());
+        tokio::spawn(deliver(config.url, config.signer, rx, counters.clone()));
+        Self {
+            tx,
+            instance: config.instance,
+            threshold: config.threshold,
+            cooldown_ms: config.cooldown.as_millis() as u64,
+            exceedances: Mutex::new(HashMap::new()),
+            counters,
+        }
+    }
+
+    /// Notifies of a --sta-lta event starting or ending.
+    pub fn event(&self, namespace: Option<&str>, transition: &Transition) {
+        let kind = match transition {
+            Transition::Start(_) => "event_start",
+            Transition::End(_) => "event_end",
+        };
+        let event = transition.event();
+        self.enqueue(Notification {
+            kind,
+            instance: self.instance.clone(),
+            namespace: namespace.map(str::to_string),
+            device: event.user_agent.clone(),
+            start_ms: event.start_ms,
+            end_ms: event.end_ms,
+            peak_intensity: event.peak_intensity,
+            peak_acceleration: Some(event.peak_acceleration),
+            threshold: None,
+            event_id: Some(event.id),
+            confirmed_id: event.confirmed_id,
+        });
+    }
+
+    /// Follows a device's intensity against --webhook-threshold, if set.
+    pub fn intensity(&self, namespace: Option<&str>, ua: &str, intensity: f64, now_ms: u64) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        let notification = |kind, exceedance: &Exceedance, end_ms| Notification {
+            kind,
+            instance: self.instance.clone(),
+            namespace: namespace.map(str::to_string),
+            device: ua.to_string(),
+            start_ms: exceedance.start_ms,
+            end_ms,
+            peak_intensity: Some(exceedance.peak),
+            peak_acceleration: None,
+            threshold: Some(threshold),
+            event_id: None,
+            confirmed_id: None,
+        };
+        let key = (namespace.map(str::to_string), ua.to_string());
+        let mut exceedances = self.exceedances.lock().unwrap();
+        let Some(exceedance) = exceedances.get_mut(&key) else {
+            if intensity >= threshold {
+                let exceedance = Exceedance {
+                    start_ms: now_ms,
+                    peak: intensity,
+                    below_since_ms: None,
+                };
+                self.enqueue(notification("threshold_exceeded", &exceedance, None));
+                exceedances.insert(key, exceedance);
+            }
+            return;
+        };
+        exceedance.peak = exceedance.peak.max(intensity);
+        if intensity >= threshold - HYSTERESIS {
+            exceedance.below_since_ms = None;
+            return;
+        }
+        let below_since_ms = *exceedance.below_since_ms.get_or_insert(now_ms);
+        if now_ms.saturating_sub(below_since_ms) >= self.cooldown_ms {
+            let exceedance = exceedances.remove(&key).expect("looked up above");
+            self.enqueue(notification("threshold_cleared", &exceedance, Some(below_since_ms)));
+        }
+    }
+
+    pub fn stats(&self) -> WebhookStats {
+        WebhookStats {
+            delivered: self.counters.delivered.load(Ordering::Relaxed),
+            attempts: self.counters.attempts.load(Ordering::Relaxed),
+            failed_attempts: self.counters.failed_attempts.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn enqueue(&self, notification: Notification) {
+        if self.tx.try_send((notification, Context::current())).is_err() {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!("webhook queue full, dropped a notification");
+        }
+    }
+}
+
+/// Checks a --webhook-url.
+pub fn parse_url(raw: &str) -> Result<reqwest::Url, String> {
+    let url = reqwest::Url::parse(raw).map_err(|e| e.to_string())?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        other => Err(format!("unsupported scheme `{}`, expected http or https", other)),
+    }
+}
+
+/// POSTs queued notifications one at a time, in order, retrying each with backoff. Each POST
+/// carries a `traceparent` continuing the trace the notification was made in, if any.
+async fn deliver(
+    url: reqwest::Url,
+    signer: Option<MessageSigner>,
+    mut rx: mpsc::Receiver<(Notification, Context)>,
+    counters: Arc<Counters>,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("webhook client builds");
+    while let Some((notification, cx)) = rx.recv().await {
+        let body = serde_json::to_string(&notification).expect("notification serializes");
+        let trace_headers = trace_context::outgoing(&cx, "POST webhook");
+        let signature = signer.as_ref().map(|signer| format!("sha256={}", signer.sign(body.as_bytes())));
+        let mut retry = FIRST_RETRY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            counters.attempts.fetch_add(1, Ordering::Relaxed);
+            let mut request = client
+                .post(url.clone())
+                .headers(trace_headers.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header("X-Yurecollect-Signature", signature);
+            }
+            let failure = match request.send().await {
+                Ok(response) if response.status().is_success() => None,
+                Ok(response) => Some(format!("answered {}", response.status())),
+                Err(err) => Some(err.to_string()),
+            };
+            let Some(failure) = failure else {
+                counters.delivered.fetch_add(1, Ordering::Relaxed);
+                break;
+            };
+            counters.failed_attempts.fetch_add(1, Ordering::Relaxed);
+            if attempt == MAX_ATTEMPTS {
+                counters.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    kind = notification.kind,
+                    device = %notification.device,
+                    "webhook delivery failed {} times, giving up: {}",
+                    MAX_ATTEMPTS,
+                    failure
+                );
+                break;
+            }
+            tracing::warn!(attempt, "webhook delivery failed, retrying in {:?}: {}", retry, failure);
+            tokio::time::sleep(retry).await;
+            retry *= 2;
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Sent `_seq`s remembered per /ws connection; older ones are only counted.
+const MAX_TRACKED: usize = 10_000;
+
+/// Which of the `_seq`s sent to one /ws connection the client has acknowledged with
+/// `{"ack":<seq>}`. Acks are cumulative: acknowledging a `_seq` covers every earlier one.
+pub struct AckTracker {
+    state: Mutex<AckState>,
+    // Unacknowledged count worth a warning; 0 never warns
+    warn_after: u64,
+}
+
+#[derive(Default)]
+struct AckState {
+    // Sent and not yet acknowledged, oldest first
+    pending: VecDeque<u64>,
+    // Unacknowledged `_seq`s pushed out of `pending`, all older than its front
+    forgotten: u64,
+    last_acked: Option<u64>,
+    // Whether the current run of missing acks has been reported
+    warned: bool,
+}
+
+impl AckTracker {
+    pub fn new(warn_after: u64) -> Self {
+        Self {
+            state: Mutex::default(),
+            warn_after,
+        }
+    }
+
+    /// Records a message handed to the connection. Returns the unacknowledged count when it
+    /// has just passed the warning threshold, once per run of missing acks; clients that never
+    /// ack are not using the protocol and are not reported.
+    pub fn sent(&self, seq: Option<u64>) -> Option<u64> {
+        let seq = seq?;
+        let mut state = self.state.lock().unwrap();
+        // Already covered by a cumulative ack
+        if state.last_acked.is_some_and(|acked| seq <= acked) {
+            return None;
+        }
+        if state.pending.len() == MAX_TRACKED {
+            state.pending.pop_front();
+            state.forgotten += 1;
+        }
+        state.pending.push_back(seq);
+        let unacked = state.forgotten + state.pending.len() as u64;
+        let overdue = self.warn_after > 0 && unacked > self.warn_after && state.last_acked.is_some();
+        if !overdue || state.warned {
+            return None;
+        }
+        state.warned = true;
+        Some(unacked)
+    }
+
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.front().is_none_or(|&oldest| seq >= oldest) {
+            state.forgotten = 0;
+        }
+        while state.pending.front().is_some_and(|&oldest| oldest <= seq) {
+            state.pending.pop_front();
+        }
+        state.last_acked = state.last_acked.max(Some(seq));
+        let unacked = state.forgotten + state.pending.len() as u64;
+        if unacked <= self.warn_after {
+            state.warned = false;
+        }
+    }
+
+    /// The highest acknowledged `_seq`, `None` until the client sends its first ack.
+    pub fn last_acked(&self) -> Option<u64> {
+        self.state.lock().unwrap().last_acked
+    }
+
+    pub fn unacked(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.forgotten + state.pending.len() as u64
+    }
+}
@@ -0,0 +1,125 @@
+use serde::de::IgnoredAny;
+use tokio::time::{Duration, Instant};
+
+/// A batch is sent early once its JSON grows past this many bytes.
+pub const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Messages of one /ws connection collected for `?batch_ms=` and sent as one frame: a JSON
+/// array of the messages, or `{"messages":[...]}` holding envelopes.
+pub struct Batch {
+    window: Duration,
+    envelope: bool,
+    // The array's elements so far, comma-separated
+    items: String,
+    // When the batch must go out; set by its first message
+    deadline: Option<Instant>,
+}
+
+impl Batch {
+    pub fn new(window: Duration, envelope: bool) -> Self {
+        Self {
+            window,
+            envelope,
+            items: String::new(),
+            deadline: None,
+        }
+    }
+
+    /// Adds a message, in arrival order, and returns the batch if that made it full. Text
+    /// that is not JSON (e.g. the placeholder for an upstream binary frame) is added as a string.
+    pub fn push(&mut self, text: &str) -> Option<String> {
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + self.window);
+        } else {
+            self.items.push(',');
+        }
+        if serde_json::from_str::<IgnoredAny>(text).is_ok() {
+            self.items.push_str(text);
+        } else {
+            self.items.push_str(&serde_json::Value::from(text).to_string());
+        }
+        (self.items.len() >= MAX_BATCH_BYTES).then(|| self.take()).flatten()
+    }
+
+    /// When the pending batch is due; `None` while empty.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Takes the pending batch as a frame's text, leaving the batch empty.
+    pub fn take(&mut self) -> Option<String> {
+        self.deadline.take()?;
+        let items = std::mem::take(&mut self.items);
+        Some(match self.envelope {
+            true => format!("{{\"messages\":[{}]}}", items),
+            false => format!("[{}]", items),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_arrival_order() {
+        let mut batch = Batch::new(Duration::from_millis(100), false);
+        for text in [r#"{"n":1}"#, r#"{"n":2}"#, r#"{"n":3}"#] {
+            assert_eq!(batch.push(text), None);
+        }
+        assert_eq!(batch.take().as_deref(), Some(r#"[{"n":1},{"n":2},{"n":3}]"#));
+    }
+
+    #[test]
+    fn wraps_envelopes_and_quotes_text() {
+        let mut batch = Batch::new(Duration::from_millis(100), true);
+        batch.push(r#"{"id":1}"#);
+        batch.push("binary frame (12 bytes)");
+        assert_eq!(
+            batch.take().as_deref(),
+            Some(r#"{"messages":[{"id":1},"binary frame (12 bytes)"]}"#)
+        );
+    }
+
+    #[test]
+    fn is_due_a_window_after_its_first_message() {
+        let mut batch = Batch::new(Duration::from_millis(100), false);
+        assert_eq!(batch.deadline(), None);
+        let before = Instant::now();
+        batch.push("1");
+        let due = batch.deadline().expect("due once a message is in");
+        assert!(due >= before + Duration::from_millis(100) && due <= Instant::now() + Duration::from_millis(100));
+        // Later messages do not push the deadline back
+        batch.push("2");
+        assert_eq!(batch.deadline(), Some(due));
+    }
+
+    #[test]
+    fn take_empties_the_batch() {
+        let mut batch = Batch::new(Duration::from_millis(100), false);
+        assert_eq!(batch.take(), None);
+        batch.push("1");
+        assert_eq!(batch.take().as_deref(), Some("[1]"));
+        assert_eq!(batch.take(), None);
+        assert_eq!(batch.deadline(), None);
+        batch.push("2");
+        assert_eq!(batch.take().as_deref(), Some("[2]"));
+    }
+
+    #[test]
+    fn flushes_early_once_full() {
+        let mut batch = Batch::new(Duration::from_secs(60), false);
+        let message = format!(r#"{{"pad":"{}"}}"#, "x".repeat(1000));
+        let mut pushed = 0;
+        let full = loop {
+            pushed += 1;
+            if let Some(full) = batch.push(&message) {
+                break full;
+            }
+        };
+        assert!(full.len() > MAX_BATCH_BYTES);
+        let items: Vec<serde_json::Value> = serde_json::from_str(&full).expect("a JSON array");
+        assert_eq!(items.len(), pushed);
+        assert_eq!(batch.deadline(), None, "the next message starts a new batch");
+    }
+}
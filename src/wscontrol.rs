@@ -0,0 +1,55 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// A control message a /ws client sends as a JSON text frame to change its own subscription
+/// without reconnecting.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Control {
+    /// Replaces the `?ua=`/`include_unknown` filter; an empty `ua` forwards everything
+    SetFilter {
+        ua: Vec<String>,
+        #[serde(default)]
+        include_unknown: bool,
+    },
+    /// Replaces `?max_hz=`; `null` turns throttling off
+    SetRate { max_hz: Option<f64> },
+    /// Stops forwarding live messages until `resume`
+    Pause,
+    Resume,
+}
+
+/// A JSON text frame from a /ws client.
+pub enum Incoming {
+    /// `{"ack":<seq>}`: every message up to this `_seq` was received
+    Ack(u64),
+    Control(Control),
+}
+
+impl Incoming {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|err| format!("invalid control message: {}", err))?;
+        if value.get("op").is_none()
+            && let Some(ack) = value.get("ack")
+        {
+            return ack
+                .as_u64()
+                .map(Incoming::Ack)
+                .ok_or_else(|| "`ack` must be a `_seq`, a non-negative integer".to_string());
+        }
+        Control::deserialize(value)
+            .map(Incoming::Control)
+            .map_err(|err| format!("invalid control message: {}", err))
+    }
+}
+
+/// The reply to a control message that took effect, echoing the resulting settings.
+pub fn ack(applied: Value) -> String {
+    json!({ "op": "ack", "applied": applied }).to_string()
+}
+
+/// The reply to a control message that was rejected; the connection stays open.
+pub fn error(message: &str) -> String {
+    json!({ "op": "error", "error": message }).to_string()
+}
@@ -0,0 +1,421 @@
+use serde_json::Value;
+
+use crate::message_format::MessageFormat;
+
+/// Longest `?filter=` accepted, in bytes.
+pub const MAX_FILTER_LEN: usize = 1024;
+/// Most operators, fields and literals a filter may have, which bounds the work per message.
+const MAX_NODES: usize = 100;
+/// Deepest nesting of parentheses and `not`s.
+const MAX_DEPTH: usize = 32;
+
+/// A `?filter=` expression of a /ws connection, compiled once and evaluated against every
+/// message before it is forwarded, e.g. `abs(x) > 0.5 or type == 'event'`.
+///
+/// Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) take dotted field paths (`acceleration.x`,
+/// `values.0`), number, string (`'...'` or `"..."`), `true`/`false`/`null` literals, `-`,
+/// `abs(...)` and `format()`, the message's format (`json`, `form_encoded`, `csv`, `raw`);
+/// they combine with `and`/`&&`, `or`/`||`, `not`/`!` and parentheses. A bare operand is a
+/// condition when it is a boolean.
+///
+/// A comparison involving a missing field, or values of different types, is unknown rather than
+/// false: `not` keeps it unknown, `or` is true if either side is, `and` false if either side is.
+/// Messages for which the whole filter is unknown are not forwarded. Non-JSON messages have no
+/// fields, so they only pass a filter that lets them through explicitly, as with
+/// `format() != 'json' or x > 1`.
+pub struct Filter {
+    root: Expr,
+}
+
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(CompareOp, Operand, Operand),
+    Condition(Operand),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+enum Operand {
+    Literal(Value),
+    Field(Vec<String>),
+    Neg(Box<Operand>),
+    Abs(Box<Operand>),
+    Format,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        if source.len() > MAX_FILTER_LEN {
+            return Err(format!("longer than {} bytes", MAX_FILTER_LEN));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            nodes: 0,
+            depth: 0,
+        };
+        let root = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Self { root }),
+            Some((offset, token)) => Err(format!("unexpected {} at offset {}", token.describe(), offset)),
+        }
+    }
+
+    /// Whether the message should be forwarded. The text is only parsed for JSON messages.
+    pub fn matches(&self, text: &str, format: MessageFormat) -> bool {
+        let message = match format {
+            MessageFormat::Json => serde_json::from_str(text).ok(),
+            _ => None,
+        };
+        let message = Message { value: message.as_ref(), format };
+        message.eval(&self.root) == Some(true)
+    }
+}
+
+struct Message<'a> {
+    // `None` for non-JSON messages, which have no fields
+    value: Option<&'a Value>,
+    format: MessageFormat,
+}
+
+impl Message<'_> {
+    // `None` is unknown
+    fn eval(&self, expr: &Expr) -> Option<bool> {
+        match expr {
+            Expr::Or(left, right) => match self.eval(left) {
+                Some(true) => Some(true),
+                left => match (left, self.eval(right)) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                },
+            },
+            Expr::And(left, right) => match self.eval(left) {
+                Some(false) => Some(false),
+                left => match (left, self.eval(right)) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                },
+            },
+            Expr::Not(inner) => self.eval(inner).map(|b| !b),
+            Expr::Compare(op, left, right) => compare(*op, &self.operand(left)?, &self.operand(right)?),
+            Expr::Condition(operand) => self.operand(operand)?.as_bool(),
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> Option<Value> {
+        match operand {
+            Operand::Literal(value) => Some(value.clone()),
+            Operand::Field(path) => path
+                .iter()
+                .try_fold(self.value?, |value, key| match value {
+                    Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+                    _ => value.get(key),
+                })
+                .cloned(),
+            Operand::Neg(inner) => Some(Value::from(-self.operand(inner)?.as_f64()?)),
+            Operand::Abs(inner) => Some(Value::from(self.operand(inner)?.as_f64()?.abs())),
+            Operand::Format => Some(Value::from(self.format.as_str())),
+        }
+    }
+}
+
+fn compare(op: CompareOp, left: &Value, right: &Value) -> Option<bool> {
+    let ordering = match (left, right) {
+        (Value::Number(_), Value::Number(_)) => left.as_f64()?.partial_cmp(&right.as_f64()?)?,
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        _ => {
+            // Other values only have equality, and only with their own type
+            let same_type = std::mem::discriminant(left) == std::mem::discriminant(right);
+            return match op {
+                CompareOp::Eq if same_type => Some(left == right),
+                CompareOp::Ne if same_type => Some(left != right),
+                _ => None,
+            };
+        }
+    };
+    Some(match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+        CompareOp::Gt => ordering.is_gt(),
+        CompareOp::Ge => ordering.is_ge(),
+    })
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Compare(CompareOp),
+    And,
+    Or,
+    Not,
+    Minus,
+    Dot,
+    Comma,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("`{}`", name),
+            Token::Number(n) => format!("number {}", n),
+            Token::Str(_) => "string".to_string(),
+            Token::Compare(_) => "comparison".to_string(),
+            Token::And => "`and`".to_string(),
+            Token::Or => "`or`".to_string(),
+            Token::Not => "`not`".to_string(),
+            Token::Minus => "`-`".to_string(),
+            Token::Dot => "`.`".to_string(),
+            Token::Comma => "`,`".to_string(),
+            Token::Open => "`(`".to_string(),
+            Token::Close => "`)`".to_string(),
+        }
+    }
+}
+
+/// Splits the filter into tokens, each with its byte offset.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                name.push(c);
+                chars.next();
+            }
+            match name.as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Ident(name),
+            }
+        } else if c.is_ascii_digit() {
+            // A path segment such as `values.0`, or a number
+            let segment = tokens.last().is_some_and(|(_, token)| *token == Token::Dot);
+            let mut number = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit() || (*c == '.' && !segment)) {
+                number.push(c);
+                chars.next();
+            }
+            if segment {
+                Token::Ident(number)
+            } else {
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid number `{}` at offset {}", number, start))?,
+                )
+            }
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err(format!("unterminated string at offset {}", start)),
+                    Some((_, q)) if q == c => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => text.push(escaped),
+                        None => return Err(format!("unterminated string at offset {}", start)),
+                    },
+                    Some((_, other)) => text.push(other),
+                }
+            }
+            Token::Str(text)
+        } else {
+            chars.next();
+            let next = chars.peek().map(|&(_, c)| c);
+            let (token, pair) = match (c, next) {
+                ('=', Some('=')) => (Token::Compare(CompareOp::Eq), true),
+                ('!', Some('=')) => (Token::Compare(CompareOp::Ne), true),
+                ('<', Some('=')) => (Token::Compare(CompareOp::Le), true),
+                ('>', Some('=')) => (Token::Compare(CompareOp::Ge), true),
+                ('&', Some('&')) => (Token::And, true),
+                ('|', Some('|')) => (Token::Or, true),
+                ('<', _) => (Token::Compare(CompareOp::Lt), false),
+                ('>', _) => (Token::Compare(CompareOp::Gt), false),
+                ('!', _) => (Token::Not, false),
+                ('-', _) => (Token::Minus, false),
+                ('.', _) => (Token::Dot, false),
+                (',', _) => (Token::Comma, false),
+                ('(', _) => (Token::Open, false),
+                (')', _) => (Token::Close, false),
+                _ => return Err(format!("unexpected `{}` at offset {}", c, start)),
+            };
+            if pair {
+                chars.next();
+            }
+            token
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent, loosest binding first: `or`, `and`, `not`, comparison, operand.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    nodes: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        if self.eat(&token) {
+            return Ok(());
+        }
+        Err(format!("expected {} {}", token.describe(), self.here()))
+    }
+
+    fn here(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some((offset, token)) => format!("at offset {}, found {}", offset, token.describe()),
+            None => "at the end".to_string(),
+        }
+    }
+
+    fn node(&mut self) -> Result<(), String> {
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return Err(format!("more than {} operators and operands", MAX_NODES));
+        }
+        Ok(())
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("nested more than {} levels deep", MAX_DEPTH));
+        }
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            self.node()?;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.eat(&Token::And) {
+            self.node()?;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Not) {
+            self.node()?;
+            return self.nested(|parser| Ok(Expr::Not(Box::new(parser.not()?))));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let left = match self.eat(&Token::Open) {
+            // A parenthesized condition, or an operand like `(x) > 1`
+            true => {
+                let expr = self.nested(Self::or)?;
+                self.expect(Token::Close)?;
+                match (expr, self.peek()) {
+                    (Expr::Condition(operand), Some(Token::Compare(_))) => operand,
+                    (_, Some(Token::Compare(_))) => return Err(format!("cannot compare a condition {}", self.here())),
+                    (expr, _) => return Ok(expr),
+                }
+            }
+            false => self.operand()?,
+        };
+        let op = match self.peek() {
+            Some(&Token::Compare(op)) => op,
+            _ => return Ok(Expr::Condition(left)),
+        };
+        self.pos += 1;
+        self.node()?;
+        Ok(Expr::Compare(op, left, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        self.node()?;
+        let Some((offset, token)) = self.tokens.get(self.pos).cloned() else {
+            return Err("expected a value at the end".to_string());
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Operand::Literal(Value::from(n))),
+            Token::Str(text) => Ok(Operand::Literal(Value::from(text))),
+            Token::Minus => Ok(Operand::Neg(Box::new(self.nested(Self::operand)?))),
+            Token::Open => {
+                let inner = self.nested(Self::operand)?;
+                self.expect(Token::Close)?;
+                Ok(inner)
+            }
+            Token::Ident(name) if self.eat(&Token::Open) => {
+                let operand = match name.as_str() {
+                    "abs" => Operand::Abs(Box::new(self.nested(Self::operand)?)),
+                    "format" => Operand::Format,
+                    _ => return Err(format!("unknown function `{}` at offset {}", name, offset)),
+                };
+                self.expect(Token::Close)?;
+                Ok(operand)
+            }
+            Token::Ident(name) => Ok(match name.as_str() {
+                "true" => Operand::Literal(Value::Bool(true)),
+                "false" => Operand::Literal(Value::Bool(false)),
+                "null" => Operand::Literal(Value::Null),
+                _ => {
+                    let mut path = vec![name];
+                    while self.eat(&Token::Dot) {
+                        match self.tokens.get(self.pos) {
+                            Some((_, Token::Ident(key))) => path.push(key.clone()),
+                            _ => return Err(format!("expected a field name {}", self.here())),
+                        }
+                        self.pos += 1;
+                    }
+                    Operand::Field(path)
+                }
+            }),
+            other => Err(format!("expected a value at offset {}, found {}", offset, other.describe())),
+        }
+    }
+}
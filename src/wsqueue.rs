@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use axum::extract::ws::Message;
+use tokio::sync::Notify;
+
+/// Bounded outgoing queue of one /ws connection, drained by its writer task.
+///
+/// Keeps a slow client's `send().await` from holding up its reader, so the broadcast is always
+/// consumed at full speed and only this client's own queue overflows.
+pub struct SendQueue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    // One consumer, so notify_one's stored permit cannot be lost
+    ready: Notify,
+    dropped: AtomicU64,
+    // Text and binary frames handed to the socket, and their payload bytes
+    sent: AtomicU64,
+    sent_bytes: AtomicU64,
+}
+
+struct QueueState {
+    // Each with whether it counts as sent data once popped
+    messages: VecDeque<(Message, bool)>,
+    closed: bool,
+}
+
+/// The queue was full and `push` was told not to evict.
+pub struct Full;
+
+impl SendQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            capacity,
+            ready: Notify::new(),
+            dropped: AtomicU64::new(0),
+            sent: AtomicU64::new(0),
+            sent_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `message`; when full, evicts the oldest one (counted in `dropped`) if
+    /// `drop_oldest`, and otherwise refuses it.
+    pub fn push(&self, message: Message, drop_oldest: bool) -> Result<(), Full> {
+        self.enqueue(message, true, drop_oldest)
+    }
+
+    /// `push` for frames that are not data, such as heartbeats, so they stay out of `sent`.
+    pub fn push_uncounted(&self, message: Message, drop_oldest: bool) -> Result<(), Full> {
+        self.enqueue(message, false, drop_oldest)
+    }
+
+    fn enqueue(&self, message: Message, counted: bool, drop_oldest: bool) -> Result<(), Full> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Ok(());
+        }
+        if state.messages.len() >= self.capacity {
+            if !drop_oldest {
+                return Err(Full);
+            }
+            state.messages.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        state.messages.push_back((message, counted));
+        drop(state);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// Discards anything pending and makes `last` (e.g. a close frame) the final message.
+    /// Does nothing if already closed, so the first close frame wins.
+    pub fn close_with(&self, last: Option<Message>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+        state.messages.clear();
+        state.messages.extend(last.map(|message| (message, false)));
+        state.closed = true;
+        drop(state);
+        self.ready.notify_one();
+    }
+
+    /// Next message to send, counted as sent unless pushed uncounted; `None` once closed and
+    /// drained.
+    pub async fn pop(&self) -> Option<Message> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some((message, counted)) = state.messages.pop_front() {
+                    if counted {
+                        self.record_sent(&message);
+                    }
+                    return Some(message);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    /// Messages evicted because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Counts a frame handed to the socket; pings and close frames are left out.
+    pub fn record_sent(&self, message: &Message) {
+        let bytes = match message {
+            Message::Text(text) => text.len(),
+            Message::Binary(data) => data.len(),
+            _ => return,
+        };
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::Relaxed)
+    }
+}